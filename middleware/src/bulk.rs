@@ -0,0 +1,270 @@
+//! Changes made to many events at once, such as marking a whole sprint done.
+//! The events are picked by id or by an EventQuery, and an EventPatch describes the change relative
+//! to each event, so it can be applied again to an event someone else updated in the meantime.
+
+use crate::history::has_changes;
+use crate::recurrence::complete_occurrence;
+use crate::tags::parse_tags;
+use crate::timezone::local_to_utc;
+use crate::{EventEntry, EventQuery, EventStore, MiddlewareError, Tz};
+use chrono::Duration;
+use mongodb::bson::oid::ObjectId;
+
+/// EventSelection tells which events a bulk operation changes; events in the trash are never changed
+#[derive(Debug, Clone, PartialEq)]
+pub enum EventSelection {
+    /// The events with these ids; ids which are no longer stored are left out
+    Ids(Vec<ObjectId>),
+    /// The events matched by the query
+    Query(EventQuery),
+}
+
+/// EventPatch is built with chained calls, e.g. `EventPatch::new().done(true).add_tag("sprint-3")`.
+/// Fields left unset keep the value each event has.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EventPatch {
+    /// Marks the events done (true) or not done (false); recurring events marked done move on
+    /// to their next occurrence instead
+    pub is_done: Option<bool>,
+    /// Tags added to the events which don't carry them yet, normalized by parse_tags
+    pub add_tags: Vec<String>,
+    /// Tags removed from the events, normalized by parse_tags so case doesn't matter
+    pub remove_tags: Vec<String>,
+    /// Moves the events by this many days, keeping their time of day in the zone of the store
+    pub shift_days: Option<i64>,
+    /// Moves the events to this project, or to the Inbox for Some(None)
+    pub project: Option<Option<ObjectId>>,
+}
+
+impl EventPatch {
+    /// A patch which changes nothing
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Marks the events done (true) or not done (false)
+    pub fn done(mut self, is_done: bool) -> Self {
+        self.is_done = Some(is_done);
+        self
+    }
+
+    /// Adds the tag; can be called several times to add several tags
+    pub fn add_tag(mut self, tag: &str) -> Self {
+        self.add_tags.push(tag.to_string());
+        self
+    }
+
+    /// Removes the tag; can be called several times to remove several tags
+    pub fn remove_tag(mut self, tag: &str) -> Self {
+        self.remove_tags.push(tag.to_string());
+        self
+    }
+
+    /// Moves the date of the events by a number of days, back in time when negative
+    pub fn shift_days(mut self, days: i64) -> Self {
+        self.shift_days = Some(days);
+        self
+    }
+
+    /// Moves the events to the project, or to the Inbox when None
+    pub fn move_to_project(mut self, project_id: Option<ObjectId>) -> Self {
+        self.project = Some(project_id);
+        self
+    }
+
+    /// The event with the patch applied; days are counted in the given zone, so an event keeps
+    /// its time of day when the clocks change in between
+    pub fn apply(&self, entry: &EventEntry, tz: &Tz) -> EventEntry {
+        let mut patched = entry.clone();
+        if let (Some(days), Some(date_time)) = (self.shift_days, patched.date_time) {
+            let local = date_time.with_timezone(tz).naive_local();
+            patched.date_time = Some(local_to_utc(local + Duration::days(days), tz));
+        }
+        let removed = parse_tags(&self.remove_tags.join(","));
+        patched.tags.retain(|tag| !removed.contains(tag));
+        for tag in parse_tags(&self.add_tags.join(",")) {
+            if !patched.tags.contains(&tag) {
+                patched.tags.push(tag);
+            }
+        }
+        if let Some(project_id) = self.project {
+            patched.project_id = project_id;
+        }
+        // Done last, so the occurrence a recurring event moves on to follows the shifted date
+        match self.is_done {
+            Some(true) if !patched.is_done => complete_occurrence(&patched, tz),
+            Some(is_done) => EventEntry { is_done, ..patched },
+            None => patched,
+        }
+    }
+}
+
+/// Loads the events of the selection which are stored and not in the trash
+pub(crate) async fn select<S: EventStore + ?Sized>(
+    store: &S,
+    selection: &EventSelection,
+) -> Result<Vec<EventEntry>, MiddlewareError> {
+    match selection {
+        EventSelection::Query(query) => store.query_events(query).await,
+        EventSelection::Ids(ids) => {
+            let mut entries = Vec::with_capacity(ids.len());
+            for id in ids {
+                match store.get_event(id).await {
+                    Ok(entry) if entry.deleted_at.is_none() => entries.push(entry),
+                    Ok(_) | Err(MiddlewareError::NotFound { .. }) => {}
+                    Err(error) => return Err(error),
+                }
+            }
+            Ok(entries)
+        }
+    }
+}
+
+/// The ids of the events in the selection, for stores which load and change them in one go; ids
+/// which are gone or in the trash are left for the store to skip
+pub(crate) async fn selected_ids<S: EventStore + ?Sized>(
+    store: &S,
+    selection: &EventSelection,
+) -> Result<Vec<ObjectId>, MiddlewareError> {
+    match selection {
+        EventSelection::Query(query) => Ok(store
+            .query_events(query)
+            .await?
+            .iter()
+            .map(|x| x.unique_id)
+            .collect()),
+        EventSelection::Ids(ids) => Ok(ids.clone()),
+    }
+}
+
+/// Applies the patch to one event through update_task; when someone else updated the event first,
+/// the patch is applied again to what they stored. Returns None when the patch changes nothing
+/// or the event is gone.
+pub(crate) async fn patch_event<S: EventStore + ?Sized>(
+    store: &S,
+    entry: EventEntry,
+    patch: &EventPatch,
+) -> Result<Option<(EventEntry, EventEntry)>, MiddlewareError> {
+    let tz = store.timezone();
+    let mut before = entry;
+    loop {
+        let after = patch.apply(&before, &tz);
+        if !has_changes(&before, &after) {
+            return Ok(None);
+        }
+        match store.update_task(&after).await {
+            Ok(stored) => return Ok(Some((before, stored))),
+            Err(MiddlewareError::Conflict { id }) => before = store.get_event(&id).await?,
+            Err(MiddlewareError::NotFound { .. }) => return Ok(None),
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+/// Applies the patch to the selected events one update_task at a time; the default of
+/// EventStore::update_many
+pub(crate) async fn update_each<S: EventStore + ?Sized>(
+    store: &S,
+    selection: &EventSelection,
+    patch: &EventPatch,
+) -> Result<Vec<(EventEntry, EventEntry)>, MiddlewareError> {
+    let mut changes = Vec::new();
+    for entry in select(store, selection).await? {
+        if let Some(change) = patch_event(store, entry, patch).await? {
+            changes.push(change);
+        }
+    }
+    Ok(changes)
+}
+
+/// Moves the selected events to the trash one delete_event at a time; the default of
+/// EventStore::delete_many
+pub(crate) async fn delete_each<S: EventStore + ?Sized>(
+    store: &S,
+    selection: &EventSelection,
+) -> Result<Vec<EventEntry>, MiddlewareError> {
+    let mut deleted = Vec::new();
+    for entry in select(store, selection).await? {
+        match store.delete_event(&entry).await {
+            Ok(()) => deleted.push(entry),
+            // Deleted elsewhere in the meantime
+            Err(MiddlewareError::NotFound { .. }) => {}
+            Err(error) => return Err(error),
+        }
+    }
+    Ok(deleted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Frequency, Recurrence};
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn test_apply() {
+        let tz: Tz = "America/Los_Angeles".parse().unwrap();
+        // 9 AM in Portland, the day after the clocks went back
+        let entry = EventEntry::new(
+            ObjectId::new(),
+            String::from("Sprint review"),
+            String::from("Details"),
+            Utc.with_ymd_and_hms(2023, 11, 6, 17, 0, 0).unwrap(),
+            false,
+            String::from("Work sprint"),
+        );
+
+        let patched = EventPatch::new()
+            .done(true)
+            .shift_days(-2)
+            .remove_tag("SPRINT")
+            .add_tag("Done")
+            .add_tag("work")
+            .apply(&entry, &tz);
+        assert!(patched.is_done);
+        // Still 9 AM, which was an hour later in UTC before the change
+        assert_eq!(
+            patched.date_time,
+            Some(Utc.with_ymd_and_hms(2023, 11, 4, 16, 0, 0).unwrap())
+        );
+        assert_eq!(patched.tags, vec!["work", "done"]);
+        let untagged = EventPatch::new()
+            .remove_tag("work")
+            .remove_tag("sprint")
+            .apply(&entry, &tz);
+        assert!(untagged.tags.is_empty());
+
+        // A recurring event marked done moves on to its next occurrence after the shift
+        let weekly = EventEntry {
+            recurrence: Some(Recurrence::new(Frequency::Weekly)),
+            ..entry.clone()
+        };
+        let advanced = EventPatch::new()
+            .shift_days(1)
+            .done(true)
+            .apply(&weekly, &tz);
+        assert!(!advanced.is_done);
+        assert_eq!(
+            advanced.date_time,
+            Some(Utc.with_ymd_and_hms(2023, 11, 14, 17, 0, 0).unwrap())
+        );
+
+        let project_id = ObjectId::new();
+        let moved = EventPatch::new()
+            .move_to_project(Some(project_id))
+            .apply(&entry, &tz);
+        assert_eq!(moved.project_id, Some(project_id));
+        let inbox = EventPatch::new().move_to_project(None).apply(&moved, &tz);
+        assert_eq!(inbox.project_id, None);
+
+        // Tags the events already carry, or don't, leave them unchanged
+        assert_eq!(EventPatch::new().apply(&entry, &tz), entry);
+        assert_eq!(
+            EventPatch::new()
+                .remove_tag("home")
+                .add_tag("Work")
+                .apply(&entry, &tz),
+            entry
+        );
+    }
+}
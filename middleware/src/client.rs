@@ -0,0 +1,80 @@
+//! KrabbyClient is the long-lived handle to the middleware.
+//! It owns one tokio runtime and one store (and with it one MongoDB connection pool), so the UI,
+//! the notifier and the tests don't have to build a new runtime or client for every operation.
+
+use crate::{
+    open_store, poll_changes, EventChangeStream, EventStore, MiddlewareConfig, MiddlewareError, Tz,
+};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::runtime::Runtime;
+
+/// KrabbyClient is cheap to clone; clones share the runtime and the store
+#[derive(Clone)]
+pub struct KrabbyClient {
+    runtime: Arc<Runtime>,
+    store: Arc<dyn EventStore>,
+}
+
+impl KrabbyClient {
+    /// Opens the store named by the config; the store is created on the runtime of the client
+    /// because the MongoDB driver runs its connection pool on the runtime it was created on
+    pub fn connect(config: &MiddlewareConfig) -> Result<Self, MiddlewareError> {
+        let runtime = Runtime::new()?;
+        let store = runtime.block_on(open_store(config))?;
+        Ok(KrabbyClient {
+            runtime: Arc::new(runtime),
+            store,
+        })
+    }
+
+    /// Loads the config from the config file and the environment, then connects to the store it names
+    pub fn connect_default() -> Result<Self, MiddlewareError> {
+        Self::connect(&MiddlewareConfig::load()?)
+    }
+
+    /// Wraps a store which has already been opened, e.g. a MemoryStore in tests
+    pub fn with_store(store: Arc<dyn EventStore>) -> Result<Self, MiddlewareError> {
+        Ok(KrabbyClient {
+            runtime: Arc::new(Runtime::new()?),
+            store,
+        })
+    }
+
+    /// The store all operations go through
+    pub fn store(&self) -> &dyn EventStore {
+        self.store.as_ref()
+    }
+
+    /// Time zone of the user, in which dates are picked and shown
+    pub fn timezone(&self) -> Tz {
+        self.store.timezone()
+    }
+
+    /// Subscribes to the changes made to the events by every instance using the store; stores
+    /// which can't push changes are polled every poll_interval instead
+    pub async fn subscribe(
+        &self,
+        poll_interval: Duration,
+    ) -> Result<EventChangeStream, MiddlewareError> {
+        match self.store.watch().await? {
+            Some(changes) => Ok(changes),
+            None => poll_changes(self.store.clone(), poll_interval).await,
+        }
+    }
+
+    /// Runs a future in the background on the runtime of the client, e.g. a loop reading a subscription
+    pub fn spawn<F>(&self, future: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        self.runtime.spawn(future);
+    }
+
+    /// Runs a future on the runtime of the client and waits for its result;
+    /// meant for synchronous callers such as the UI
+    pub fn block_on<F: Future>(&self, future: F) -> F::Output {
+        self.runtime.block_on(future)
+    }
+}
@@ -0,0 +1,386 @@
+//! Configuration of the middleware.
+//! Values are read from `$XDG_CONFIG_HOME/krabbydo/config.toml` (or the file named by `KRABBYDO_CONFIG`)
+//! and can then be overridden by `KRABBYDO_*` environment variables.
+//!
+//! ```toml
+//! backend = "mongodb"
+//! actor = "krabby"
+//! timezone = "America/Los_Angeles"
+//!
+//! [mongodb]
+//! uri = "mongodb://db1.example.com:27017,db2.example.com:27017/?replicaSet=rs0"
+//! database = "events"
+//! collection = "todos"
+//! metadata_collection = "metadata"
+//! revisions_collection = "revisions"
+//! projects_collection = "projects"
+//! username = "krabby"
+//! password = "secret"
+//! tls = true
+//! connect_timeout_ms = 5000
+//! server_selection_timeout_ms = 10000
+//!
+//! trash_retention_days = 30
+//!
+//! [sqlite]
+//! path = "/home/krabby/.local/share/krabbydo/krabbydo.sqlite3"
+//!
+//! [urgency]
+//! priority_high = 6.0
+//! due = 12.0
+//! age_max_days = 365
+//!
+//! [urgency.tag_coefficients]
+//! urgent = 5.0
+//! ```
+
+use crate::history::default_actor;
+use crate::timezone::{local_timezone, parse_timezone, Tz};
+use crate::{MiddlewareError, UrgencyConfig};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Environment variable naming a config file to use instead of the one in the XDG config dir
+pub const CONFIG_PATH_VAR: &str = "KRABBYDO_CONFIG";
+
+/// Storage backends which the middleware can be configured to use
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    MongoDb,
+    Sqlite,
+}
+
+impl Default for Backend {
+    /// SQLite when the "sqlite" feature is enabled, MongoDB otherwise
+    fn default() -> Self {
+        if cfg!(feature = "sqlite") {
+            Backend::Sqlite
+        } else {
+            Backend::MongoDb
+        }
+    }
+}
+
+impl std::str::FromStr for Backend {
+    type Err = MiddlewareError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "mongodb" | "mongo" => Ok(Backend::MongoDb),
+            "sqlite" => Ok(Backend::Sqlite),
+            _ => Err(MiddlewareError::Config(format!(
+                "Unknown backend \"{}\"",
+                s
+            ))),
+        }
+    }
+}
+
+/// Settings used to connect to MongoDB
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct MongoConfig {
+    /// Connection string of the server or replica set
+    pub uri: String,
+    /// Name of the database holding the events
+    pub database: String,
+    /// Name of the collection holding the events
+    pub collection: String,
+    /// Name of the collection recording the schema version of the events
+    pub metadata_collection: String,
+    /// Name of the collection holding the revision history of the events
+    pub revisions_collection: String,
+    /// Name of the collection holding the projects the events are grouped in
+    pub projects_collection: String,
+    /// Username used to authenticate, if any
+    pub username: Option<String>,
+    /// Password used to authenticate, if any
+    pub password: Option<String>,
+    /// Database the user is defined in, MongoDB uses "admin" when not set
+    pub auth_source: Option<String>,
+    /// Connect over TLS
+    pub tls: bool,
+    /// Certificate authority file used to validate the server certificate
+    pub tls_ca_file: Option<PathBuf>,
+    /// Accept server certificates which can't be validated; only meant for testing setups
+    pub tls_allow_invalid_certificates: bool,
+    /// Time allowed to open a connection, in milliseconds
+    pub connect_timeout_ms: Option<u64>,
+    /// Time allowed to find a suitable server for an operation, in milliseconds
+    pub server_selection_timeout_ms: Option<u64>,
+}
+
+impl Default for MongoConfig {
+    fn default() -> Self {
+        MongoConfig {
+            uri: String::from("mongodb://localhost:27017"),
+            database: String::from("events"),
+            collection: String::from("todos"),
+            metadata_collection: String::from("metadata"),
+            revisions_collection: String::from("revisions"),
+            projects_collection: String::from("projects"),
+            username: None,
+            password: None,
+            auth_source: None,
+            tls: false,
+            tls_ca_file: None,
+            tls_allow_invalid_certificates: false,
+            connect_timeout_ms: None,
+            server_selection_timeout_ms: None,
+        }
+    }
+}
+
+/// Settings of the SQLite backend
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(default)]
+pub struct SqliteConfig {
+    /// Location of the database file; defaults to `$XDG_DATA_HOME/krabbydo/krabbydo.sqlite3`
+    pub path: Option<PathBuf>,
+}
+
+impl SqliteConfig {
+    /// Location of the database file, falling back to the data dir of the user
+    pub fn database_path(&self) -> PathBuf {
+        self.path.clone().unwrap_or_else(|| {
+            dirs::data_dir()
+                .unwrap_or_else(|| PathBuf::from("."))
+                .join("krabbydo")
+                .join("krabbydo.sqlite3")
+        })
+    }
+}
+
+/// MiddlewareConfig holds every setting of the middleware
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct MiddlewareConfig {
+    /// Storage backend to use
+    pub backend: Backend,
+    /// Days a deleted event stays in the trash before it is purged
+    pub trash_retention_days: u32,
+    /// Name recorded as the author of changes; defaults to the login name of the user
+    pub actor: Option<String>,
+    /// IANA name of the time zone of the user; defaults to the zone of the system
+    pub timezone: Option<String>,
+    /// Settings of the MongoDB backend
+    pub mongodb: MongoConfig,
+    /// Settings of the SQLite backend
+    pub sqlite: SqliteConfig,
+    /// Coefficients of the urgency score the events can be sorted by
+    pub urgency: UrgencyConfig,
+}
+
+impl Default for MiddlewareConfig {
+    fn default() -> Self {
+        MiddlewareConfig {
+            backend: Backend::default(),
+            trash_retention_days: 30,
+            actor: None,
+            timezone: None,
+            mongodb: MongoConfig::default(),
+            sqlite: SqliteConfig::default(),
+            urgency: UrgencyConfig::default(),
+        }
+    }
+}
+
+impl MiddlewareConfig {
+    /// Loads the config file, if there is one, and applies the environment variables on top of it
+    pub fn load() -> Result<Self, MiddlewareError> {
+        let path = match std::env::var_os(CONFIG_PATH_VAR) {
+            Some(path) => Some(PathBuf::from(path)),
+            None => Self::default_path(),
+        };
+        let mut config = match path {
+            Some(path) if path.exists() => Self::from_file(&path)?,
+            _ => Self::default(),
+        };
+        config.apply_env_vars(std::env::vars())?;
+        Ok(config)
+    }
+
+    /// How long a deleted event stays in the trash
+    pub fn trash_retention(&self) -> chrono::Duration {
+        chrono::Duration::days(self.trash_retention_days.into())
+    }
+
+    /// Name recorded as the author of changes
+    pub fn actor(&self) -> String {
+        self.actor.clone().unwrap_or_else(default_actor)
+    }
+
+    /// Time zone of the user; a name which isn't a known IANA zone is an error
+    pub fn timezone(&self) -> Result<Tz, MiddlewareError> {
+        match &self.timezone {
+            Some(name) => parse_timezone(name),
+            None => Ok(local_timezone()),
+        }
+    }
+
+    /// Location of the config file in the XDG config dir
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("krabbydo").join("config.toml"))
+    }
+
+    /// Reads the config from a TOML file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, MiddlewareError> {
+        let contents = std::fs::read_to_string(path)?;
+        Self::from_toml(&contents)
+    }
+
+    /// Reads the config from a TOML string; missing keys keep their default value
+    pub fn from_toml(contents: &str) -> Result<Self, MiddlewareError> {
+        Ok(toml::from_str(contents)?)
+    }
+
+    /// Overrides settings with the `KRABBYDO_*` variables found among the given ones
+    pub fn apply_env_vars<I>(&mut self, vars: I) -> Result<(), MiddlewareError>
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        for (key, value) in vars {
+            let mongodb = &mut self.mongodb;
+            match key.as_str() {
+                "KRABBYDO_BACKEND" => self.backend = value.parse()?,
+                "KRABBYDO_ACTOR" => self.actor = Some(value),
+                "KRABBYDO_TIMEZONE" => self.timezone = Some(value),
+                "KRABBYDO_TRASH_RETENTION_DAYS" => {
+                    self.trash_retention_days = value.parse().map_err(|_| {
+                        MiddlewareError::Config(format!(
+                            "{} must be a number of days, got \"{}\"",
+                            key, value
+                        ))
+                    })?
+                }
+                "KRABBYDO_MONGODB_URI" => mongodb.uri = value,
+                "KRABBYDO_MONGODB_DATABASE" => mongodb.database = value,
+                "KRABBYDO_MONGODB_COLLECTION" => mongodb.collection = value,
+                "KRABBYDO_MONGODB_METADATA_COLLECTION" => mongodb.metadata_collection = value,
+                "KRABBYDO_MONGODB_REVISIONS_COLLECTION" => mongodb.revisions_collection = value,
+                "KRABBYDO_MONGODB_PROJECTS_COLLECTION" => mongodb.projects_collection = value,
+                "KRABBYDO_MONGODB_USERNAME" => mongodb.username = Some(value),
+                "KRABBYDO_MONGODB_PASSWORD" => mongodb.password = Some(value),
+                "KRABBYDO_MONGODB_AUTH_SOURCE" => mongodb.auth_source = Some(value),
+                "KRABBYDO_MONGODB_TLS" => mongodb.tls = parse_bool(&key, &value)?,
+                "KRABBYDO_MONGODB_TLS_CA_FILE" => mongodb.tls_ca_file = Some(value.into()),
+                "KRABBYDO_MONGODB_TLS_ALLOW_INVALID_CERTIFICATES" => {
+                    mongodb.tls_allow_invalid_certificates = parse_bool(&key, &value)?
+                }
+                "KRABBYDO_MONGODB_CONNECT_TIMEOUT_MS" => {
+                    mongodb.connect_timeout_ms = Some(parse_millis(&key, &value)?)
+                }
+                "KRABBYDO_MONGODB_SERVER_SELECTION_TIMEOUT_MS" => {
+                    mongodb.server_selection_timeout_ms = Some(parse_millis(&key, &value)?)
+                }
+                "KRABBYDO_SQLITE_PATH" => self.sqlite.path = Some(value.into()),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Accepts the usual spellings of a boolean in environment variables
+fn parse_bool(key: &str, value: &str) -> Result<bool, MiddlewareError> {
+    match value.to_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Ok(true),
+        "0" | "false" | "no" | "off" => Ok(false),
+        _ => Err(MiddlewareError::Config(format!(
+            "{} must be true or false, got \"{}\"",
+            key, value
+        ))),
+    }
+}
+
+/// Parses a duration given in milliseconds
+fn parse_millis(key: &str, value: &str) -> Result<u64, MiddlewareError> {
+    value.parse().map_err(|_| {
+        MiddlewareError::Config(format!(
+            "{} must be a number of milliseconds, got \"{}\"",
+            key, value
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_toml() {
+        let config = MiddlewareConfig::from_toml(
+            r#"
+            backend = "mongodb"
+
+            [mongodb]
+            uri = "mongodb://db1:27017,db2:27017/?replicaSet=rs0"
+            database = "team"
+            username = "krabby"
+            password = "secret"
+            tls = true
+            server_selection_timeout_ms = 5000
+
+            [urgency]
+            priority_high = 7.5
+
+            [urgency.tag_coefficients]
+            urgent = 5.0
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.backend, Backend::MongoDb);
+        assert_eq!(
+            config.mongodb.uri,
+            "mongodb://db1:27017,db2:27017/?replicaSet=rs0"
+        );
+        assert_eq!(config.mongodb.database, "team");
+        // Keys missing from the file keep their default value
+        assert_eq!(config.mongodb.collection, "todos");
+        assert_eq!(config.mongodb.projects_collection, "projects");
+        assert_eq!(config.mongodb.username.as_deref(), Some("krabby"));
+        assert!(config.mongodb.tls);
+        assert_eq!(config.mongodb.connect_timeout_ms, None);
+        assert_eq!(config.mongodb.server_selection_timeout_ms, Some(5000));
+        assert_eq!(config.urgency.priority_high, 7.5);
+        assert_eq!(config.urgency.tag_coefficients.get("urgent"), Some(&5.0));
+        assert_eq!(config.urgency.due, UrgencyConfig::default().due);
+    }
+
+    #[test]
+    fn test_apply_env_vars() {
+        let mut config = MiddlewareConfig::from_toml("[mongodb]\ndatabase = \"team\"\n").unwrap();
+        let vars = [
+            ("KRABBYDO_BACKEND", "sqlite"),
+            ("KRABBYDO_TRASH_RETENTION_DAYS", "7"),
+            ("KRABBYDO_TIMEZONE", "America/Los_Angeles"),
+            ("KRABBYDO_MONGODB_DATABASE", "override"),
+            ("KRABBYDO_MONGODB_TLS", "yes"),
+            ("KRABBYDO_MONGODB_CONNECT_TIMEOUT_MS", "2500"),
+            ("KRABBYDO_SQLITE_PATH", "/tmp/krabbydo.sqlite3"),
+            ("HOME", "/home/krabby"),
+        ]
+        .map(|(key, value)| (key.to_string(), value.to_string()));
+        config.apply_env_vars(vars).unwrap();
+
+        assert_eq!(config.backend, Backend::Sqlite);
+        assert_eq!(config.trash_retention(), chrono::Duration::days(7));
+        assert_eq!(config.timezone().unwrap(), Tz::America__Los_Angeles);
+        assert_eq!(config.mongodb.database, "override");
+        assert!(config.mongodb.tls);
+        assert_eq!(config.mongodb.connect_timeout_ms, Some(2500));
+        assert_eq!(
+            config.sqlite.database_path(),
+            PathBuf::from("/tmp/krabbydo.sqlite3")
+        );
+
+        config.timezone = Some(String::from("Portland"));
+        assert!(config.timezone().is_err());
+
+        let invalid = [("KRABBYDO_MONGODB_TLS", "maybe")]
+            .map(|(key, value)| (key.to_string(), value.to_string()));
+        assert!(config.apply_env_vars(invalid).is_err());
+    }
+}
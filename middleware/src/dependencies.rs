@@ -0,0 +1,197 @@
+//! Dependencies between events: an event blocked by others can't be started until they are done,
+//! e.g. "Announce the release" waits for "Tag the commit". Blockers which are done, in the trash
+//! or gone no longer block. Every store checks the blockers of the events it adds and updates: it
+//! rejects a blocker it doesn't hold and any blocker which would make events wait for each other
+//! in a cycle.
+
+use crate::{EventEntry, MiddlewareError};
+use mongodb::bson::oid::ObjectId;
+use std::collections::HashMap;
+
+impl EventEntry {
+    /// Blockers of the event which aren't done yet, in the order they were added; events is the
+    /// list the blockers are looked up in, usually every event not in the trash
+    pub fn open_blockers<'a>(&self, events: &'a [EventEntry]) -> Vec<&'a EventEntry> {
+        self.blocked_by
+            .iter()
+            .filter_map(|id| events.iter().find(|x| x.unique_id == *id))
+            .filter(|x| !x.is_done && x.deleted_at.is_none())
+            .collect()
+    }
+
+    /// Whether the event waits for a blocker which isn't done yet
+    pub fn is_blocked(&self, events: &[EventEntry]) -> bool {
+        !self.open_blockers(events).is_empty()
+    }
+}
+
+/// Checks the blockers of the entry against the stored events: each must be another event which
+/// is stored and not in the trash, and the entry must not end up waiting for itself through them
+pub fn check_blockers(entry: &EventEntry, events: &[EventEntry]) -> Result<(), MiddlewareError> {
+    check_new_blockers(entry, &[], events)
+}
+
+/// Checks the blockers of an entry being stored, whose stored blockers were previous, like
+/// check_blockers; nothing is checked when they are unchanged, and blockers which were stored
+/// already may be gone, since a blocker deleted later simply no longer blocks
+pub(crate) fn check_new_blockers(
+    entry: &EventEntry,
+    previous: &[ObjectId],
+    events: &[EventEntry],
+) -> Result<(), MiddlewareError> {
+    if entry.blocked_by == previous {
+        return Ok(());
+    }
+    let mut blockers: HashMap<ObjectId, &EventEntry> = events
+        .iter()
+        .filter(|x| x.deleted_at.is_none())
+        .map(|x| (x.unique_id, x))
+        .collect();
+    for id in &entry.blocked_by {
+        if *id == entry.unique_id {
+            return Err(MiddlewareError::Validation(String::from(
+                "An event can't be blocked by itself",
+            )));
+        }
+        if !blockers.contains_key(id) && !previous.contains(id) {
+            return Err(MiddlewareError::Validation(format!(
+                "The blocker {} isn't a stored event",
+                id
+            )));
+        }
+    }
+    // The stored blockers of the entry are replaced by the ones checked
+    blockers.insert(entry.unique_id, entry);
+    if let Some(cycle) = find_cycle(entry, &blockers) {
+        let titles: Vec<&str> = cycle.iter().map(|x| x.title.as_str()).collect();
+        return Err(MiddlewareError::Validation(format!(
+            "The blockers would make a cycle: {}",
+            titles.join(" waits for ")
+        )));
+    }
+    Ok(())
+}
+
+/// Follows the blockers from the entry depth-first; returns the events of a path leading back to
+/// the entry, starting and ending with it, or None when there is no such path
+fn find_cycle<'a>(
+    entry: &'a EventEntry,
+    events: &HashMap<ObjectId, &'a EventEntry>,
+) -> Option<Vec<&'a EventEntry>> {
+    let mut path = vec![entry];
+    let mut next = vec![entry.blocked_by.iter()];
+    let mut visited = vec![entry.unique_id];
+    while let Some(blockers) = next.last_mut() {
+        let Some(id) = blockers.next() else {
+            next.pop();
+            path.pop();
+            continue;
+        };
+        if *id == entry.unique_id {
+            path.push(entry);
+            return Some(path);
+        }
+        if visited.contains(id) {
+            continue;
+        }
+        visited.push(*id);
+        // References to events which are gone lead nowhere
+        if let Some(blocker) = events.get(id) {
+            path.push(blocker);
+            next.push(blocker.blocked_by.iter());
+        }
+    }
+    None
+}
+
+/// Text form of the blockers kept in the history and by SQLite: their ids separated by commas
+pub(crate) fn blockers_to_text(blocked_by: &[ObjectId]) -> String {
+    blocked_by
+        .iter()
+        .map(|id| id.to_hex())
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+/// Reads the blockers back from their text form; None when an id is invalid
+pub(crate) fn blockers_from_text(text: &str) -> Option<Vec<ObjectId>> {
+    text.split(',')
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(|id| ObjectId::parse_str(id).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample_entry;
+    use chrono::Utc;
+
+    #[test]
+    fn test_check_blockers() {
+        let tag = sample_entry("Tag the commit");
+        let build = EventEntry {
+            blocked_by: vec![tag.unique_id],
+            ..sample_entry("Build the packages")
+        };
+        let announce = EventEntry {
+            blocked_by: vec![build.unique_id],
+            ..sample_entry("Announce it")
+        };
+        let events = vec![tag.clone(), build.clone(), announce.clone()];
+        assert!(check_blockers(&announce, &events).is_ok());
+
+        let waits_for_itself = EventEntry {
+            blocked_by: vec![tag.unique_id],
+            ..tag.clone()
+        };
+        assert!(check_blockers(&waits_for_itself, &events).is_err());
+        let unknown = EventEntry {
+            blocked_by: vec![ObjectId::new()],
+            ..tag.clone()
+        };
+        assert!(check_blockers(&unknown, &events).is_err());
+
+        // Tagging after the announcement would have the three events wait for each other
+        let cycle = EventEntry {
+            blocked_by: vec![announce.unique_id],
+            ..tag.clone()
+        };
+        match check_blockers(&cycle, &events) {
+            Err(MiddlewareError::Validation(message)) => assert_eq!(
+                message,
+                "The blockers would make a cycle: Tag the commit waits for Announce it \
+                 waits for Build the packages waits for Tag the commit"
+            ),
+            result => panic!("Expected a cycle, got {:?}", result),
+        }
+
+        // A blocker in the trash can't be added
+        let deleted = EventEntry {
+            deleted_at: Some(Utc::now()),
+            ..tag.clone()
+        };
+        assert!(check_blockers(&build, &[deleted]).is_err());
+    }
+
+    #[test]
+    fn test_is_blocked() {
+        let mut tag = sample_entry("Tag the commit");
+        let build = sample_entry("Build the packages");
+        let announce = EventEntry {
+            blocked_by: vec![tag.unique_id, build.unique_id],
+            ..sample_entry("Announce it")
+        };
+        let events = vec![tag.clone(), announce.clone()];
+        // The build is gone, so only the tag blocks
+        assert_eq!(announce.open_blockers(&events), vec![&tag]);
+        tag.is_done = true;
+        assert!(!announce.is_blocked(&[tag]));
+
+        let text = blockers_to_text(&announce.blocked_by);
+        assert_eq!(blockers_from_text(&text), Some(announce.blocked_by.clone()));
+        assert_eq!(blockers_from_text(""), Some(Vec::new()));
+        assert_eq!(blockers_from_text("tag"), None);
+    }
+}
@@ -0,0 +1,127 @@
+//! MiddlewareError is returned by every fallible function of the middleware,
+//! so callers can tell a connection failure from a malformed document or a missing record.
+
+use mongodb::bson::oid::ObjectId;
+use mongodb::error::{ErrorKind, WriteFailure};
+
+/// Error code MongoDB reports when an insert collides with an existing _id
+const DUPLICATE_KEY_CODE: i32 = 11000;
+
+/// Errors of the middleware
+#[derive(Debug, thiserror::Error)]
+pub enum MiddlewareError {
+    /// The database can't be reached, or refused the credentials
+    #[error("Could not connect to the database: {0}")]
+    Connection(#[source] Box<dyn std::error::Error + Send + Sync>),
+
+    /// No event has the given id
+    #[error("Event {id} not found")]
+    NotFound { id: ObjectId },
+
+    /// A stored event has a missing or malformed field
+    #[error("Invalid {field} in event {id}")]
+    Decode { field: String, id: String },
+
+    /// The operation collides with the stored state of an event
+    #[error("Event {id} conflicts with the stored event")]
+    Conflict { id: ObjectId },
+
+    /// The caller passed a value which can't be stored
+    #[error("Invalid value: {0}")]
+    Validation(String),
+
+    /// The config file or an environment variable holds an invalid setting
+    #[error("Invalid configuration: {0}")]
+    Config(String),
+
+    /// Reading a file or starting the runtime failed
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    /// Any other failure reported by the database
+    #[error("Database error: {0}")]
+    Database(#[source] Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl MiddlewareError {
+    /// Decode error for a field of the event with the given id
+    pub fn decode(field: &str, id: Option<&ObjectId>) -> Self {
+        MiddlewareError::Decode {
+            field: field.to_string(),
+            id: id.map_or_else(|| String::from("<unknown>"), |id| id.to_hex()),
+        }
+    }
+
+    /// Whether a write failed because a document with the same _id already exists
+    pub(crate) fn is_duplicate_key(error: &mongodb::error::Error) -> bool {
+        matches!(
+            error.kind.as_ref(),
+            ErrorKind::Write(WriteFailure::WriteError(write_error))
+                if write_error.code == DUPLICATE_KEY_CODE
+        )
+    }
+}
+
+impl From<mongodb::error::Error> for MiddlewareError {
+    fn from(error: mongodb::error::Error) -> Self {
+        match error.kind.as_ref() {
+            ErrorKind::ServerSelection { .. }
+            | ErrorKind::ConnectionPoolCleared { .. }
+            | ErrorKind::Authentication { .. }
+            | ErrorKind::DnsResolve { .. }
+            | ErrorKind::InvalidTlsConfig { .. }
+            | ErrorKind::Io(_) => MiddlewareError::Connection(Box::new(error)),
+            ErrorKind::InvalidArgument { message, .. } => MiddlewareError::Config(message.clone()),
+            _ => MiddlewareError::Database(Box::new(error)),
+        }
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl From<rusqlite::Error> for MiddlewareError {
+    fn from(error: rusqlite::Error) -> Self {
+        match error.sqlite_error_code() {
+            Some(rusqlite::ErrorCode::CannotOpen)
+            | Some(rusqlite::ErrorCode::PermissionDenied)
+            | Some(rusqlite::ErrorCode::NotADatabase) => {
+                MiddlewareError::Connection(Box::new(error))
+            }
+            _ => MiddlewareError::Database(Box::new(error)),
+        }
+    }
+}
+
+impl From<toml::de::Error> for MiddlewareError {
+    fn from(error: toml::de::Error) -> Self {
+        MiddlewareError::Config(error.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_mongodb_error() {
+        let error = mongodb::error::Error::from(std::io::Error::from(
+            std::io::ErrorKind::ConnectionRefused,
+        ));
+        assert!(matches!(
+            MiddlewareError::from(error),
+            MiddlewareError::Connection(_)
+        ));
+    }
+
+    #[test]
+    fn test_decode_message() {
+        let id = ObjectId::parse_str("6482a04d44d9bc1cff4c66d7").unwrap();
+        assert_eq!(
+            MiddlewareError::decode("date_time", Some(&id)).to_string(),
+            "Invalid date_time in event 6482a04d44d9bc1cff4c66d7"
+        );
+        assert_eq!(
+            MiddlewareError::decode("_id", None).to_string(),
+            "Invalid _id in event <unknown>"
+        );
+    }
+}
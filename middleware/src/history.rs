@@ -0,0 +1,302 @@
+//! Revision history of the events.
+//! Every create, update, completion, delete and restore is recorded as a Revision holding the
+//! field-level diff, the time of the change and who made it, so earlier contents can be looked up and restored.
+
+use crate::dependencies::{blockers_from_text, blockers_to_text};
+use crate::subtasks::{subtasks_from_text, subtasks_to_text};
+use crate::tags::{parse_tags, tags_to_text};
+use crate::{EventEntry, MiddlewareError};
+use chrono::{DateTime, Utc};
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+/// Kinds of change recorded in the history of an event
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RevisionAction {
+    Create,
+    Update,
+    Complete,
+    Delete,
+    Restore,
+}
+
+impl RevisionAction {
+    /// Name under which the action is stored
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RevisionAction::Create => "create",
+            RevisionAction::Update => "update",
+            RevisionAction::Complete => "complete",
+            RevisionAction::Delete => "delete",
+            RevisionAction::Restore => "restore",
+        }
+    }
+}
+
+impl std::str::FromStr for RevisionAction {
+    type Err = MiddlewareError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "create" => Ok(RevisionAction::Create),
+            "update" => Ok(RevisionAction::Update),
+            "complete" => Ok(RevisionAction::Complete),
+            "delete" => Ok(RevisionAction::Delete),
+            "restore" => Ok(RevisionAction::Restore),
+            _ => Err(MiddlewareError::decode("action", None)),
+        }
+    }
+}
+
+/// FieldChange holds the value of one field before and after a change.
+/// Values are kept as text: dates in RFC 3339, booleans as "true" / "false", recurrences as RRULE
+/// text and subtasks as a "- [x] title" checklist.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FieldChange {
+    /// Name of the field of EventEntry
+    pub field: String,
+    /// Value before the change; empty for a newly created event
+    pub old_value: String,
+    /// Value after the change
+    pub new_value: String,
+}
+
+/// Revision records one change of an event
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Revision {
+    /// Id of the revision itself
+    pub revision_id: ObjectId,
+    /// Id of the event which was changed
+    pub event_id: ObjectId,
+    /// What was done to the event
+    pub action: RevisionAction,
+    /// Fields which changed; empty for deletes and restores
+    pub changes: Vec<FieldChange>,
+    /// When the change was made
+    pub timestamp: DateTime<Utc>,
+    /// Who made the change
+    pub actor: String,
+}
+
+impl Revision {
+    /// Records the change from old to new made now by the actor
+    pub fn new(
+        action: RevisionAction,
+        old: Option<&EventEntry>,
+        new: &EventEntry,
+        actor: &str,
+    ) -> Self {
+        Revision {
+            revision_id: ObjectId::new(),
+            event_id: new.unique_id,
+            action,
+            changes: diff_events(old, new),
+            timestamp: Utc::now(),
+            actor: actor.to_string(),
+        }
+    }
+}
+
+/// Fields tracked by the history, in the order they are listed in a diff
+const TRACKED_FIELDS: [&str; 11] = [
+    "title",
+    "details",
+    "date_time",
+    "is_done",
+    "tags",
+    "recurrence",
+    "subtasks",
+    "auto_complete",
+    "blocked_by",
+    "priority",
+    "project_id",
+];
+
+/// Text form of a tracked field of the event
+fn field_value(entry: &EventEntry, field: &str) -> String {
+    match field {
+        "title" => entry.title.clone(),
+        "details" => entry.details.clone().unwrap_or_default(),
+        "date_time" => entry
+            .date_time
+            .map(|date_time| date_time.to_rfc3339())
+            .unwrap_or_default(),
+        "is_done" => entry.is_done.to_string(),
+        "tags" => tags_to_text(&entry.tags),
+        "recurrence" => entry
+            .recurrence
+            .as_ref()
+            .map(ToString::to_string)
+            .unwrap_or_default(),
+        "subtasks" => subtasks_to_text(&entry.subtasks),
+        "auto_complete" => entry.auto_complete.to_string(),
+        "blocked_by" => blockers_to_text(&entry.blocked_by),
+        "priority" => entry.priority.to_string(),
+        "project_id" => entry.project_id.map(|id| id.to_hex()).unwrap_or_default(),
+        _ => String::new(),
+    }
+}
+
+/// An optional field is recorded as empty text when it has no value
+fn optional_value(value: &str) -> Option<String> {
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+/// Sets a tracked field of the event from its text form
+fn set_field_value(
+    entry: &mut EventEntry,
+    field: &str,
+    value: &str,
+) -> Result<(), MiddlewareError> {
+    let invalid = || MiddlewareError::decode(field, Some(&entry.unique_id));
+    match field {
+        "title" => entry.title = value.to_string(),
+        "details" => entry.details = optional_value(value),
+        "date_time" if value.is_empty() => entry.date_time = None,
+        "date_time" => {
+            entry.date_time = Some(
+                DateTime::parse_from_rfc3339(value)
+                    .map_err(|_| invalid())?
+                    .with_timezone(&Utc),
+            )
+        }
+        "is_done" => entry.is_done = value.parse().map_err(|_| invalid())?,
+        "tags" => entry.tags = parse_tags(value),
+        "recurrence" if value.is_empty() => entry.recurrence = None,
+        "recurrence" => entry.recurrence = Some(value.parse().map_err(|_| invalid())?),
+        "subtasks" => {
+            entry.subtasks = subtasks_from_text(value, &entry.subtasks).ok_or_else(invalid)?
+        }
+        "auto_complete" => entry.auto_complete = value.parse().map_err(|_| invalid())?,
+        "blocked_by" => entry.blocked_by = blockers_from_text(value).ok_or_else(invalid)?,
+        "priority" => entry.priority = value.parse().map_err(|_| invalid())?,
+        "project_id" if value.is_empty() => entry.project_id = None,
+        "project_id" => entry.project_id = Some(ObjectId::parse_str(value).map_err(|_| invalid())?),
+        _ => return Err(invalid()),
+    }
+    Ok(())
+}
+
+/// Lists the tracked fields whose value differs between old and new;
+/// every field counts as changed when there is no old event
+pub fn diff_events(old: Option<&EventEntry>, new: &EventEntry) -> Vec<FieldChange> {
+    TRACKED_FIELDS
+        .iter()
+        .filter_map(|field| {
+            let old_value = old.map(|x| field_value(x, field)).unwrap_or_default();
+            let new_value = field_value(new, field);
+            if old.is_some() && old_value == new_value {
+                None
+            } else {
+                Some(FieldChange {
+                    field: field.to_string(),
+                    old_value,
+                    new_value,
+                })
+            }
+        })
+        .collect()
+}
+
+/// Whether any tracked field differs between old and new, i.e. whether an update is worth recording
+pub fn has_changes(old: &EventEntry, new: &EventEntry) -> bool {
+    !diff_events(Some(old), new).is_empty()
+}
+
+/// The action recorded for an update from old to new
+pub fn update_action(old: &EventEntry, new: &EventEntry) -> RevisionAction {
+    if !old.is_done && new.is_done {
+        RevisionAction::Complete
+    } else {
+        RevisionAction::Update
+    }
+}
+
+/// Contents of the event right after the given revision, worked out by undoing the revisions
+/// made after it on the current contents; history must be ordered from oldest to newest
+pub fn event_at_revision(
+    current: &EventEntry,
+    history: &[Revision],
+    revision_id: &ObjectId,
+) -> Result<EventEntry, MiddlewareError> {
+    let position = history
+        .iter()
+        .position(|x| x.revision_id == *revision_id)
+        .ok_or(MiddlewareError::NotFound { id: *revision_id })?;
+
+    let mut entry = current.clone();
+    for revision in history[position + 1..].iter().rev() {
+        for change in &revision.changes {
+            set_field_value(&mut entry, &change.field, &change.old_value)?;
+        }
+    }
+    Ok(entry)
+}
+
+/// Name recorded as the actor when none is configured: the login name of the user
+pub fn default_actor() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| String::from("unknown"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample_entry;
+    use crate::{Frequency, Recurrence};
+
+    #[test]
+    fn test_diff_events() {
+        let old = EventEntry {
+            details: Some(String::from("First details")),
+            ..sample_entry("KrabbyDo")
+        };
+        let mut new = old.clone();
+        new.details = Some(String::from("Second details"));
+        new.is_done = true;
+
+        let changes = diff_events(Some(&old), &new);
+        assert_eq!(
+            changes,
+            vec![
+                FieldChange {
+                    field: String::from("details"),
+                    old_value: String::from("First details"),
+                    new_value: String::from("Second details"),
+                },
+                FieldChange {
+                    field: String::from("is_done"),
+                    old_value: String::from("false"),
+                    new_value: String::from("true"),
+                },
+            ]
+        );
+        assert_eq!(update_action(&old, &new), RevisionAction::Complete);
+        assert_eq!(diff_events(None, &new).len(), TRACKED_FIELDS.len());
+    }
+
+    #[test]
+    fn test_event_at_revision() {
+        let first = sample_entry("KrabbyDo");
+        let mut second = first.clone();
+        second.details = Some(String::from("Second details"));
+        let mut third = second.clone();
+        third.date_time = None;
+        third.title = String::from("Renamed");
+        third.recurrence = Some(Recurrence::new(Frequency::Weekly).count(3));
+        third.add_subtask("Review the notes").unwrap();
+
+        let history = vec![
+            Revision::new(RevisionAction::Create, None, &first, "krabby"),
+            Revision::new(RevisionAction::Update, Some(&first), &second, "krabby"),
+            Revision::new(RevisionAction::Update, Some(&second), &third, "krabby"),
+        ];
+        for (revision, expected) in history.iter().zip([&first, &second, &third]) {
+            let entry = event_at_revision(&third, &history, &revision.revision_id).unwrap();
+            assert_eq!(&entry, expected);
+        }
+        assert!(event_at_revision(&third, &history, &ObjectId::new()).is_err());
+    }
+}
@@ -0,0 +1,23 @@
+//! Indexes backing the query API.
+//! Each store declares the indexes its queries need (date, done and date, tags, text), and
+//! `ensure_indexes` creates the ones missing when the store is opened, so filters don't scan every event.
+
+/// IndexReport lists the indexes ensure_indexes created and those which were already present
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct IndexReport {
+    /// Indexes which were missing and have been created
+    pub created: Vec<String>,
+    /// Indexes which were already present
+    pub existing: Vec<String>,
+}
+
+impl IndexReport {
+    /// Files the index under created or existing
+    pub(crate) fn add(&mut self, name: &str, created: bool) {
+        if created {
+            self.created.push(name.to_string());
+        } else {
+            self.existing.push(name.to_string());
+        }
+    }
+}
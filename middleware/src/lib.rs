@@ -1,12 +1,59 @@
 //! This is the middleware of project , which deals with database and provide CRUDE operation which can be accessed by other crates as per need
 //! It Will connect to mongo database which we operated throuht monngo DB compass application.
+//! Every operation goes through the EventStore trait, so an in-memory store can be used in place of MongoDB.
 
 use chrono::{DateTime, Utc};
-use mongodb::bson::{doc, oid::ObjectId, Bson, Document};
-use mongodb::Collection;
-use mongodb::{options::ClientOptions, Client};
+use mongodb::bson::oid::ObjectId;
 use serde::{Deserialize, Serialize};
-use tokio_stream::StreamExt as TokioStreamExt;
+use std::sync::Arc;
+
+mod bulk;
+mod client;
+mod config;
+mod dependencies;
+mod error;
+mod history;
+mod indexes;
+mod memory;
+mod migrations;
+mod mongo;
+mod projects;
+mod query;
+mod recurrence;
+mod search;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod store;
+mod subtasks;
+mod sync;
+mod tags;
+mod timezone;
+mod urgency;
+
+pub use bulk::{EventPatch, EventSelection};
+pub use client::KrabbyClient;
+pub use config::{Backend, MiddlewareConfig, MongoConfig, SqliteConfig};
+pub use dependencies::check_blockers;
+pub use error::MiddlewareError;
+pub use history::{FieldChange, Revision, RevisionAction};
+pub use indexes::IndexReport;
+pub use memory::MemoryStore;
+pub use migrations::{MigrationReport, StepReport};
+pub use mongo::{create_mongodb_client, MongoStore};
+pub use projects::Project;
+pub use query::{EventQuery, SortDirection, SortKey};
+pub use recurrence::{
+    complete_occurrence, occurrences_between, ByDay, Frequency, Occurrence, Recurrence,
+};
+pub use search::SearchHit;
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteStore;
+pub use store::EventStore;
+pub use subtasks::Subtask;
+pub use sync::{diff_snapshots, poll_changes, EventChange, EventChangeStream};
+pub use tags::{parse_tags, TagCount};
+pub use timezone::{day_bounds, local_timezone, local_to_utc, parse_timezone, today, Tz};
+pub use urgency::{Priority, UrgencyConfig};
 
 /// EventEntry structs stores the data related to one particular event.
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -15,17 +62,48 @@ pub struct EventEntry {
     pub unique_id: ObjectId,
     /// Denotates Title of the task
     pub title: String,
-    /// Denotates Descripation of task
-    pub details: String,
-    /// Denotates the time for deadline of task
-    pub date_time: DateTime<Utc>,
+    /// Denotates Descripation of task; None when the task has none
+    #[serde(default)]
+    pub details: Option<String>,
+    /// Denotates the time for deadline of task; None for someday / maybe tasks without a deadline
+    #[serde(default)]
+    pub date_time: Option<DateTime<Utc>>,
     /// Denotates if task is done or not
     pub is_done: bool,
-    /// Assigns the tag to the task like Home, Work etc.,
-    pub tags: String,
+    /// Assigns tags to the task like home, work etc., normalized by parse_tags; empty when the
+    /// task has no tags
+    #[serde(default, deserialize_with = "tags::deserialize_tags")]
+    pub tags: Vec<String>,
+    /// Denotates when the task was moved to the trash; None while it isn't in the trash
+    #[serde(default)]
+    pub deleted_at: Option<DateTime<Utc>>,
+    /// Denotates how many times the task was updated; an update carries the version it was made on,
+    /// so a task changed by someone else in the meantime isn't overwritten
+    #[serde(default)]
+    pub version: u64,
+    /// Denotates the rule the task repeats by; None for a task which happens once
+    #[serde(default)]
+    pub recurrence: Option<Recurrence>,
+    /// Steps of the task, in the order they are listed
+    #[serde(default)]
+    pub subtasks: Vec<Subtask>,
+    /// Denotates if the task is marked done once all its subtasks are done
+    #[serde(default)]
+    pub auto_complete: bool,
+    /// Ids of the tasks which have to be done before this one can start
+    #[serde(default)]
+    pub blocked_by: Vec<ObjectId>,
+    /// Denotates how important the task is; tasks have no priority unless one is given
+    #[serde(default)]
+    pub priority: Priority,
+    /// Id of the project the task belongs to; None for a task in the Inbox
+    #[serde(default)]
+    pub project_id: Option<ObjectId>,
 }
 
 impl EventEntry {
+    /// Builds a task with every field set, its tags split out of the text by parse_tags; optional
+    /// fields are left out with the struct update syntax, e.g. `EventEntry { date_time: None, ..EventEntry::new(..) }`
     pub fn new(
         unique_id: ObjectId,
         title: String,
@@ -37,155 +115,89 @@ impl EventEntry {
         EventEntry {
             unique_id,
             title,
-            details,
-            date_time,
+            details: Some(details),
+            date_time: Some(date_time),
             is_done,
-            tags,
+            tags: parse_tags(&tags),
+            deleted_at: None,
+            version: 0,
+            recurrence: None,
+            subtasks: Vec::new(),
+            auto_complete: false,
+            blocked_by: Vec::new(),
+            priority: Priority::None,
+            project_id: None,
         }
     }
-    /// This function adds an event to the database
-    pub async fn add_event(&self) -> Result<(), Box<dyn std::error::Error>> {
-        #[cfg(feature = "print_debug_log")]
-        println!("Event added to MongoDB");
-
-        let client = create_mongodb_client().await?;
-        // Get a handle to the "todos" collection in the "tasks" database
-        let db = client.database("events");
-        let collection = db.collection("todos");
-
-        // Create a document representing the ToDo task
-        let document = doc! {
-            "title": self.title.clone(),
-            "details": self.details.clone(),
-            "date_time": self.date_time.to_rfc3339(),
-            "is_done": false,
-            "tags": self.tags.clone(),
-        };
-
-        // Insert the document into the collection
-        collection.insert_one(document, None).await?;
-
-        Ok(())
-    }
-    /// This function updates an event to the database
-    pub async fn update_task(&self) -> Result<(), Box<dyn std::error::Error>> {
-        #[cfg(feature = "print_debug_log")]
-        println!("Updating event with unique_id: {}", self.unique_id);
-
-        let client = create_mongodb_client().await?;
-        // Get a handle to the "todos" collection in the "tasks" database
-        let db = client.database("events");
-        let collection: Collection<Document> = db.collection("todos");
-
-        // Create a document representing the ToDo task
-
-        let filter = doc! { "_id":self.unique_id };
-        let update = doc! { "$set": { "title": self.title.clone(), "details": self.details.clone(),"date_time": self.date_time.to_rfc3339(),
-        "is_done": self.is_done, } };
-
-        // Insert the document into the collection
-        collection.update_one(filter, update, None).await?;
-
-        Ok(())
-    }
-    /// This function deletes the event from the database
-    pub async fn delete_event(&self) -> Result<(), Box<dyn std::error::Error>> {
-        let client = create_mongodb_client().await?;
-        let db = client.database("events");
-        let collection: Collection<Document> = db.collection("todos");
-
-        // Define the filter to find the event by its unique_id
-        let filter = doc! { "_id": self.unique_id };
-
-        // Update the document in the collection
-        collection.delete_one(filter, None).await?;
-
-        Ok(())
-    }
-    /// This function fetches all the events from database to show on UI
-    pub async fn get_all_tasks() -> Result<Vec<EventEntry>, Box<dyn std::error::Error>> {
-        let client = create_mongodb_client().await?;
-        let db = client.database("events");
-        let collection: Collection<Document> = db.collection("todos");
-
-        // Find all documents in the collection
-        let mut cursor = collection.find(None, None).await?;
-
-        // Iterate over the cursor using the `try_next` method
-        let mut tasks = Vec::new();
-
-        while let Some(result) = TokioStreamExt::try_next(&mut cursor).await? {
-            // Extract task data from the document
-            let unique_id = match result.get("_id") {
-                Some(Bson::ObjectId(object_id)) => *object_id,
-                _ => return Err("Invalid unique_id".into()), // Handle the error as you prefer
-            };
-            let title = result.get_str("title")?.to_string();
-            let details = result.get_str("details")?.to_string();
-            let date_time_str = result.get_str("date_time")?;
-            let date_time = DateTime::parse_from_rfc3339(date_time_str)?.with_timezone(&Utc);
-            let is_done = result.get_bool("is_done")?;
-            let tags = result.get_str("tags")?.to_string();
-
-            // Create a new EventEntry instance
-            let task = EventEntry::new(unique_id, title, details, date_time, is_done, tags);
+}
 
-            // Add the task to the vector
-            tasks.push(task);
+/// This function opens the storage backend chosen by the config, runs its pending migrations
+/// and purges the events which have been in the trash for longer than the retention period
+pub async fn open_store(config: &MiddlewareConfig) -> Result<Arc<dyn EventStore>, MiddlewareError> {
+    let store: Arc<dyn EventStore> = match config.backend {
+        Backend::MongoDb => Arc::new(
+            MongoStore::connect(&config.mongodb)
+                .await?
+                .with_actor(&config.actor())
+                .with_timezone(config.timezone()?)
+                .with_urgency(config.urgency.clone()),
+        ),
+        #[cfg(feature = "sqlite")]
+        Backend::Sqlite => Arc::new(
+            SqliteStore::open(config.sqlite.database_path())?
+                .with_actor(&config.actor())
+                .with_timezone(config.timezone()?)
+                .with_urgency(config.urgency.clone()),
+        ),
+        #[cfg(not(feature = "sqlite"))]
+        Backend::Sqlite => {
+            return Err(MiddlewareError::Config(String::from(
+                "The sqlite backend requires the \"sqlite\" feature",
+            )))
         }
-        Ok(tasks)
-    }
+    };
+    store.migrate(false).await?;
+    let _index_report = store.ensure_indexes().await?;
+    #[cfg(feature = "print_debug_log")]
+    println!("{:?}", _index_report);
+    store.purge_trash(config.trash_retention()).await?;
+    Ok(store)
+}
 
-    /// This function fetches only todays events from the database
-    pub async fn get_today_events() -> Result<Vec<EventEntry>, Box<dyn std::error::Error>> {
-        let client = create_mongodb_client().await?;
-        let db = client.database("events");
-        let collection: Collection<Document> = db.collection("todos");
+/// Event the tests start from: due now, not done, with some details and the tag "work"
+#[cfg(test)]
+pub(crate) fn sample_entry(title: &str) -> EventEntry {
+    EventEntry::new(
+        ObjectId::new(),
+        String::from(title),
+        String::from("Details"),
+        Utc::now(),
+        false,
+        String::from("Work"),
+    )
+}
 
-        let now = Utc::now();
-        let today = now.date_naive().and_hms_opt(0, 0, 0).unwrap();
-        let tomorrow = today + chrono::Duration::days(1);
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        // Filter documents based on the date range from today to tomorrow
-        let filter = doc! {
-            "date_time": {
-                "$gte": today.to_string(),
-                "$lt": tomorrow.to_string()
-            }
+    /// Client shared by the tests below; they need a MongoDB server on localhost
+    fn mongo_client() -> KrabbyClient {
+        let config = MiddlewareConfig {
+            backend: Backend::MongoDb,
+            ..Default::default()
         };
+        KrabbyClient::connect(&config).unwrap()
+    }
 
-        // Find documents that match the filter
-        let mut cursor = collection.find(filter, None).await?;
-
-        let mut tasks = Vec::new();
-
-        while let Some(result) = TokioStreamExt::try_next(&mut cursor).await? {
-            let unique_id = match result.get("_id") {
-                Some(Bson::ObjectId(object_id)) => *object_id,
-                _ => return Err("Invalid unique_id".into()),
-            };
-            let title = result.get_str("title")?.to_string();
-            let details = result.get_str("details")?.to_string();
-            let date_time_str = result.get_str("date_time")?;
-            let date_time = DateTime::parse_from_rfc3339(date_time_str)?.with_timezone(&Utc);
-            let is_done = result.get_bool("is_done")?;
-            let tags = result.get_str("tags")?.to_string();
-            let task = EventEntry::new(unique_id, title, details, date_time, is_done, tags);
-            tasks.push(task);
-        }
-        Ok(tasks)
+    /// Adds the event and returns it as stored; updating or deleting an event
+    /// which isn't stored fails with NotFound, so those tests start from a stored event
+    fn stored_copy(client: &KrabbyClient, event_entry: EventEntry) -> EventEntry {
+        client
+            .block_on(client.store().add_event(&event_entry))
+            .unwrap()
     }
-}
-/// This function creates a connection client for the database
-pub async fn create_mongodb_client() -> Result<Client, Box<dyn std::error::Error>> {
-    let client_options = ClientOptions::parse("mongodb://localhost:27017").await?;
-    let client = Client::with_options(client_options)?;
-    Ok(client)
-}
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::str::FromStr;
+
     #[test]
     fn test_update_task() {
         let task_name = String::from("KrabbyDo new setup 4");
@@ -193,7 +205,7 @@ mod tests {
         let reminder_time = Utc::now();
         let is_completed = false;
         let tags = String::from("Work");
-        let mongo_id = ObjectId::from_str("6482a04d44d9bc1cff4c66d7").unwrap();
+        let mongo_id = ObjectId::new();
 
         let event_entry = EventEntry::new(
             mongo_id,
@@ -203,8 +215,9 @@ mod tests {
             is_completed,
             tags,
         );
-        let rt = tokio::runtime::Runtime::new().unwrap();
-        let result = rt.block_on(async { event_entry.update_task().await });
+        let client = mongo_client();
+        let event_entry = stored_copy(&client, event_entry);
+        let result = client.block_on(client.store().update_task(&event_entry));
 
         // Assert that the add_task function succeeded
         assert!(result.is_ok(), "_event failed");
@@ -225,10 +238,10 @@ mod tests {
             is_completed,
             tags,
         );
-        let rt = tokio::runtime::Runtime::new().unwrap();
+        let client = mongo_client();
 
         // Run the add_task function asynchronously
-        let result = rt.block_on(async { event_entry.add_event().await });
+        let result = client.block_on(client.store().add_event(&event_entry));
 
         // Assert that the add_task function succeeded
         assert!(result.is_ok(), "add_event failed");
@@ -236,10 +249,10 @@ mod tests {
     #[test]
 
     fn test_get_all_tasks() {
-        let rt = tokio::runtime::Runtime::new().unwrap();
+        let client = mongo_client();
 
         // Run the get_all_tasks function asynchronously
-        let result = rt.block_on(async { EventEntry::get_all_tasks().await });
+        let result = client.block_on(client.store().get_all_tasks());
 
         // Assert that the get_all_tasks function succeeded
         assert!(result.is_ok(), "get_all_tasks failed");
@@ -250,7 +263,7 @@ mod tests {
         let task_desc = String::from("Test delete_or_mark_completed");
         let reminder_time = Utc::now();
         let is_completed = false;
-        let mongo_id = ObjectId::from_str("6482a04d44d9bc1cff4c66d7").unwrap();
+        let mongo_id = ObjectId::new();
         let tags = String::from("Work");
         let event_entry = EventEntry::new(
             mongo_id,
@@ -260,18 +273,19 @@ mod tests {
             is_completed,
             tags,
         );
-        let rt = tokio::runtime::Runtime::new().unwrap();
-        let result = rt.block_on(async { event_entry.delete_event().await });
+        let client = mongo_client();
+        let event_entry = stored_copy(&client, event_entry);
+        let result = client.block_on(client.store().delete_event(&event_entry));
 
         // Assert that the delete_or_mark_completed function succeeded
         assert!(result.is_ok(), "delete_event failed");
     }
     #[test]
     fn test_get_today_events() {
-        let rt = tokio::runtime::Runtime::new().unwrap();
+        let client = mongo_client();
 
         // Run the get_today_events function asynchronously
-        let result = rt.block_on(async { EventEntry::get_today_events().await });
+        let result = client.block_on(client.store().get_today_events());
 
         // Assert that the get_today_events function succeeded
         assert!(result.is_ok(), "get_today_events failed");
@@ -0,0 +1,850 @@
+//! In-memory implementation of the EventStore.
+//! Nothing is persisted, which makes it handy for tests and for running Krabby Do without a database.
+
+use crate::bulk::selected_ids;
+use crate::dependencies::check_new_blockers;
+use crate::history::{default_actor, has_changes, update_action, Revision, RevisionAction};
+use crate::projects::sort_projects;
+use crate::search::{rank, score, search_terms};
+use crate::timezone::{day_bounds, local_timezone, today};
+use crate::{
+    EventEntry, EventPatch, EventQuery, EventSelection, EventStore, MiddlewareError, Project,
+    SearchHit, Tz, UrgencyConfig,
+};
+use async_trait::async_trait;
+use chrono::{Duration, Utc};
+use mongodb::bson::oid::ObjectId;
+use std::sync::Mutex;
+
+/// MemoryStore keeps the events in a vector guarded by a mutex so it can be shared between threads
+#[derive(Debug)]
+pub struct MemoryStore {
+    events: Mutex<Vec<EventEntry>>,
+    /// Revision history of the events, oldest first
+    revisions: Mutex<Vec<Revision>>,
+    /// Projects the events are grouped in
+    projects: Mutex<Vec<Project>>,
+    /// Recorded as the author of every revision
+    actor: String,
+    /// Zone in which days start and end
+    timezone: Tz,
+    /// Coefficients of the urgency score
+    urgency: UrgencyConfig,
+}
+
+impl Default for MemoryStore {
+    fn default() -> Self {
+        Self::with_events(Vec::new())
+    }
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Creates a store which already holds the given events
+    pub fn with_events(events: Vec<EventEntry>) -> Self {
+        MemoryStore {
+            events: Mutex::new(events),
+            revisions: Mutex::new(Vec::new()),
+            projects: Mutex::new(Vec::new()),
+            actor: default_actor(),
+            timezone: local_timezone(),
+            urgency: UrgencyConfig::default(),
+        }
+    }
+
+    /// Sets the name recorded as the author of the revisions
+    pub fn with_actor(mut self, actor: &str) -> Self {
+        self.actor = actor.to_string();
+        self
+    }
+
+    /// Sets the zone in which days start and end
+    pub fn with_timezone(mut self, timezone: Tz) -> Self {
+        self.timezone = timezone;
+        self
+    }
+
+    /// Sets the coefficients of the urgency score
+    pub fn with_urgency(mut self, urgency: UrgencyConfig) -> Self {
+        self.urgency = urgency;
+        self
+    }
+
+    /// Adds a revision to the history
+    fn record(&self, action: RevisionAction, old: Option<&EventEntry>, new: &EventEntry) {
+        let revision = Revision::new(action, old, new, &self.actor);
+        self.revisions.lock().unwrap().push(revision);
+    }
+}
+
+#[async_trait]
+impl EventStore for MemoryStore {
+    async fn add_event(&self, entry: &EventEntry) -> Result<EventEntry, MiddlewareError> {
+        let mut events = self.events.lock().unwrap();
+        if events.iter().any(|x| x.unique_id == entry.unique_id) {
+            return Err(MiddlewareError::Conflict {
+                id: entry.unique_id,
+            });
+        }
+        check_new_blockers(entry, &[], &events)?;
+        events.push(entry.clone());
+        self.record(RevisionAction::Create, None, entry);
+        Ok(entry.clone())
+    }
+
+    async fn update_task(&self, entry: &EventEntry) -> Result<EventEntry, MiddlewareError> {
+        let mut events = self.events.lock().unwrap();
+        let index = events
+            .iter()
+            .position(|x| x.unique_id == entry.unique_id && x.deleted_at.is_none())
+            .ok_or(MiddlewareError::NotFound {
+                id: entry.unique_id,
+            })?;
+        if events[index].version != entry.version {
+            return Err(MiddlewareError::Conflict {
+                id: entry.unique_id,
+            });
+        }
+        check_new_blockers(entry, &events[index].blocked_by, &events)?;
+        let event = &mut events[index];
+        let old = event.clone();
+        *event = EventEntry {
+            deleted_at: None,
+            version: old.version + 1,
+            ..entry.clone()
+        };
+        if has_changes(&old, event) {
+            self.record(update_action(&old, event), Some(&old), event);
+        }
+        Ok(event.clone())
+    }
+
+    async fn delete_event(&self, entry: &EventEntry) -> Result<(), MiddlewareError> {
+        let mut events = self.events.lock().unwrap();
+        let event = events
+            .iter_mut()
+            .find(|x| x.unique_id == entry.unique_id && x.deleted_at.is_none())
+            .ok_or(MiddlewareError::NotFound {
+                id: entry.unique_id,
+            })?;
+        event.deleted_at = Some(Utc::now());
+        self.record(RevisionAction::Delete, Some(event), event);
+        Ok(())
+    }
+
+    async fn get_event(&self, id: &ObjectId) -> Result<EventEntry, MiddlewareError> {
+        self.events
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|x| x.unique_id == *id)
+            .cloned()
+            .ok_or(MiddlewareError::NotFound { id: *id })
+    }
+
+    async fn get_all_tasks(&self) -> Result<Vec<EventEntry>, MiddlewareError> {
+        Ok(self
+            .events
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|x| x.deleted_at.is_none())
+            .cloned()
+            .collect())
+    }
+
+    fn timezone(&self) -> Tz {
+        self.timezone
+    }
+
+    fn urgency(&self) -> &UrgencyConfig {
+        &self.urgency
+    }
+
+    async fn get_today_events(&self) -> Result<Vec<EventEntry>, MiddlewareError> {
+        let (today, tomorrow) = day_bounds(today(&self.timezone), &self.timezone);
+
+        Ok(self
+            .events
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|x| x.deleted_at.is_none())
+            .filter(|x| matches!(x.date_time, Some(date_time) if date_time >= today && date_time < tomorrow))
+            .cloned()
+            .collect())
+    }
+
+    async fn query_events(&self, query: &EventQuery) -> Result<Vec<EventEntry>, MiddlewareError> {
+        let events = self.events.lock().unwrap().clone();
+        Ok(query.apply(events, &self.urgency, Utc::now()))
+    }
+
+    async fn search_events(&self, text: &str) -> Result<Vec<SearchHit>, MiddlewareError> {
+        let terms = search_terms(text);
+        let mut hits: Vec<SearchHit> = self
+            .events
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|x| x.deleted_at.is_none())
+            .map(|x| SearchHit {
+                entry: x.clone(),
+                score: score(x, &terms),
+            })
+            .filter(|hit| hit.score > 0.0)
+            .collect();
+        rank(&mut hits);
+        Ok(hits)
+    }
+
+    async fn get_trash(&self) -> Result<Vec<EventEntry>, MiddlewareError> {
+        Ok(self
+            .events
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|x| x.deleted_at.is_some())
+            .cloned()
+            .collect())
+    }
+
+    async fn restore_event(&self, entry: &EventEntry) -> Result<EventEntry, MiddlewareError> {
+        let mut events = self.events.lock().unwrap();
+        let event = events
+            .iter_mut()
+            .find(|x| x.unique_id == entry.unique_id && x.deleted_at.is_some())
+            .ok_or(MiddlewareError::NotFound {
+                id: entry.unique_id,
+            })?;
+        event.deleted_at = None;
+        self.record(RevisionAction::Restore, Some(event), event);
+        Ok(event.clone())
+    }
+
+    async fn purge_event(&self, entry: &EventEntry) -> Result<(), MiddlewareError> {
+        let mut events = self.events.lock().unwrap();
+        let index = events
+            .iter()
+            .position(|x| x.unique_id == entry.unique_id && x.deleted_at.is_some())
+            .ok_or(MiddlewareError::NotFound {
+                id: entry.unique_id,
+            })?;
+        events.remove(index);
+        Ok(())
+    }
+
+    async fn get_history(&self, event_id: &ObjectId) -> Result<Vec<Revision>, MiddlewareError> {
+        Ok(self
+            .revisions
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|x| x.event_id == *event_id)
+            .cloned()
+            .collect())
+    }
+
+    async fn purge_trash(&self, retention: Duration) -> Result<u64, MiddlewareError> {
+        let cutoff = Utc::now() - retention;
+        let mut events = self.events.lock().unwrap();
+        let count = events.len();
+        events.retain(|x| !matches!(x.deleted_at, Some(deleted_at) if deleted_at <= cutoff));
+        Ok((count - events.len()) as u64)
+    }
+
+    async fn update_many(
+        &self,
+        selection: &EventSelection,
+        patch: &EventPatch,
+    ) -> Result<Vec<(EventEntry, EventEntry)>, MiddlewareError> {
+        let ids = selected_ids(self, selection).await?;
+        // The events are changed under one lock, so no one sees the batch half done
+        let mut events = self.events.lock().unwrap();
+        let mut changes = Vec::new();
+        for id in ids {
+            let Some(event) = events
+                .iter_mut()
+                .find(|x| x.unique_id == id && x.deleted_at.is_none())
+            else {
+                continue;
+            };
+            let old = event.clone();
+            let patched = patch.apply(&old, &self.timezone);
+            if !has_changes(&old, &patched) {
+                continue;
+            }
+            *event = EventEntry {
+                version: old.version + 1,
+                ..patched
+            };
+            self.record(update_action(&old, event), Some(&old), event);
+            changes.push((old, event.clone()));
+        }
+        Ok(changes)
+    }
+
+    async fn delete_many(
+        &self,
+        selection: &EventSelection,
+    ) -> Result<Vec<EventEntry>, MiddlewareError> {
+        let ids = selected_ids(self, selection).await?;
+        let mut events = self.events.lock().unwrap();
+        let mut deleted = Vec::new();
+        for id in ids {
+            let Some(event) = events
+                .iter_mut()
+                .find(|x| x.unique_id == id && x.deleted_at.is_none())
+            else {
+                continue;
+            };
+            deleted.push(event.clone());
+            event.deleted_at = Some(Utc::now());
+            self.record(RevisionAction::Delete, Some(event), event);
+        }
+        Ok(deleted)
+    }
+
+    async fn add_project(&self, project: &Project) -> Result<Project, MiddlewareError> {
+        project.check()?;
+        let mut projects = self.projects.lock().unwrap();
+        if projects.iter().any(|x| x.id == project.id) {
+            return Err(MiddlewareError::Conflict { id: project.id });
+        }
+        projects.push(project.clone());
+        Ok(project.clone())
+    }
+
+    async fn update_project(&self, project: &Project) -> Result<Project, MiddlewareError> {
+        project.check()?;
+        let mut projects = self.projects.lock().unwrap();
+        let stored = projects
+            .iter_mut()
+            .find(|x| x.id == project.id)
+            .ok_or(MiddlewareError::NotFound { id: project.id })?;
+        *stored = project.clone();
+        Ok(project.clone())
+    }
+
+    async fn remove_project(&self, project_id: &ObjectId) -> Result<(), MiddlewareError> {
+        let mut projects = self.projects.lock().unwrap();
+        let index = projects
+            .iter()
+            .position(|x| x.id == *project_id)
+            .ok_or(MiddlewareError::NotFound { id: *project_id })?;
+        projects.remove(index);
+        Ok(())
+    }
+
+    async fn get_projects(&self) -> Result<Vec<Project>, MiddlewareError> {
+        let mut projects = self.projects.lock().unwrap().clone();
+        sort_projects(&mut projects);
+        Ok(projects)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample_entry;
+    use crate::{parse_tags, EventPatch, EventSelection, Frequency, Recurrence};
+    use chrono::TimeZone;
+    use mongodb::bson::oid::ObjectId;
+
+    #[test]
+    fn test_add_update_delete() {
+        let store = MemoryStore::new();
+        let mut entry = sample_entry("KrabbyDo memory store");
+        let rt = tokio::runtime::Runtime::new().unwrap();
+
+        rt.block_on(async { store.add_event(&entry).await })
+            .unwrap();
+        entry.title = String::from("Updated title");
+        entry = rt
+            .block_on(async { store.update_task(&entry).await })
+            .unwrap();
+        assert_eq!(entry.version, 1);
+        let tasks = rt.block_on(async { store.get_all_tasks().await }).unwrap();
+        assert_eq!(tasks, vec![entry.clone()]);
+
+        rt.block_on(async { store.delete_event(&entry).await })
+            .unwrap();
+        let tasks = rt.block_on(async { store.get_all_tasks().await }).unwrap();
+        assert!(tasks.is_empty());
+
+        // The event is in the trash, so it can neither be updated nor deleted again
+        let result = rt.block_on(async { store.update_task(&entry).await });
+        assert!(matches!(result, Err(MiddlewareError::NotFound { .. })));
+        let result = rt.block_on(async { store.delete_event(&entry).await });
+        assert!(matches!(result, Err(MiddlewareError::NotFound { .. })));
+    }
+
+    #[test]
+    fn test_history_and_revert() {
+        let store = MemoryStore::new().with_actor("krabby");
+        let mut entry = sample_entry("First title");
+        let rt = tokio::runtime::Runtime::new().unwrap();
+
+        rt.block_on(async { store.add_event(&entry).await })
+            .unwrap();
+        let first = entry.clone();
+        entry.title = String::from("Second title");
+        entry = rt
+            .block_on(async { store.update_task(&entry).await })
+            .unwrap();
+        entry.is_done = true;
+        entry = rt
+            .block_on(async { store.update_task(&entry).await })
+            .unwrap();
+
+        let history = rt
+            .block_on(async { store.get_history(&entry.unique_id).await })
+            .unwrap();
+        let actions: Vec<RevisionAction> = history.iter().map(|x| x.action).collect();
+        assert_eq!(
+            actions,
+            vec![
+                RevisionAction::Create,
+                RevisionAction::Update,
+                RevisionAction::Complete
+            ]
+        );
+        assert!(history.iter().all(|x| x.actor == "krabby"));
+
+        let reverted = rt
+            .block_on(async {
+                store
+                    .revert_event(&entry.unique_id, &history[0].revision_id)
+                    .await
+            })
+            .unwrap();
+        // Reverting is an update of its own, so the version keeps counting
+        let first = EventEntry {
+            version: 3,
+            ..first
+        };
+        assert_eq!(reverted, first);
+        let tasks = rt.block_on(async { store.get_all_tasks().await }).unwrap();
+        assert_eq!(tasks, vec![first]);
+    }
+
+    #[test]
+    fn test_update_conflict() {
+        let store = MemoryStore::new();
+        let entry = sample_entry("Loaded twice");
+        let rt = tokio::runtime::Runtime::new().unwrap();
+
+        rt.block_on(async { store.add_event(&entry).await })
+            .unwrap();
+        let mut mine = entry.clone();
+        mine.title = String::from("Mine");
+        let mut theirs = entry.clone();
+        theirs.is_done = true;
+        rt.block_on(async { store.update_task(&theirs).await })
+            .unwrap();
+
+        let result = rt.block_on(async { store.update_task(&mine).await });
+        assert!(matches!(result, Err(MiddlewareError::Conflict { .. })));
+        let tasks = rt.block_on(async { store.get_all_tasks().await }).unwrap();
+        assert_eq!(tasks[0].title, "Loaded twice");
+        assert!(tasks[0].is_done);
+    }
+
+    #[test]
+    fn test_update_many_delete_many() {
+        let store = MemoryStore::new().with_timezone(Tz::UTC);
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let first = sample_entry("First");
+        let second = sample_entry("Second");
+        let mut done = sample_entry("Done");
+        done.is_done = true;
+        for entry in [&first, &second, &done] {
+            rt.block_on(async { store.add_event(entry).await }).unwrap();
+        }
+
+        // The event which is done already isn't changed again
+        let selection = EventSelection::Ids(vec![first.unique_id, done.unique_id, ObjectId::new()]);
+        let patch = EventPatch::new().done(true).shift_days(1);
+        let changes = rt
+            .block_on(async { store.update_many(&selection, &patch).await })
+            .unwrap();
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[0].0, first);
+        assert!(changes[0].1.is_done);
+        assert_eq!(
+            changes[0].1.date_time,
+            first.date_time.map(|x| x + Duration::days(1))
+        );
+        assert_eq!(changes[0].1.version, 1);
+
+        let selection = EventSelection::Query(EventQuery::new().done(true));
+        let deleted = rt
+            .block_on(async { store.delete_many(&selection).await })
+            .unwrap();
+        assert_eq!(deleted.len(), 2);
+        let tasks = rt.block_on(async { store.get_all_tasks().await }).unwrap();
+        assert_eq!(tasks, vec![second]);
+    }
+
+    #[test]
+    fn test_recurring_events() {
+        let store = MemoryStore::new().with_timezone(Tz::UTC);
+        let start = Utc.with_ymd_and_hms(2023, 6, 9, 17, 0, 0).unwrap();
+        let standup = EventEntry {
+            recurrence: Some(Recurrence::new(Frequency::Weekly).interval(2)),
+            date_time: Some(start),
+            ..sample_entry("Standup")
+        };
+        let review = EventEntry {
+            date_time: Some(start + Duration::days(3)),
+            ..sample_entry("Review")
+        };
+        let undated = EventEntry {
+            date_time: None,
+            ..sample_entry("Someday")
+        };
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        for entry in [&standup, &review, &undated] {
+            rt.block_on(async { store.add_event(entry).await }).unwrap();
+        }
+
+        let occurrences = rt
+            .block_on(async {
+                store
+                    .expand_occurrences(start, start + Duration::weeks(5))
+                    .await
+            })
+            .unwrap();
+        let listed: Vec<(&str, i64)> = occurrences
+            .iter()
+            .map(|x| (x.entry.title.as_str(), (x.date_time - start).num_days()))
+            .collect();
+        assert_eq!(
+            listed,
+            vec![
+                ("Standup", 0),
+                ("Review", 3),
+                ("Standup", 14),
+                ("Standup", 28)
+            ]
+        );
+
+        // Completing the standup moves it on two weeks and keeps it not done
+        let next = rt
+            .block_on(async { store.complete_event(&standup).await })
+            .unwrap();
+        assert!(!next.is_done);
+        assert_eq!(next.date_time, Some(start + Duration::weeks(2)));
+        let done = rt
+            .block_on(async { store.complete_event(&review).await })
+            .unwrap();
+        assert!(done.is_done);
+        let result = rt.block_on(async { store.complete_event(&standup).await });
+        assert!(matches!(result, Err(MiddlewareError::Conflict { .. })));
+    }
+
+    #[test]
+    fn test_subtasks() {
+        let store = MemoryStore::new();
+        let entry = EventEntry {
+            auto_complete: true,
+            ..sample_entry("Prepare release")
+        };
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let mut entry = rt
+            .block_on(async { store.add_event(&entry).await })
+            .unwrap();
+
+        for title in ["Tag the commit", "Write the changelog"] {
+            entry = rt
+                .block_on(async { store.add_subtask(&entry, title).await })
+                .unwrap();
+        }
+        let (tag, changelog) = (entry.subtasks[0].id, entry.subtasks[1].id);
+        entry = rt
+            .block_on(async { store.move_subtask(&entry, &changelog, 0).await })
+            .unwrap();
+        assert_eq!(entry.subtasks[0].title, "Write the changelog");
+        let stale = entry.clone();
+        for id in [tag, changelog] {
+            entry = rt
+                .block_on(async { store.toggle_subtask(&entry, &id).await })
+                .unwrap();
+        }
+        // Checking the last step completed the event
+        assert!(entry.is_done);
+        let result = rt.block_on(async { store.remove_subtask(&stale, &tag).await });
+        assert!(matches!(result, Err(MiddlewareError::Conflict { .. })));
+        entry = rt
+            .block_on(async { store.remove_subtask(&entry, &tag).await })
+            .unwrap();
+        assert_eq!(entry.subtask_progress(), (1, 1));
+
+        // Reverting to the move brings the removed step back; the kept one keeps its id
+        let history = rt
+            .block_on(async { store.get_history(&entry.unique_id).await })
+            .unwrap();
+        let reverted = rt
+            .block_on(async {
+                store
+                    .revert_event(&entry.unique_id, &history[3].revision_id)
+                    .await
+            })
+            .unwrap();
+        let titles: Vec<&str> = reverted.subtasks.iter().map(|x| x.title.as_str()).collect();
+        assert_eq!(titles, vec!["Write the changelog", "Tag the commit"]);
+        assert_eq!(reverted.subtasks[0].id, changelog);
+        assert_eq!(reverted.subtask_progress(), (0, 2));
+        assert!(!reverted.is_done);
+    }
+
+    #[test]
+    fn test_blockers() {
+        let store = MemoryStore::new();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let mut entries = Vec::new();
+        for title in ["Tag the commit", "Announce it", "Plan the next one"] {
+            let entry = sample_entry(title);
+            entries.push(rt.block_on(store.add_event(&entry)).unwrap());
+        }
+        let (tag, announce) = (entries[0].clone(), entries[1].clone());
+
+        let announce = rt
+            .block_on(store.add_blocker(&announce, &tag.unique_id))
+            .unwrap();
+        assert_eq!(announce.blocked_by, vec![tag.unique_id]);
+        let result = rt.block_on(store.add_blocker(&tag, &announce.unique_id));
+        assert!(matches!(result, Err(MiddlewareError::Validation(_))));
+        let result = rt.block_on(store.add_blocker(&tag, &ObjectId::new()));
+        assert!(matches!(result, Err(MiddlewareError::Validation(_))));
+
+        // Updating or adding an event with bad blockers is refused the same way
+        let cycle = EventEntry {
+            blocked_by: vec![announce.unique_id],
+            ..tag.clone()
+        };
+        let result = rt.block_on(store.update_task(&cycle));
+        assert!(matches!(result, Err(MiddlewareError::Validation(_))));
+        let itself = EventEntry {
+            blocked_by: vec![tag.unique_id],
+            ..tag.clone()
+        };
+        let result = rt.block_on(store.update_task(&itself));
+        assert!(matches!(result, Err(MiddlewareError::Validation(_))));
+        let unknown = EventEntry {
+            blocked_by: vec![ObjectId::new()],
+            ..sample_entry("Write the notes")
+        };
+        let result = rt.block_on(store.add_event(&unknown));
+        assert!(matches!(result, Err(MiddlewareError::Validation(_))));
+
+        let ids = |events: Vec<EventEntry>| -> Vec<String> {
+            events.into_iter().map(|x| x.title).collect()
+        };
+        let ready = rt.block_on(store.get_ready_events()).unwrap();
+        assert_eq!(ids(ready), vec!["Tag the commit", "Plan the next one"]);
+        let blocked = rt.block_on(store.get_blocked_events()).unwrap();
+        assert_eq!(ids(blocked), vec!["Announce it"]);
+
+        // Once the tag is done the announcement is ready
+        rt.block_on(store.complete_event(&tag)).unwrap();
+        let ready = rt.block_on(store.get_ready_events()).unwrap();
+        assert_eq!(ids(ready), vec!["Announce it", "Plan the next one"]);
+        let announce = rt
+            .block_on(store.remove_blocker(&announce, &tag.unique_id))
+            .unwrap();
+        assert!(announce.blocked_by.is_empty());
+
+        // A stored blocker which was deleted since doesn't keep the event from being updated
+        let plan = rt
+            .block_on(store.add_blocker(&entries[2], &announce.unique_id))
+            .unwrap();
+        rt.block_on(store.delete_event(&announce)).unwrap();
+        let plan = EventEntry {
+            title: String::from("Plan the next release"),
+            ..plan
+        };
+        assert!(rt.block_on(store.update_task(&plan)).is_ok());
+    }
+
+    #[test]
+    fn test_get_tags() {
+        let store = MemoryStore::new();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        for (title, tags) in [
+            ("Report", "Work"),
+            ("Groceries", "home, errands"),
+            ("Standup", "work home"),
+        ] {
+            let entry = EventEntry {
+                tags: parse_tags(tags),
+                ..sample_entry(title)
+            };
+            rt.block_on(store.add_event(&entry)).unwrap();
+        }
+        let counts = |store: &MemoryStore| -> Vec<(String, usize)> {
+            rt.block_on(store.get_tags())
+                .unwrap()
+                .into_iter()
+                .map(|x| (x.tag, x.count))
+                .collect()
+        };
+        assert_eq!(
+            counts(&store),
+            vec![
+                (String::from("home"), 2),
+                (String::from("work"), 2),
+                (String::from("errands"), 1)
+            ]
+        );
+
+        // Events in the trash don't count
+        let groceries = rt
+            .block_on(store.query_events(&EventQuery::new().tag("errands")))
+            .unwrap();
+        rt.block_on(store.delete_event(&groceries[0])).unwrap();
+        assert_eq!(
+            counts(&store),
+            vec![(String::from("work"), 2), (String::from("home"), 1)]
+        );
+    }
+
+    #[test]
+    fn test_projects() {
+        let store = MemoryStore::new();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let mut home = Project::new("Home");
+        let work = Project {
+            position: 1,
+            ..Project::new("Work")
+        };
+        for project in [&home, &work] {
+            rt.block_on(store.add_project(project)).unwrap();
+        }
+        assert!(matches!(
+            rt.block_on(store.add_project(&Project::new(" "))),
+            Err(MiddlewareError::Validation(_))
+        ));
+        home.archived = true;
+        rt.block_on(store.update_project(&home)).unwrap();
+
+        let report = EventEntry {
+            project_id: Some(work.id),
+            ..sample_entry("Report")
+        };
+        for entry in [&report, &sample_entry("Groceries")] {
+            rt.block_on(store.add_event(entry)).unwrap();
+        }
+        let titles = |query: EventQuery| -> Vec<String> {
+            rt.block_on(store.query_events(&query))
+                .unwrap()
+                .into_iter()
+                .map(|x| x.title)
+                .collect()
+        };
+        assert_eq!(
+            titles(EventQuery::new().in_project(work.id)),
+            vec!["Report"]
+        );
+        assert_eq!(
+            titles(EventQuery::new().without_project()),
+            vec!["Groceries"]
+        );
+
+        let names = |projects: Vec<Project>| -> Vec<String> {
+            projects.into_iter().map(|x| x.name).collect()
+        };
+        let moved = rt.block_on(store.move_project(&work.id, 0)).unwrap();
+        assert_eq!(names(moved), vec!["Work", "Home"]);
+        let projects = rt.block_on(store.get_projects()).unwrap();
+        assert!(projects[1].archived);
+        assert_eq!(names(projects), vec!["Work", "Home"]);
+
+        // Deleting the project moves its events to the Inbox
+        rt.block_on(store.delete_project(&work.id)).unwrap();
+        assert_eq!(
+            names(rt.block_on(store.get_projects()).unwrap()),
+            vec!["Home"]
+        );
+        assert_eq!(
+            titles(EventQuery::new().without_project()),
+            vec!["Report", "Groceries"]
+        );
+        assert!(matches!(
+            rt.block_on(store.remove_project(&work.id)),
+            Err(MiddlewareError::NotFound { .. })
+        ));
+    }
+
+    #[test]
+    fn test_trash_restore_purge() {
+        let store = MemoryStore::new();
+        let first = sample_entry("First");
+        let second = sample_entry("Second");
+        let rt = tokio::runtime::Runtime::new().unwrap();
+
+        for entry in [&first, &second] {
+            rt.block_on(async { store.add_event(entry).await }).unwrap();
+            rt.block_on(async { store.delete_event(entry).await })
+                .unwrap();
+        }
+        let trash = rt.block_on(async { store.get_trash().await }).unwrap();
+        assert_eq!(trash.len(), 2);
+        assert!(trash.iter().all(|x| x.deleted_at.is_some()));
+
+        let restored = rt
+            .block_on(async { store.restore_event(&first).await })
+            .unwrap();
+        assert_eq!(restored, first);
+        let tasks = rt.block_on(async { store.get_all_tasks().await }).unwrap();
+        assert_eq!(tasks, vec![first.clone()]);
+
+        // Nothing was deleted a day ago, so a one day retention keeps the trash
+        let purged = rt
+            .block_on(async { store.purge_trash(Duration::days(1)).await })
+            .unwrap();
+        assert_eq!(purged, 0);
+        let purged = rt
+            .block_on(async { store.purge_trash(Duration::zero()).await })
+            .unwrap();
+        assert_eq!(purged, 1);
+        assert!(rt
+            .block_on(async { store.get_trash().await })
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_search_events() {
+        let mut details = sample_entry("Groceries");
+        details.details = Some(String::from("Buy coffee before the planning meeting"));
+        let title = sample_entry("Weekly meetings");
+        let store = MemoryStore::with_events(vec![details, title]);
+        let rt = tokio::runtime::Runtime::new().unwrap();
+
+        let hits = rt
+            .block_on(async { store.search_events("Meeting plans").await })
+            .unwrap();
+        let titles: Vec<&str> = hits.iter().map(|x| x.entry.title.as_str()).collect();
+        assert_eq!(titles, vec!["Weekly meetings", "Groceries"]);
+    }
+
+    #[test]
+    fn test_get_today_events() {
+        let today = sample_entry("Today");
+        let tomorrow = EventEntry {
+            date_time: Some(Utc::now() + chrono::Duration::days(1)),
+            ..sample_entry("Tomorrow")
+        };
+        let store = MemoryStore::with_events(vec![today.clone(), tomorrow]);
+        let rt = tokio::runtime::Runtime::new().unwrap();
+
+        let tasks = rt
+            .block_on(async { store.get_today_events().await })
+            .unwrap();
+        assert_eq!(tasks, vec![today]);
+    }
+}
@@ -0,0 +1,297 @@
+//! Versioned schema migrations of the stored events.
+//! The schema version reached so far is recorded next to the data (in the metadata collection on MongoDB),
+//! and the upgrade steps above it run in order when a store is opened.
+//! Every step is idempotent, so running one again is harmless.
+
+use crate::mongo::{parse_legacy_date, to_bson_date};
+use crate::tags::parse_tags;
+use crate::MiddlewareError;
+use async_trait::async_trait;
+use mongodb::bson::{doc, oid::ObjectId, Bson, Document};
+use mongodb::options::{FindOptions, UpdateOptions};
+use mongodb::Collection;
+use tokio_stream::StreamExt as TokioStreamExt;
+
+/// Key of the document holding the schema version in the metadata collection
+const SCHEMA_VERSION_ID: &str = "schema_version";
+
+/// MigrationReport describes which steps ran, or would run on a dry run
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct MigrationReport {
+    /// Set when nothing was changed and the report only lists what would be done
+    pub dry_run: bool,
+    /// Schema version found before migrating
+    pub from_version: u32,
+    /// Schema version reached, or which would be reached on a dry run
+    pub to_version: u32,
+    /// One entry per pending step, in the order they run
+    pub steps: Vec<StepReport>,
+}
+
+/// StepReport describes one upgrade step
+#[derive(Debug, Clone, PartialEq)]
+pub struct StepReport {
+    /// Schema version the step upgrades to
+    pub version: u32,
+    /// What the step does
+    pub description: String,
+    /// Events the step changes; empty for steps which only change the schema
+    pub documents: Vec<ObjectId>,
+}
+
+/// One upgrade step of the todos collection on MongoDB
+#[async_trait]
+trait MongoMigration: Send + Sync {
+    /// Schema version the step upgrades to
+    fn version(&self) -> u32;
+
+    /// What the step does
+    fn description(&self) -> &'static str;
+
+    /// Matches the documents the step would change
+    fn pending_filter(&self) -> Document;
+
+    /// Applies the step to the collection
+    async fn apply(&self, collection: &Collection<Document>) -> Result<(), MiddlewareError>;
+}
+
+/// Every MongoDB step, ordered by version
+fn mongo_migrations() -> Vec<Box<dyn MongoMigration>> {
+    vec![
+        Box::new(StringDatesToBson),
+        Box::new(FillMissingFields),
+        Box::new(AddVersion),
+        Box::new(SplitTags),
+    ]
+}
+
+/// Version 1: date_time used to be written as an RFC 3339 string
+struct StringDatesToBson;
+
+#[async_trait]
+impl MongoMigration for StringDatesToBson {
+    fn version(&self) -> u32 {
+        1
+    }
+
+    fn description(&self) -> &'static str {
+        "Convert date_time strings into BSON DateTime"
+    }
+
+    fn pending_filter(&self) -> Document {
+        doc! { "date_time": { "$type": "string" } }
+    }
+
+    async fn apply(&self, collection: &Collection<Document>) -> Result<(), MiddlewareError> {
+        let mut cursor = collection.find(self.pending_filter(), None).await?;
+        while let Some(document) = TokioStreamExt::try_next(&mut cursor).await? {
+            let unique_id = document
+                .get_object_id("_id")
+                .map_err(|_| MiddlewareError::decode("_id", None))?;
+            let date_time_str = document
+                .get_str("date_time")
+                .map_err(|_| MiddlewareError::decode("date_time", Some(&unique_id)))?;
+            // Dates which can't be parsed are left untouched and keep showing up in the report
+            if let Some(date_time) = parse_legacy_date(date_time_str) {
+                // Only convert the value if it is still the string we read
+                let filter = doc! { "_id": unique_id, "date_time": date_time_str };
+                let update = doc! { "$set": { "date_time": to_bson_date(&date_time) } };
+                collection.update_one(filter, update, None).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Version 2: documents written by hand or by older versions may miss some fields
+struct FillMissingFields;
+
+impl FillMissingFields {
+    /// Fields which get a default value when they are missing
+    fn defaults() -> Document {
+        doc! { "title": "", "details": "", "is_done": false, "tags": "" }
+    }
+}
+
+#[async_trait]
+impl MongoMigration for FillMissingFields {
+    fn version(&self) -> u32 {
+        2
+    }
+
+    fn description(&self) -> &'static str {
+        "Add missing title, details, is_done and tags fields"
+    }
+
+    fn pending_filter(&self) -> Document {
+        let missing: Vec<Document> = Self::defaults()
+            .keys()
+            .map(|field| doc! { field: { "$exists": false } })
+            .collect();
+        doc! { "$or": missing }
+    }
+
+    async fn apply(&self, collection: &Collection<Document>) -> Result<(), MiddlewareError> {
+        for (field, default) in Self::defaults() {
+            let filter = doc! { &field: { "$exists": false } };
+            let update = doc! { "$set": { &field: default } };
+            collection.update_many(filter, update, None).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Version 3: updates are checked against the version of the event, which starts at 0
+struct AddVersion;
+
+#[async_trait]
+impl MongoMigration for AddVersion {
+    fn version(&self) -> u32 {
+        3
+    }
+
+    fn description(&self) -> &'static str {
+        "Add the version field"
+    }
+
+    fn pending_filter(&self) -> Document {
+        doc! { "version": { "$exists": false } }
+    }
+
+    async fn apply(&self, collection: &Collection<Document>) -> Result<(), MiddlewareError> {
+        let update = doc! { "$set": { "version": 0i64 } };
+        collection
+            .update_many(self.pending_filter(), update, None)
+            .await?;
+        Ok(())
+    }
+}
+
+/// Version 4: tags used to be one free-text string, like "Work, home"
+struct SplitTags;
+
+#[async_trait]
+impl MongoMigration for SplitTags {
+    fn version(&self) -> u32 {
+        4
+    }
+
+    fn description(&self) -> &'static str {
+        "Split the tags strings into arrays of normalized tags"
+    }
+
+    fn pending_filter(&self) -> Document {
+        // Strings, nulls and missing fields; $type matches the elements of arrays too, so the
+        // arrays are left out as a whole
+        doc! { "tags": { "$not": { "$type": "array" } } }
+    }
+
+    async fn apply(&self, collection: &Collection<Document>) -> Result<(), MiddlewareError> {
+        let mut cursor = collection.find(self.pending_filter(), None).await?;
+        while let Some(document) = TokioStreamExt::try_next(&mut cursor).await? {
+            let unique_id = document
+                .get_object_id("_id")
+                .map_err(|_| MiddlewareError::decode("_id", None))?;
+            // Null also matches a missing field
+            let tags = document.get("tags").cloned().unwrap_or(Bson::Null);
+            let split = parse_tags(tags.as_str().unwrap_or_default());
+            // Only convert the value if it is still the one we read
+            let filter = doc! { "_id": unique_id, "tags": tags };
+            let update = doc! { "$set": { "tags": split } };
+            collection.update_one(filter, update, None).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs the MongoDB steps above the version recorded in the metadata collection;
+/// on a dry run nothing is changed and the report lists the documents each step would change
+pub(crate) async fn run_mongo_migrations(
+    collection: &Collection<Document>,
+    metadata: &Collection<Document>,
+    dry_run: bool,
+) -> Result<MigrationReport, MiddlewareError> {
+    let from_version = match metadata
+        .find_one(doc! { "_id": SCHEMA_VERSION_ID }, None)
+        .await?
+    {
+        Some(document) => document.get_i64("version").map_err(|_| {
+            MiddlewareError::Database(format!("Invalid {} document", SCHEMA_VERSION_ID).into())
+        })? as u32,
+        None => 0,
+    };
+    let mut report = MigrationReport {
+        dry_run,
+        from_version,
+        to_version: from_version,
+        steps: Vec::new(),
+    };
+
+    for migration in mongo_migrations() {
+        if migration.version() <= from_version {
+            continue;
+        }
+
+        // Documents which the step is about to change
+        let options = FindOptions::builder().projection(doc! { "_id": 1 }).build();
+        let mut cursor = collection.find(migration.pending_filter(), options).await?;
+        let mut documents = Vec::new();
+        while let Some(document) = TokioStreamExt::try_next(&mut cursor).await? {
+            documents.push(
+                document
+                    .get_object_id("_id")
+                    .map_err(|_| MiddlewareError::decode("_id", None))?,
+            );
+        }
+
+        if !dry_run {
+            migration.apply(collection).await?;
+            let options = UpdateOptions::builder().upsert(true).build();
+            metadata
+                .update_one(
+                    doc! { "_id": SCHEMA_VERSION_ID },
+                    doc! { "$set": { "version": migration.version() as i64 } },
+                    options,
+                )
+                .await?;
+        }
+
+        #[cfg(feature = "print_debug_log")]
+        println!(
+            "Migration {} ({}): {} documents",
+            migration.version(),
+            migration.description(),
+            documents.len()
+        );
+
+        report.to_version = migration.version();
+        report.steps.push(StepReport {
+            version: migration.version(),
+            description: migration.description().to_string(),
+            documents,
+        });
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mongo_migrations_are_ordered() {
+        let versions: Vec<u32> = mongo_migrations().iter().map(|x| x.version()).collect();
+        assert_eq!(versions, (1..=versions.len() as u32).collect::<Vec<u32>>());
+    }
+
+    #[test]
+    fn test_fill_missing_fields_filter() {
+        let filter = FillMissingFields.pending_filter();
+        let missing = filter.get_array("$or").unwrap();
+        assert_eq!(missing.len(), FillMissingFields::defaults().len());
+        assert_eq!(
+            missing[0].as_document().unwrap(),
+            &doc! { "title": { "$exists": false } }
+        );
+    }
+}
@@ -0,0 +1,1259 @@
+//! MongoDB implementation of the EventStore.
+//! It Will connect to mongo database which we operated throuht monngo DB compass application.
+
+use crate::bulk::{delete_each, selected_ids, update_each};
+use crate::dependencies::check_new_blockers;
+use crate::history::{
+    default_actor, has_changes, update_action, FieldChange, Revision, RevisionAction,
+};
+use crate::migrations::run_mongo_migrations;
+use crate::projects::sort_projects;
+use crate::search::{search_terms, DETAILS_WEIGHT, TAGS_WEIGHT, TITLE_WEIGHT};
+use crate::tags::parse_tags;
+use crate::timezone::{day_bounds, local_timezone, today};
+use crate::{
+    EventChange, EventChangeStream, EventEntry, EventPatch, EventQuery, EventSelection, EventStore,
+    IndexReport, MiddlewareError, MigrationReport, MongoConfig, Priority, Project, SearchHit,
+    SortKey, Subtask, Tz, UrgencyConfig,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use mongodb::bson::{bson, doc, oid::ObjectId, Bson, Document};
+use mongodb::change_stream::event::{ChangeStreamEvent, OperationType};
+use mongodb::error::{TRANSIENT_TRANSACTION_ERROR, UNKNOWN_TRANSACTION_COMMIT_RESULT};
+use mongodb::options::{
+    ChangeStreamOptions, ClientOptions, Credential, FindOneAndUpdateOptions, FindOptions,
+    FullDocumentType, IndexOptions, ReturnDocument, Tls, TlsOptions,
+};
+use mongodb::{Client, ClientSession, Collection, IndexModel};
+use std::time::Duration;
+use tokio_stream::StreamExt as TokioStreamExt;
+
+/// How many times a bulk operation runs its transaction before giving up on transient errors
+const TRANSACTION_ATTEMPTS: u32 = 3;
+
+/// MongoStore keeps the events in the database and collection named by its MongoConfig.
+/// It holds one Client, so every operation reuses the same connection pool.
+#[derive(Debug, Clone)]
+pub struct MongoStore {
+    client: Client,
+    collection: Collection<Document>,
+    /// Holds the schema version of the collection of events
+    metadata: Collection<Document>,
+    /// Holds the revision history of the events
+    revisions: Collection<Document>,
+    /// Holds the projects the events are grouped in
+    projects: Collection<Document>,
+    /// Recorded as the author of every revision
+    actor: String,
+    /// Zone in which days start and end
+    timezone: Tz,
+    /// Coefficients of the urgency score
+    urgency: UrgencyConfig,
+}
+
+impl MongoStore {
+    /// This function creates the client and the handle to the collection of events
+    pub async fn connect(config: &MongoConfig) -> Result<Self, MiddlewareError> {
+        let client = create_mongodb_client(config).await?;
+        let database = client.database(&config.database);
+        Ok(MongoStore {
+            collection: database.collection(&config.collection),
+            metadata: database.collection(&config.metadata_collection),
+            revisions: database.collection(&config.revisions_collection),
+            projects: database.collection(&config.projects_collection),
+            actor: default_actor(),
+            timezone: local_timezone(),
+            urgency: UrgencyConfig::default(),
+            client,
+        })
+    }
+
+    /// Sets the name recorded as the author of the revisions
+    pub fn with_actor(mut self, actor: &str) -> Self {
+        self.actor = actor.to_string();
+        self
+    }
+
+    /// Sets the zone in which days start and end
+    pub fn with_timezone(mut self, timezone: Tz) -> Self {
+        self.timezone = timezone;
+        self
+    }
+
+    /// Sets the coefficients of the urgency score
+    pub fn with_urgency(mut self, urgency: UrgencyConfig) -> Self {
+        self.urgency = urgency;
+        self
+    }
+
+    /// This function adds a revision to the history
+    async fn record(
+        &self,
+        action: RevisionAction,
+        old: Option<&EventEntry>,
+        new: &EventEntry,
+    ) -> Result<(), MiddlewareError> {
+        let revision = Revision::new(action, old, new, &self.actor);
+        self.revisions
+            .insert_one(revision_to_document(&revision), None)
+            .await?;
+        Ok(())
+    }
+
+    /// Applies the patch to the events with the given ids within the transaction of the session,
+    /// and records the changes in the history
+    async fn patch_in_session(
+        &self,
+        ids: &[ObjectId],
+        patch: &EventPatch,
+        session: &mut ClientSession,
+    ) -> Result<Vec<(EventEntry, EventEntry)>, MiddlewareError> {
+        let mut changes = Vec::new();
+        let mut revisions = Vec::new();
+        for before in self.find_in_session(ids, session).await? {
+            let after = patch.apply(&before, &self.timezone);
+            if !has_changes(&before, &after) {
+                continue;
+            }
+            let update = doc! { "$set": event_fields(&after), "$inc": { "version": 1i64 } };
+            self.collection
+                .update_one_with_session(doc! { "_id": before.unique_id }, update, None, session)
+                .await?;
+            let new = EventEntry {
+                version: before.version + 1,
+                ..as_stored(&after)
+            };
+            let revision = Revision::new(
+                update_action(&before, &new),
+                Some(&before),
+                &new,
+                &self.actor,
+            );
+            revisions.push(revision_to_document(&revision));
+            changes.push((before, new));
+        }
+        if !revisions.is_empty() {
+            self.revisions
+                .insert_many_with_session(revisions, None, session)
+                .await?;
+        }
+        Ok(changes)
+    }
+
+    /// Moves the events with the given ids to the trash within the transaction of the session,
+    /// and records the deletes in the history
+    async fn delete_in_session(
+        &self,
+        ids: &[ObjectId],
+        session: &mut ClientSession,
+    ) -> Result<Vec<EventEntry>, MiddlewareError> {
+        let deleted = self.find_in_session(ids, session).await?;
+        if deleted.is_empty() {
+            return Ok(deleted);
+        }
+        let found: Vec<ObjectId> = deleted.iter().map(|x| x.unique_id).collect();
+        self.collection
+            .update_many_with_session(
+                doc! { "_id": { "$in": found } },
+                doc! { "$set": { "deleted_at": to_bson_date(&Utc::now()) } },
+                None,
+                session,
+            )
+            .await?;
+        let revisions = deleted.iter().map(|entry| {
+            revision_to_document(&Revision::new(
+                RevisionAction::Delete,
+                Some(entry),
+                entry,
+                &self.actor,
+            ))
+        });
+        self.revisions
+            .insert_many_with_session(revisions, None, session)
+            .await?;
+        Ok(deleted)
+    }
+
+    /// Loads the events with the given ids which aren't in the trash, in the order of the ids
+    async fn find_in_session(
+        &self,
+        ids: &[ObjectId],
+        session: &mut ClientSession,
+    ) -> Result<Vec<EventEntry>, MiddlewareError> {
+        let filter = doc! { "_id": { "$in": ids.to_vec() }, "deleted_at": null };
+        let mut cursor = self
+            .collection
+            .find_with_session(filter, None, session)
+            .await?;
+        let mut events = Vec::new();
+        while let Some(document) = cursor.next(session).await.transpose()? {
+            events.push(document_to_event(&document)?);
+        }
+        events.sort_by_key(|x| ids.iter().position(|id| *id == x.unique_id));
+        Ok(events)
+    }
+
+    /// The client shared by all operations of the store
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+}
+
+#[async_trait]
+impl EventStore for MongoStore {
+    /// This function adds an event to the database
+    async fn add_event(&self, entry: &EventEntry) -> Result<EventEntry, MiddlewareError> {
+        #[cfg(feature = "print_debug_log")]
+        println!("Event added to MongoDB");
+
+        if !entry.blocked_by.is_empty() {
+            check_new_blockers(entry, &[], &self.get_all_tasks().await?)?;
+        }
+
+        // Create a document representing the ToDo task
+        let document = doc! {
+            "_id": entry.unique_id,
+            "title": entry.title.clone(),
+            "details": entry.details.clone(),
+            "date_time": entry.date_time.as_ref().map(to_bson_date),
+            "is_done": entry.is_done,
+            "tags": entry.tags.clone(),
+            "deleted_at": entry.deleted_at.as_ref().map(to_bson_date),
+            "version": entry.version as i64,
+            "recurrence": entry.recurrence.as_ref().map(ToString::to_string),
+            "subtasks": subtasks_to_bson(&entry.subtasks),
+            "auto_complete": entry.auto_complete,
+            "blocked_by": entry.blocked_by.clone(),
+            "priority": entry.priority.as_str(),
+            "project_id": entry.project_id,
+        };
+
+        // Insert the document into the collection
+        match self.collection.insert_one(&document, None).await {
+            Err(error) if MiddlewareError::is_duplicate_key(&error) => {
+                Err(MiddlewareError::Conflict {
+                    id: entry.unique_id,
+                })
+            }
+            Err(error) => Err(error.into()),
+            Ok(_) => {
+                // The inserted document is what a later read returns, with the date truncated to milliseconds
+                let stored = document_to_event(&document)?;
+                self.record(RevisionAction::Create, None, &stored).await?;
+                Ok(stored)
+            }
+        }
+    }
+    /// This function updates an event to the database
+    async fn update_task(&self, entry: &EventEntry) -> Result<EventEntry, MiddlewareError> {
+        #[cfg(feature = "print_debug_log")]
+        println!("Updating event with unique_id: {}", entry.unique_id);
+
+        // Blockers are checked against the stored events; an event without any can't add a cycle
+        if !entry.blocked_by.is_empty() {
+            let events = self.get_all_tasks().await?;
+            let previous = events
+                .iter()
+                .find(|x| x.unique_id == entry.unique_id)
+                .map_or(&[][..], |x| x.blocked_by.as_slice());
+            check_new_blockers(entry, previous, &events)?;
+        }
+
+        // Create a document representing the ToDo task
+
+        // Only the version the entry was loaded with is updated; documents which predate versions
+        // count as version 0
+        let version = match entry.version {
+            0 => bson!({ "$in": [0i64, Bson::Null] }),
+            version => bson!(version as i64),
+        };
+        let filter = doc! { "_id": entry.unique_id, "deleted_at": null, "version": version };
+        let update = doc! { "$set": event_fields(entry), "$inc": { "version": 1i64 } };
+
+        // Update the document and keep its previous contents for the history
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::Before)
+            .build();
+        let old = match self
+            .collection
+            .find_one_and_update(filter, update, options)
+            .await?
+        {
+            Some(document) => document_to_event(&document)?,
+            // Nothing matched: either the event is gone or someone else updated it first
+            None => {
+                let current = self
+                    .collection
+                    .find_one(doc! { "_id": entry.unique_id, "deleted_at": null }, None)
+                    .await?;
+                return Err(match current {
+                    Some(_) => MiddlewareError::Conflict {
+                        id: entry.unique_id,
+                    },
+                    None => MiddlewareError::NotFound {
+                        id: entry.unique_id,
+                    },
+                });
+            }
+        };
+
+        let new = EventEntry {
+            deleted_at: old.deleted_at,
+            version: old.version + 1,
+            ..as_stored(entry)
+        };
+        if has_changes(&old, &new) {
+            self.record(update_action(&old, &new), Some(&old), &new)
+                .await?;
+        }
+        Ok(new)
+    }
+    /// This function moves the event to the trash
+    async fn delete_event(&self, entry: &EventEntry) -> Result<(), MiddlewareError> {
+        // Define the filter to find the event by its unique_id; "null" also matches a missing field
+        let filter = doc! { "_id": entry.unique_id, "deleted_at": null };
+        let update = doc! { "$set": { "deleted_at": to_bson_date(&Utc::now()) } };
+
+        // Update the document in the collection
+        let result = self.collection.update_one(filter, update, None).await?;
+        if result.matched_count == 0 {
+            return Err(MiddlewareError::NotFound {
+                id: entry.unique_id,
+            });
+        }
+        self.record(RevisionAction::Delete, Some(entry), entry)
+            .await?;
+
+        Ok(())
+    }
+
+    /// This function fetches one event, whether it is in the trash or not
+    async fn get_event(&self, id: &ObjectId) -> Result<EventEntry, MiddlewareError> {
+        match self.collection.find_one(doc! { "_id": id }, None).await? {
+            Some(document) => document_to_event(&document),
+            None => Err(MiddlewareError::NotFound { id: *id }),
+        }
+    }
+
+    /// This function fetches all the events from database to show on UI
+    async fn get_all_tasks(&self) -> Result<Vec<EventEntry>, MiddlewareError> {
+        // Find all documents in the collection
+        let mut cursor = self
+            .collection
+            .find(doc! { "deleted_at": null }, None)
+            .await?;
+
+        // Iterate over the cursor using the `try_next` method
+        let mut tasks = Vec::new();
+
+        while let Some(result) = TokioStreamExt::try_next(&mut cursor).await? {
+            // Add the task to the vector
+            tasks.push(document_to_event(&result)?);
+        }
+        Ok(tasks)
+    }
+
+    fn timezone(&self) -> Tz {
+        self.timezone
+    }
+
+    fn urgency(&self) -> &UrgencyConfig {
+        &self.urgency
+    }
+
+    /// This function fetches only todays events from the database
+    async fn get_today_events(&self) -> Result<Vec<EventEntry>, MiddlewareError> {
+        let (today, tomorrow) = day_bounds(today(&self.timezone), &self.timezone);
+
+        // Filter documents based on the date range from today to tomorrow
+        let filter = doc! {
+            "date_time": {
+                "$gte": to_bson_date(&today),
+                "$lt": to_bson_date(&tomorrow)
+            },
+            "deleted_at": null
+        };
+
+        // Find documents that match the filter
+        let mut cursor = self.collection.find(filter, None).await?;
+
+        let mut tasks = Vec::new();
+
+        while let Some(result) = TokioStreamExt::try_next(&mut cursor).await? {
+            tasks.push(document_to_event(&result)?);
+        }
+        Ok(tasks)
+    }
+
+    /// This function opens a change stream on the todos collection; moving an event to the trash
+    /// is reported as a delete. Change streams need a replica set, so a standalone server returns None.
+    async fn watch(&self) -> Result<Option<EventChangeStream>, MiddlewareError> {
+        let options = ChangeStreamOptions::builder()
+            .full_document(Some(FullDocumentType::UpdateLookup))
+            .build();
+        let change_stream = match self.collection.watch(None, options).await {
+            Ok(change_stream) => change_stream,
+            Err(error) if is_change_stream_unsupported(&error) => return Ok(None),
+            Err(error) => return Err(error.into()),
+        };
+        // The futures_util combinator, tokio_stream has a filter_map of its own
+        let changes = futures_util::StreamExt::filter_map(change_stream, |event| async move {
+            match event {
+                Ok(event) => event_to_change(event).transpose(),
+                Err(error) => Some(Err(error.into())),
+            }
+        });
+        Ok(Some(Box::pin(changes)))
+    }
+
+    /// This function creates the indexes of the todos collection which are missing
+    async fn ensure_indexes(&self) -> Result<IndexReport, MiddlewareError> {
+        let present = match self.collection.list_index_names().await {
+            Ok(names) => names,
+            // The collection doesn't exist until the first event is added
+            Err(error) if is_namespace_not_found(&error) => Vec::new(),
+            Err(error) => return Err(error.into()),
+        };
+
+        let mut report = IndexReport::default();
+        for index in mongo_indexes() {
+            let name = index
+                .options
+                .as_ref()
+                .and_then(|options| options.name.clone())
+                .unwrap_or_default();
+            let created = !present.contains(&name);
+            if created {
+                self.collection.create_index(index, None).await?;
+            }
+            report.add(&name, created);
+        }
+        Ok(report)
+    }
+
+    /// This function searches the text index created by ensure_indexes; MongoDB matches any of the words and scores the hits
+    async fn search_events(&self, text: &str) -> Result<Vec<SearchHit>, MiddlewareError> {
+        let terms = search_terms(text);
+        if terms.is_empty() {
+            return Ok(Vec::new());
+        }
+        let filter = doc! {
+            "$text": { "$search": terms.join(" ") },
+            "deleted_at": null
+        };
+        let options = FindOptions::builder()
+            .projection(doc! { "score": { "$meta": "textScore" } })
+            .sort(doc! { "score": { "$meta": "textScore" }, "date_time": 1 })
+            .build();
+        let mut cursor = self.collection.find(filter, options).await?;
+
+        let mut hits = Vec::new();
+        while let Some(result) = TokioStreamExt::try_next(&mut cursor).await? {
+            hits.push(SearchHit {
+                entry: document_to_event(&result)?,
+                score: result.get_f64("score").unwrap_or_default(),
+            });
+        }
+        Ok(hits)
+    }
+
+    /// This function fetches the events matched by the query
+    async fn query_events(&self, query: &EventQuery) -> Result<Vec<EventEntry>, MiddlewareError> {
+        // The urgency isn't stored, so the matching events are sorted and paged here
+        if query.sort_key == SortKey::Urgency {
+            let events = self.query_events(&query.unordered()).await?;
+            return Ok(query.apply(events, &self.urgency, Utc::now()));
+        }
+        let options = FindOptions::builder()
+            .sort(query.to_mongo_sort())
+            .skip(query.skip)
+            .limit(query.limit.map(|limit| limit as i64))
+            .build();
+        let mut cursor = self
+            .collection
+            .find(query.to_mongo_filter(), options)
+            .await?;
+
+        let mut tasks = Vec::new();
+        while let Some(result) = TokioStreamExt::try_next(&mut cursor).await? {
+            tasks.push(document_to_event(&result)?);
+        }
+        Ok(tasks)
+    }
+
+    /// This function fetches the events in the trash
+    async fn get_trash(&self) -> Result<Vec<EventEntry>, MiddlewareError> {
+        let filter = doc! { "deleted_at": { "$ne": null } };
+        let mut cursor = self.collection.find(filter, None).await?;
+
+        let mut tasks = Vec::new();
+        while let Some(result) = TokioStreamExt::try_next(&mut cursor).await? {
+            tasks.push(document_to_event(&result)?);
+        }
+        Ok(tasks)
+    }
+
+    /// This function takes the event out of the trash
+    async fn restore_event(&self, entry: &EventEntry) -> Result<EventEntry, MiddlewareError> {
+        let filter = doc! { "_id": entry.unique_id, "deleted_at": { "$ne": null } };
+        let update = doc! { "$set": { "deleted_at": null } };
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+
+        match self
+            .collection
+            .find_one_and_update(filter, update, options)
+            .await?
+        {
+            Some(document) => {
+                let restored = document_to_event(&document)?;
+                self.record(RevisionAction::Restore, Some(&restored), &restored)
+                    .await?;
+                Ok(restored)
+            }
+            None => Err(MiddlewareError::NotFound {
+                id: entry.unique_id,
+            }),
+        }
+    }
+
+    /// This function permanently removes one event from the trash
+    async fn purge_event(&self, entry: &EventEntry) -> Result<(), MiddlewareError> {
+        let filter = doc! { "_id": entry.unique_id, "deleted_at": { "$ne": null } };
+        let result = self.collection.delete_one(filter, None).await?;
+        if result.deleted_count == 0 {
+            return Err(MiddlewareError::NotFound {
+                id: entry.unique_id,
+            });
+        }
+        Ok(())
+    }
+
+    /// This function fetches the revision history of the event, oldest first
+    async fn get_history(&self, event_id: &ObjectId) -> Result<Vec<Revision>, MiddlewareError> {
+        let options = FindOptions::builder()
+            .sort(doc! { "timestamp": 1, "_id": 1 })
+            .build();
+        let mut cursor = self
+            .revisions
+            .find(doc! { "event_id": event_id }, options)
+            .await?;
+
+        let mut revisions = Vec::new();
+        while let Some(result) = TokioStreamExt::try_next(&mut cursor).await? {
+            revisions.push(document_to_revision(&result)?);
+        }
+        Ok(revisions)
+    }
+
+    /// This function permanently removes the events deleted longer than retention ago
+    async fn purge_trash(&self, retention: chrono::Duration) -> Result<u64, MiddlewareError> {
+        let cutoff = Utc::now() - retention;
+        let filter = doc! { "deleted_at": { "$lte": to_bson_date(&cutoff) } };
+        let result = self.collection.delete_many(filter, None).await?;
+        Ok(result.deleted_count)
+    }
+
+    /// This function patches the selected events in one transaction, so an error leaves all of
+    /// them as they were. Transactions need a replica set, so on a standalone server the events are
+    /// patched one at a time instead.
+    async fn update_many(
+        &self,
+        selection: &EventSelection,
+        patch: &EventPatch,
+    ) -> Result<Vec<(EventEntry, EventEntry)>, MiddlewareError> {
+        let ids = selected_ids(self, selection).await?;
+        let mut session = self.client.start_session(None).await?;
+        let mut attempt = 1;
+        loop {
+            match session.start_transaction(None).await {
+                Ok(()) => {}
+                Err(error) if is_transaction_unsupported(&error) => {
+                    return update_each(self, selection, patch).await
+                }
+                Err(error) => return Err(error.into()),
+            }
+            let result = self.patch_in_session(&ids, patch, &mut session).await;
+            match finish_transaction(&mut session, result).await {
+                Err(error) if is_transient(&error) && attempt < TRANSACTION_ATTEMPTS => {
+                    attempt += 1
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// This function moves the selected events to the trash in one transaction; like
+    /// update_many it goes one event at a time on a standalone server
+    async fn delete_many(
+        &self,
+        selection: &EventSelection,
+    ) -> Result<Vec<EventEntry>, MiddlewareError> {
+        let ids = selected_ids(self, selection).await?;
+        let mut session = self.client.start_session(None).await?;
+        let mut attempt = 1;
+        loop {
+            match session.start_transaction(None).await {
+                Ok(()) => {}
+                Err(error) if is_transaction_unsupported(&error) => {
+                    return delete_each(self, selection).await
+                }
+                Err(error) => return Err(error.into()),
+            }
+            let result = self.delete_in_session(&ids, &mut session).await;
+            match finish_transaction(&mut session, result).await {
+                Err(error) if is_transient(&error) && attempt < TRANSACTION_ATTEMPTS => {
+                    attempt += 1
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// This function runs the pending schema migrations of the collection
+    async fn migrate(&self, dry_run: bool) -> Result<MigrationReport, MiddlewareError> {
+        run_mongo_migrations(&self.collection, &self.metadata, dry_run).await
+    }
+
+    /// This function adds a project to the projects collection
+    async fn add_project(&self, project: &Project) -> Result<Project, MiddlewareError> {
+        project.check()?;
+        match self
+            .projects
+            .insert_one(project_to_document(project), None)
+            .await
+        {
+            Err(error) if MiddlewareError::is_duplicate_key(&error) => {
+                Err(MiddlewareError::Conflict { id: project.id })
+            }
+            Err(error) => Err(error.into()),
+            Ok(_) => Ok(project.clone()),
+        }
+    }
+
+    /// This function replaces a project in the projects collection
+    async fn update_project(&self, project: &Project) -> Result<Project, MiddlewareError> {
+        project.check()?;
+        let result = self
+            .projects
+            .replace_one(
+                doc! { "_id": project.id },
+                project_to_document(project),
+                None,
+            )
+            .await?;
+        if result.matched_count == 0 {
+            return Err(MiddlewareError::NotFound { id: project.id });
+        }
+        Ok(project.clone())
+    }
+
+    /// This function removes a project from the projects collection
+    async fn remove_project(&self, project_id: &ObjectId) -> Result<(), MiddlewareError> {
+        let result = self
+            .projects
+            .delete_one(doc! { "_id": project_id }, None)
+            .await?;
+        if result.deleted_count == 0 {
+            return Err(MiddlewareError::NotFound { id: *project_id });
+        }
+        Ok(())
+    }
+
+    /// This function fetches every project in the order of their position
+    async fn get_projects(&self) -> Result<Vec<Project>, MiddlewareError> {
+        let mut cursor = self.projects.find(None, None).await?;
+        let mut projects = Vec::new();
+        while let Some(result) = TokioStreamExt::try_next(&mut cursor).await? {
+            projects.push(document_to_project(&result)?);
+        }
+        sort_projects(&mut projects);
+        Ok(projects)
+    }
+}
+
+/// Indexes of the todos collection: date for the day and range queries, done state and date for
+/// the event lists, tags, project, and the text index searched by search_events, whose words are stemmed as
+/// English and weighted like the other stores weight them
+fn mongo_indexes() -> Vec<IndexModel> {
+    let index = |name: &str, keys: Document| {
+        IndexModel::builder()
+            .keys(keys)
+            .options(IndexOptions::builder().name(name.to_string()).build())
+            .build()
+    };
+    let text_options = IndexOptions::builder()
+        .name(String::from("text_search"))
+        .default_language(String::from("english"))
+        .weights(doc! {
+            "title": TITLE_WEIGHT as i32,
+            "tags": TAGS_WEIGHT as i32,
+            "details": DETAILS_WEIGHT as i32,
+        })
+        .build();
+    vec![
+        index("date_time", doc! { "date_time": 1 }),
+        index("is_done_date_time", doc! { "is_done": 1, "date_time": 1 }),
+        index("tags", doc! { "tags": 1 }),
+        index("project_id", doc! { "project_id": 1 }),
+        IndexModel::builder()
+            .keys(doc! { "title": "text", "details": "text", "tags": "text" })
+            .options(text_options)
+            .build(),
+    ]
+}
+
+/// This function turns an event of the change stream into the change of the stored events;
+/// events which don't change a document, like dropping the collection, give None
+fn event_to_change(
+    event: ChangeStreamEvent<Document>,
+) -> Result<Option<EventChange>, MiddlewareError> {
+    let id = event
+        .document_key
+        .as_ref()
+        .and_then(|key| key.get_object_id("_id").ok());
+    let change = match (event.operation_type, event.full_document) {
+        (OperationType::Insert, Some(document)) => {
+            EventChange::Inserted(document_to_event(&document)?)
+        }
+        (OperationType::Update | OperationType::Replace, Some(document)) => {
+            let entry = document_to_event(&document)?;
+            if entry.deleted_at.is_some() {
+                EventChange::Deleted(entry.unique_id)
+            } else {
+                EventChange::Updated(entry)
+            }
+        }
+        // An update whose document is gone by the time it is looked up, or a purge
+        (OperationType::Update | OperationType::Replace | OperationType::Delete, _) => match id {
+            Some(id) => EventChange::Deleted(id),
+            None => return Ok(None),
+        },
+        _ => return Ok(None),
+    };
+    Ok(Some(change))
+}
+
+/// Whether the server can't open change streams because it isn't part of a replica set
+fn is_change_stream_unsupported(error: &mongodb::error::Error) -> bool {
+    matches!(
+        error.kind.as_ref(),
+        mongodb::error::ErrorKind::Command(command_error) if command_error.code == 40573
+    )
+}
+
+/// Whether the server can't run transactions because it isn't part of a replica set
+fn is_transaction_unsupported(error: &mongodb::error::Error) -> bool {
+    matches!(
+        error.kind.as_ref(),
+        mongodb::error::ErrorKind::Transaction { .. }
+    )
+}
+
+/// Whether the transaction failed for a reason which may be gone when it is run again, such as a
+/// write conflict with another client
+fn is_transient(error: &MiddlewareError) -> bool {
+    match error {
+        MiddlewareError::Connection(source) | MiddlewareError::Database(source) => source
+            .downcast_ref::<mongodb::error::Error>()
+            .is_some_and(|error| error.contains_label(TRANSIENT_TRANSACTION_ERROR)),
+        _ => false,
+    }
+}
+
+/// Commits the transaction of the session when its operations succeeded and aborts it otherwise
+async fn finish_transaction<T>(
+    session: &mut ClientSession,
+    result: Result<T, MiddlewareError>,
+) -> Result<T, MiddlewareError> {
+    match result {
+        Ok(value) => loop {
+            match session.commit_transaction().await {
+                Ok(()) => return Ok(value),
+                // The commit may or may not have gone through; committing again is safe
+                Err(error) if error.contains_label(UNKNOWN_TRANSACTION_COMMIT_RESULT) => {}
+                Err(error) => return Err(error.into()),
+            }
+        },
+        Err(error) => {
+            // The server aborts the transaction itself when it times out, so a failed abort
+            // doesn't change the outcome
+            let _ = session.abort_transaction().await;
+            Err(error)
+        }
+    }
+}
+
+/// Whether the operation failed because the collection doesn't exist
+fn is_namespace_not_found(error: &mongodb::error::Error) -> bool {
+    matches!(
+        error.kind.as_ref(),
+        mongodb::error::ErrorKind::Command(command_error) if command_error.code == 26
+    )
+}
+
+/// The fields of the event an update sets, everything but the id, the version and the trash
+fn event_fields(entry: &EventEntry) -> Document {
+    doc! { "title": entry.title.clone(), "details": entry.details.clone(),"date_time": entry.date_time.as_ref().map(to_bson_date),
+    "is_done": entry.is_done, "tags": entry.tags.clone(), "recurrence": entry.recurrence.as_ref().map(ToString::to_string),
+    "subtasks": subtasks_to_bson(&entry.subtasks), "auto_complete": entry.auto_complete,
+    "blocked_by": entry.blocked_by.clone(), "priority": entry.priority.as_str(),
+    "project_id": entry.project_id, }
+}
+
+/// The entry as it reads back once stored: BSON keeps dates to the millisecond
+fn as_stored(entry: &EventEntry) -> EventEntry {
+    EventEntry {
+        date_time: entry
+            .date_time
+            .map(|date_time| from_bson_date(&to_bson_date(&date_time)).unwrap_or(date_time)),
+        ..entry.clone()
+    }
+}
+
+/// This function extracts the task data from a document of the "todos" collection;
+/// fields other than _id and date_time get their default value when they are missing
+fn document_to_event(document: &Document) -> Result<EventEntry, MiddlewareError> {
+    let unique_id = match document.get("_id") {
+        Some(Bson::ObjectId(object_id)) => *object_id,
+        _ => return Err(MiddlewareError::decode("_id", None)),
+    };
+    let title = document.get_str("title").unwrap_or_default().to_string();
+    let details = document.get_str("details").ok().map(str::to_string);
+    let date_time = match document.get("date_time") {
+        Some(Bson::Null) | None => None,
+        date_time => Some(
+            match date_time {
+                Some(Bson::DateTime(date_time)) => from_bson_date(date_time),
+                // Documents written before dates were stored as BSON DateTime
+                Some(Bson::String(date_time)) => parse_legacy_date(date_time),
+                _ => None,
+            }
+            .ok_or_else(|| MiddlewareError::decode("date_time", Some(&unique_id)))?,
+        ),
+    };
+    let is_done = document.get_bool("is_done").unwrap_or_default();
+    let tags = match document.get("tags") {
+        Some(Bson::Array(tags)) => tags
+            .iter()
+            .map(|tag| tag.as_str().map(str::to_string))
+            .collect::<Option<Vec<String>>>()
+            .ok_or_else(|| MiddlewareError::decode("tags", Some(&unique_id)))?,
+        // Documents which haven't been migrated yet keep the tags as text
+        Some(Bson::String(tags)) => parse_tags(tags),
+        Some(Bson::Null) | None => Vec::new(),
+        Some(_) => return Err(MiddlewareError::decode("tags", Some(&unique_id))),
+    };
+    let deleted_at = match document.get("deleted_at") {
+        Some(Bson::DateTime(deleted_at)) => Some(
+            from_bson_date(deleted_at)
+                .ok_or_else(|| MiddlewareError::decode("deleted_at", Some(&unique_id)))?,
+        ),
+        Some(Bson::Null) | None => None,
+        Some(_) => return Err(MiddlewareError::decode("deleted_at", Some(&unique_id))),
+    };
+
+    // Documents written before versions were introduced have none
+    let version = match document.get("version") {
+        Some(Bson::Int64(version)) => *version as u64,
+        Some(Bson::Int32(version)) => *version as u64,
+        _ => 0,
+    };
+
+    // Stored as the RRULE text of the recurrence
+    let recurrence = match document.get("recurrence") {
+        Some(Bson::String(rule)) => Some(
+            rule.parse()
+                .map_err(|_| MiddlewareError::decode("recurrence", Some(&unique_id)))?,
+        ),
+        Some(Bson::Null) | None => None,
+        Some(_) => return Err(MiddlewareError::decode("recurrence", Some(&unique_id))),
+    };
+
+    let subtasks = match document.get("subtasks") {
+        Some(Bson::Array(subtasks)) => subtasks
+            .iter()
+            .map(|subtask| {
+                let subtask = subtask.as_document()?;
+                Some(Subtask {
+                    id: subtask.get_object_id("id").ok()?,
+                    title: subtask.get_str("title").ok()?.to_string(),
+                    is_done: subtask.get_bool("is_done").ok()?,
+                })
+            })
+            .collect::<Option<Vec<Subtask>>>()
+            .ok_or_else(|| MiddlewareError::decode("subtasks", Some(&unique_id)))?,
+        Some(Bson::Null) | None => Vec::new(),
+        Some(_) => return Err(MiddlewareError::decode("subtasks", Some(&unique_id))),
+    };
+    let auto_complete = document.get_bool("auto_complete").unwrap_or_default();
+    let blocked_by = match document.get("blocked_by") {
+        Some(Bson::Array(blocked_by)) => blocked_by
+            .iter()
+            .map(Bson::as_object_id)
+            .collect::<Option<Vec<ObjectId>>>()
+            .ok_or_else(|| MiddlewareError::decode("blocked_by", Some(&unique_id)))?,
+        Some(Bson::Null) | None => Vec::new(),
+        Some(_) => return Err(MiddlewareError::decode("blocked_by", Some(&unique_id))),
+    };
+
+    // Events stored before priorities were introduced have none
+    let priority = match document.get("priority") {
+        Some(Bson::String(name)) => name
+            .parse()
+            .map_err(|_| MiddlewareError::decode("priority", Some(&unique_id)))?,
+        Some(Bson::Null) | None => Priority::None,
+        Some(_) => return Err(MiddlewareError::decode("priority", Some(&unique_id))),
+    };
+
+    let project_id = match document.get("project_id") {
+        Some(Bson::ObjectId(project_id)) => Some(*project_id),
+        Some(Bson::Null) | None => None,
+        Some(_) => return Err(MiddlewareError::decode("project_id", Some(&unique_id))),
+    };
+
+    // Create a new EventEntry instance
+    Ok(EventEntry {
+        unique_id,
+        title,
+        details,
+        date_time,
+        is_done,
+        tags,
+        deleted_at,
+        version,
+        recurrence,
+        subtasks,
+        auto_complete,
+        blocked_by,
+        priority,
+        project_id,
+    })
+}
+
+/// This function builds the document stored in the projects collection
+fn project_to_document(project: &Project) -> Document {
+    doc! {
+        "_id": project.id,
+        "name": project.name.clone(),
+        "color": project.color.clone(),
+        "archived": project.archived,
+        "position": project.position as i64,
+    }
+}
+
+/// This function extracts a project from a document of the projects collection
+fn document_to_project(document: &Document) -> Result<Project, MiddlewareError> {
+    let id = document
+        .get_object_id("_id")
+        .map_err(|_| MiddlewareError::decode("_id", None))?;
+    let invalid = |field: &str| MiddlewareError::decode(field, Some(&id));
+    let position = match document.get("position") {
+        Some(Bson::Int64(position)) => *position as u32,
+        Some(Bson::Int32(position)) => *position as u32,
+        _ => return Err(invalid("position")),
+    };
+    Ok(Project {
+        id,
+        name: document
+            .get_str("name")
+            .map_err(|_| invalid("name"))?
+            .to_string(),
+        color: document
+            .get_str("color")
+            .map_err(|_| invalid("color"))?
+            .to_string(),
+        archived: document.get_bool("archived").unwrap_or_default(),
+        position,
+    })
+}
+
+/// This function builds the array of subtask documents stored in an event, in checklist order
+fn subtasks_to_bson(subtasks: &[Subtask]) -> Vec<Document> {
+    subtasks
+        .iter()
+        .map(|subtask| {
+            doc! {
+                "id": subtask.id,
+                "title": subtask.title.clone(),
+                "is_done": subtask.is_done,
+            }
+        })
+        .collect()
+}
+
+/// This function builds the document stored in the revisions collection
+fn revision_to_document(revision: &Revision) -> Document {
+    let changes: Vec<Document> = revision
+        .changes
+        .iter()
+        .map(|change| {
+            doc! {
+                "field": change.field.clone(),
+                "old_value": change.old_value.clone(),
+                "new_value": change.new_value.clone(),
+            }
+        })
+        .collect();
+    doc! {
+        "_id": revision.revision_id,
+        "event_id": revision.event_id,
+        "action": revision.action.as_str(),
+        "changes": changes,
+        "timestamp": to_bson_date(&revision.timestamp),
+        "actor": revision.actor.clone(),
+    }
+}
+
+/// This function extracts a revision from a document of the revisions collection
+fn document_to_revision(document: &Document) -> Result<Revision, MiddlewareError> {
+    let revision_id = document
+        .get_object_id("_id")
+        .map_err(|_| MiddlewareError::decode("_id", None))?;
+    let invalid = |field: &str| MiddlewareError::decode(field, Some(&revision_id));
+    let mut changes = Vec::new();
+    for change in document
+        .get_array("changes")
+        .map_err(|_| invalid("changes"))?
+    {
+        let change = change.as_document().ok_or_else(|| invalid("changes"))?;
+        let get = |field: &str| {
+            change
+                .get_str(field)
+                .map(String::from)
+                .map_err(|_| invalid("changes"))
+        };
+        changes.push(FieldChange {
+            field: get("field")?,
+            old_value: get("old_value")?,
+            new_value: get("new_value")?,
+        });
+    }
+
+    Ok(Revision {
+        revision_id,
+        event_id: document
+            .get_object_id("event_id")
+            .map_err(|_| invalid("event_id"))?,
+        action: document
+            .get_str("action")
+            .map_err(|_| invalid("action"))?
+            .parse()?,
+        changes,
+        timestamp: document
+            .get_datetime("timestamp")
+            .ok()
+            .and_then(from_bson_date)
+            .ok_or_else(|| invalid("timestamp"))?,
+        actor: document.get_str("actor").unwrap_or_default().to_string(),
+    })
+}
+
+/// Converts a chrono date into the BSON DateTime stored in the database (millisecond precision)
+pub(crate) fn to_bson_date(date_time: &DateTime<Utc>) -> mongodb::bson::DateTime {
+    mongodb::bson::DateTime::from_millis(date_time.timestamp_millis())
+}
+
+/// Converts a BSON DateTime read from the database into a chrono date
+fn from_bson_date(date_time: &mongodb::bson::DateTime) -> Option<DateTime<Utc>> {
+    Utc.timestamp_millis_opt(date_time.timestamp_millis())
+        .single()
+}
+
+/// Parses the date strings written by older versions; RFC 3339 as written by add_event,
+/// or the `NaiveDateTime` format, which is taken as UTC
+pub(crate) fn parse_legacy_date(date_time: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(date_time)
+        .map(|date_time| date_time.with_timezone(&Utc))
+        .ok()
+        .or_else(|| {
+            NaiveDateTime::parse_from_str(date_time, "%Y-%m-%d %H:%M:%S%.f")
+                .map(|date_time| date_time.and_utc())
+                .ok()
+        })
+}
+
+/// This function creates a connection client for the database described by the config
+pub async fn create_mongodb_client(config: &MongoConfig) -> Result<Client, MiddlewareError> {
+    let mut client_options = ClientOptions::parse(&config.uri).await?;
+
+    if config.username.is_some() || config.password.is_some() {
+        client_options.credential = Some(
+            Credential::builder()
+                .username(config.username.clone())
+                .password(config.password.clone())
+                .source(config.auth_source.clone())
+                .build(),
+        );
+    }
+    if config.tls {
+        client_options.tls = Some(Tls::Enabled(
+            TlsOptions::builder()
+                .ca_file_path(config.tls_ca_file.clone())
+                .allow_invalid_certificates(Some(config.tls_allow_invalid_certificates))
+                .build(),
+        ));
+    }
+    if let Some(timeout) = config.connect_timeout_ms {
+        client_options.connect_timeout = Some(Duration::from_millis(timeout));
+    }
+    if let Some(timeout) = config.server_selection_timeout_ms {
+        client_options.server_selection_timeout = Some(Duration::from_millis(timeout));
+    }
+
+    let client = Client::with_options(client_options)?;
+    Ok(client)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Frequency, Recurrence};
+    use chrono::Weekday;
+    use mongodb::bson::oid::ObjectId;
+
+    #[test]
+    fn test_parse_legacy_date() {
+        let expected = Utc.with_ymd_and_hms(2023, 6, 9, 15, 9, 0).unwrap();
+        assert_eq!(
+            parse_legacy_date("2023-06-09T15:09:00+00:00"),
+            Some(expected)
+        );
+        assert_eq!(
+            parse_legacy_date("2023-06-09T08:09:00-07:00"),
+            Some(expected)
+        );
+        assert_eq!(parse_legacy_date("2023-06-09 15:09:00"), Some(expected));
+        assert_eq!(parse_legacy_date("next friday"), None);
+    }
+
+    #[test]
+    fn test_mongo_indexes() {
+        let names: Vec<String> = mongo_indexes()
+            .into_iter()
+            .filter_map(|index| index.options.and_then(|options| options.name))
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                "date_time",
+                "is_done_date_time",
+                "tags",
+                "project_id",
+                "text_search"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_document_to_event() {
+        let unique_id = ObjectId::new();
+        let date_time = Utc.with_ymd_and_hms(2023, 6, 9, 15, 9, 0).unwrap();
+        let mut document = doc! {
+            "_id": unique_id,
+            "title": "KrabbyDo",
+            "details": "Store dates as BSON",
+            "date_time": to_bson_date(&date_time),
+            "is_done": false,
+            "tags": "Work, home",
+        };
+        assert_eq!(
+            document_to_event(&document).unwrap().date_time,
+            Some(date_time)
+        );
+
+        // Documents which haven't been migrated yet can still be read
+        document.insert("date_time", date_time.to_rfc3339());
+        assert_eq!(
+            document_to_event(&document).unwrap().date_time,
+            Some(date_time)
+        );
+        assert_eq!(
+            document_to_event(&document).unwrap().tags,
+            vec!["work", "home"]
+        );
+        document.insert("tags", vec!["work", "home"]);
+        assert_eq!(
+            document_to_event(&document).unwrap().tags,
+            vec!["work", "home"]
+        );
+        document.insert("tags", vec![1]);
+        assert!(document_to_event(&document).is_err());
+
+        // Missing optional fields are read as None
+        document.remove("tags");
+        document.remove("details");
+        document.insert("date_time", Bson::Null);
+        let event = document_to_event(&document).unwrap();
+        assert!(event.tags.is_empty());
+        assert_eq!(event.details, None);
+        assert_eq!(event.date_time, None);
+        assert_eq!(event.deleted_at, None);
+
+        document.insert("deleted_at", to_bson_date(&date_time));
+        assert_eq!(
+            document_to_event(&document).unwrap().deleted_at,
+            Some(date_time)
+        );
+
+        document.insert("recurrence", "RRULE:FREQ=WEEKLY;BYDAY=MO,FR");
+        assert_eq!(
+            document_to_event(&document).unwrap().recurrence,
+            Some(
+                Recurrence::new(Frequency::Weekly)
+                    .on(Weekday::Mon)
+                    .on(Weekday::Fri)
+            )
+        );
+        document.insert("recurrence", "RRULE:FREQ=HOURLY");
+        assert!(document_to_event(&document).is_err());
+        document.remove("recurrence");
+
+        let subtasks = vec![Subtask::new("Tag the commit")];
+        document.insert("subtasks", subtasks_to_bson(&subtasks));
+        assert_eq!(document_to_event(&document).unwrap().subtasks, subtasks);
+        document.insert("subtasks", vec![doc! { "title": "No id" }]);
+        assert!(document_to_event(&document).is_err());
+        document.remove("subtasks");
+
+        let blocked_by = vec![ObjectId::new()];
+        document.insert("blocked_by", blocked_by.clone());
+        assert_eq!(document_to_event(&document).unwrap().blocked_by, blocked_by);
+        document.insert("blocked_by", vec!["Tag the commit"]);
+        assert!(document_to_event(&document).is_err());
+        document.remove("blocked_by");
+
+        document.insert("priority", "high");
+        assert_eq!(
+            document_to_event(&document).unwrap().priority,
+            Priority::High
+        );
+        document.insert("priority", "urgent");
+        assert!(document_to_event(&document).is_err());
+        document.remove("priority");
+
+        let project_id = ObjectId::new();
+        document.insert("project_id", project_id);
+        assert_eq!(
+            document_to_event(&document).unwrap().project_id,
+            Some(project_id)
+        );
+        document.insert("project_id", "Home");
+        assert!(document_to_event(&document).is_err());
+    }
+
+    #[test]
+    fn test_document_to_project() {
+        let project = Project {
+            archived: true,
+            position: 3,
+            ..Project::new("Home")
+        };
+        let mut document = project_to_document(&project);
+        assert_eq!(document_to_project(&document).unwrap(), project);
+        document.remove("name");
+        assert!(document_to_project(&document).is_err());
+    }
+}
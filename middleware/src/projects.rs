@@ -0,0 +1,136 @@
+//! Projects group events into lists, e.g. "Home" or "Release 2". An event belongs to at most one
+//! project through its project_id; events without one are in the Inbox. Projects are listed by
+//! their position, and archiving a project hides it from the lists while keeping its events.
+
+use crate::MiddlewareError;
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+/// Color given to projects which aren't given one
+const DEFAULT_COLOR: &str = "#4a90d9";
+
+/// Project is a list events can be grouped in
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Project {
+    /// Identifies the project; events refer to it by this id
+    pub id: ObjectId,
+    /// Name shown in the project navigator
+    pub name: String,
+    /// Color the project is shown in, written as "#rrggbb"
+    pub color: String,
+    /// Denotates if the project is hidden from the lists
+    pub archived: bool,
+    /// Position of the project in the navigator, counted from 0
+    pub position: u32,
+}
+
+impl Project {
+    /// A project in the default color which isn't archived, listed first
+    pub fn new(name: &str) -> Self {
+        Project {
+            id: ObjectId::new(),
+            name: name.trim().to_string(),
+            color: String::from(DEFAULT_COLOR),
+            archived: false,
+            position: 0,
+        }
+    }
+
+    /// Red, green and blue of the color; None when it isn't written as "#rrggbb"
+    pub fn rgb(&self) -> Option<[u8; 3]> {
+        let hex = self.color.strip_prefix('#').filter(|x| x.len() == 6)?;
+        let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+        Some([channel(0)?, channel(2)?, channel(4)?])
+    }
+
+    /// Sets the color from its red, green and blue
+    pub fn set_rgb(&mut self, [r, g, b]: [u8; 3]) {
+        self.color = format!("#{:02x}{:02x}{:02x}", r, g, b);
+    }
+
+    /// Checks the project can be stored: it needs a name, and its color must be "#rrggbb"
+    pub fn check(&self) -> Result<(), MiddlewareError> {
+        if self.name.trim().is_empty() {
+            return Err(MiddlewareError::Validation(String::from(
+                "A project needs a name",
+            )));
+        }
+        if self.rgb().is_none() {
+            return Err(MiddlewareError::Validation(format!(
+                "The color \"{}\" isn't written as #rrggbb",
+                self.color
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Sorts the projects by position, and projects at the same position by name
+pub(crate) fn sort_projects(projects: &mut [Project]) {
+    projects.sort_by(|a, b| {
+        a.position
+            .cmp(&b.position)
+            .then_with(|| a.name.cmp(&b.name))
+    });
+}
+
+/// Moves the project to the given position, or to the end when the position is past it, and
+/// numbers the projects from 0 in their new order; returns the projects in that order
+pub(crate) fn reorder(
+    mut projects: Vec<Project>,
+    id: &ObjectId,
+    position: usize,
+) -> Result<Vec<Project>, MiddlewareError> {
+    sort_projects(&mut projects);
+    let index = projects
+        .iter()
+        .position(|x| x.id == *id)
+        .ok_or(MiddlewareError::NotFound { id: *id })?;
+    let project = projects.remove(index);
+    projects.insert(position.min(projects.len()), project);
+    for (position, project) in projects.iter_mut().enumerate() {
+        project.position = position as u32;
+    }
+    Ok(projects)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        let mut project = Project::new(" Home ");
+        assert_eq!(project.name, "Home");
+        assert!(project.check().is_ok());
+        project.set_rgb([255, 0, 16]);
+        assert_eq!(project.color, "#ff0010");
+        assert_eq!(project.rgb(), Some([255, 0, 16]));
+
+        project.color = String::from("red");
+        assert!(project.check().is_err());
+        assert!(Project::new("  ").check().is_err());
+    }
+
+    #[test]
+    fn test_reorder() {
+        let projects: Vec<Project> = ["Home", "Work", "Garden"]
+            .iter()
+            .enumerate()
+            .map(|(position, name)| Project {
+                position: position as u32,
+                ..Project::new(name)
+            })
+            .collect();
+        let names = |projects: &[Project]| -> Vec<String> {
+            projects.iter().map(|x| x.name.clone()).collect()
+        };
+
+        let moved = reorder(projects.clone(), &projects[2].id, 0).unwrap();
+        assert_eq!(names(&moved), vec!["Garden", "Home", "Work"]);
+        assert_eq!(moved[2].position, 2);
+        let moved = reorder(projects.clone(), &projects[0].id, 99).unwrap();
+        assert_eq!(names(&moved), vec!["Work", "Garden", "Home"]);
+        assert!(reorder(projects, &ObjectId::new(), 0).is_err());
+    }
+}
@@ -0,0 +1,576 @@
+//! EventQuery describes which events to fetch and in which order.
+//! Each backend translates it into its own query language (a filter document on MongoDB,
+//! a WHERE clause on SQLite), and `matches` / `sort` give the reference behaviour they follow.
+
+use crate::tags::{parse_tags, tags_to_text};
+use crate::{EventEntry, Priority, UrgencyConfig};
+use chrono::{DateTime, Utc};
+use mongodb::bson::{doc, oid::ObjectId, Bson, Document, Regex};
+use std::cmp::Ordering;
+
+/// Fields events can be sorted by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortKey {
+    #[default]
+    DateTime,
+    Title,
+    IsDone,
+    /// Score worked out by UrgencyConfig::score, so a critical event due tomorrow can come
+    /// before a minor one due today; usually sorted Descending, most urgent first
+    Urgency,
+}
+
+impl SortKey {
+    /// Name of the field in the stored events; the urgency isn't stored but computed when sorting
+    pub fn field(&self) -> &'static str {
+        match self {
+            SortKey::DateTime => "date_time",
+            SortKey::Title => "title",
+            SortKey::IsDone => "is_done",
+            SortKey::Urgency => "urgency",
+        }
+    }
+}
+
+/// Order in which the sort key is applied
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortDirection {
+    #[default]
+    Ascending,
+    Descending,
+}
+
+/// EventQuery is built with chained calls, e.g.
+/// `EventQuery::new().date_range(from, until).tag("Work").done(false).limit(20)`.
+/// Events in the trash are never returned, and events without a date only match when no date range is set.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EventQuery {
+    /// Events at or after this time
+    pub from: Option<DateTime<Utc>>,
+    /// Events before this time
+    pub until: Option<DateTime<Utc>>,
+    /// Tags every returned event carries, compared without regard to case
+    pub tags: Vec<String>,
+    /// Only events which are done, or not done
+    pub is_done: Option<bool>,
+    /// Only events which have a date (true), or which have none (false)
+    pub has_date: Option<bool>,
+    /// Only events of this priority or a higher one
+    pub min_priority: Option<Priority>,
+    /// Only events of this project, or the events in no project for Some(None)
+    pub project: Option<Option<ObjectId>>,
+    /// Text found in the title, details or tags, compared without regard to case
+    pub text: Option<String>,
+    /// Field the events are sorted by
+    pub sort_key: SortKey,
+    /// Order of the sort
+    pub sort_direction: SortDirection,
+    /// Number of matching events to skip
+    pub skip: u64,
+    /// Largest number of events to return
+    pub limit: Option<u64>,
+}
+
+impl EventQuery {
+    /// A query matching every event, sorted by date
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Only events from `from` (inclusive) until `until` (exclusive)
+    pub fn date_range(mut self, from: DateTime<Utc>, until: DateTime<Utc>) -> Self {
+        self.from = Some(from);
+        self.until = Some(until);
+        self
+    }
+
+    /// Only events at or after the given time
+    pub fn from(mut self, from: DateTime<Utc>) -> Self {
+        self.from = Some(from);
+        self
+    }
+
+    /// Only events before the given time
+    pub fn until(mut self, until: DateTime<Utc>) -> Self {
+        self.until = Some(until);
+        self
+    }
+
+    /// Only events carrying the tag; can be called several times to require several tags
+    pub fn tag(mut self, tag: &str) -> Self {
+        self.tags.push(tag.to_string());
+        self
+    }
+
+    /// Only events which are done (true) or not done (false)
+    pub fn done(mut self, is_done: bool) -> Self {
+        self.is_done = Some(is_done);
+        self
+    }
+
+    /// Only events which have a date (true), or the someday / maybe events which have none (false)
+    pub fn dated(mut self, has_date: bool) -> Self {
+        self.has_date = Some(has_date);
+        self
+    }
+
+    /// Only events of the given priority or a higher one
+    pub fn min_priority(mut self, priority: Priority) -> Self {
+        self.min_priority = Some(priority);
+        self
+    }
+
+    /// Only events of the project
+    pub fn in_project(mut self, project_id: ObjectId) -> Self {
+        self.project = Some(Some(project_id));
+        self
+    }
+
+    /// Only the events in the Inbox, which belong to no project
+    pub fn without_project(mut self) -> Self {
+        self.project = Some(None);
+        self
+    }
+
+    /// Only events whose title, details or tags contain the text
+    pub fn text(mut self, text: &str) -> Self {
+        self.text = Some(text.to_string());
+        self
+    }
+
+    /// Sorts the events by the key in the given direction
+    pub fn sort_by(mut self, sort_key: SortKey, sort_direction: SortDirection) -> Self {
+        self.sort_key = sort_key;
+        self.sort_direction = sort_direction;
+        self
+    }
+
+    /// Skips the first matching events
+    pub fn skip(mut self, skip: u64) -> Self {
+        self.skip = skip;
+        self
+    }
+
+    /// Returns at most this many events
+    pub fn limit(mut self, limit: u64) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Whether the event is matched by the query, leaving sorting and paging aside
+    pub fn matches(&self, entry: &EventEntry) -> bool {
+        if entry.deleted_at.is_some() {
+            return false;
+        }
+        if self.from.is_some() || self.until.is_some() {
+            let Some(date_time) = entry.date_time else {
+                return false;
+            };
+            if matches!(self.from, Some(from) if date_time < from) {
+                return false;
+            }
+            if matches!(self.until, Some(until) if date_time >= until) {
+                return false;
+            }
+        }
+        if matches!(self.has_date, Some(has_date) if entry.date_time.is_some() != has_date) {
+            return false;
+        }
+        if matches!(self.is_done, Some(is_done) if entry.is_done != is_done) {
+            return false;
+        }
+        if matches!(self.min_priority, Some(priority) if entry.priority < priority) {
+            return false;
+        }
+        if matches!(self.project, Some(project_id) if entry.project_id != project_id) {
+            return false;
+        }
+        if !self.tag_names().iter().all(|tag| entry.tags.contains(tag)) {
+            return false;
+        }
+        match &self.text {
+            Some(text) => {
+                let text = text.to_lowercase();
+                [
+                    Some(&entry.title),
+                    entry.details.as_ref(),
+                    Some(&tags_to_text(&entry.tags)),
+                ]
+                .iter()
+                .flatten()
+                .any(|field| field.to_lowercase().contains(&text))
+            }
+            None => true,
+        }
+    }
+
+    /// Compares two events by the sort key and direction of the query; ties are broken by id.
+    /// Events without a date come before those with one, the way the databases sort a missing value.
+    /// The urgency is scored with the given coefficients at the given time.
+    pub fn compare(
+        &self,
+        a: &EventEntry,
+        b: &EventEntry,
+        urgency: &UrgencyConfig,
+        now: DateTime<Utc>,
+    ) -> Ordering {
+        let ordering = match self.sort_key {
+            SortKey::DateTime => a.date_time.cmp(&b.date_time),
+            SortKey::Title => a.title.cmp(&b.title),
+            SortKey::IsDone => a.is_done.cmp(&b.is_done),
+            SortKey::Urgency => urgency.score(a, now).total_cmp(&urgency.score(b, now)),
+        };
+        let ordering = match self.sort_direction {
+            SortDirection::Ascending => ordering,
+            SortDirection::Descending => ordering.reverse(),
+        };
+        ordering.then_with(|| a.unique_id.cmp(&b.unique_id))
+    }
+
+    /// Filters, sorts and pages a list of events the way the query describes
+    pub fn apply(
+        &self,
+        entries: impl IntoIterator<Item = EventEntry>,
+        urgency: &UrgencyConfig,
+        now: DateTime<Utc>,
+    ) -> Vec<EventEntry> {
+        let mut entries: Vec<EventEntry> = entries
+            .into_iter()
+            .filter(|entry| self.matches(entry))
+            .collect();
+        entries.sort_by(|a, b| self.compare(a, b, urgency, now));
+        entries
+            .into_iter()
+            .skip(self.skip as usize)
+            .take(self.limit.map_or(usize::MAX, |limit| limit as usize))
+            .collect()
+    }
+
+    /// The query without its order and paging; the databases can't sort by the urgency, so the
+    /// stores fetch the matching events this way and sort and page them with `apply`
+    pub(crate) fn unordered(&self) -> EventQuery {
+        EventQuery {
+            sort_key: SortKey::DateTime,
+            sort_direction: SortDirection::Ascending,
+            skip: 0,
+            limit: None,
+            ..self.clone()
+        }
+    }
+
+    /// The tags of the query normalized like the tags of the events
+    pub(crate) fn tag_names(&self) -> Vec<String> {
+        parse_tags(&self.tags.join(","))
+    }
+
+    /// Names of the priorities matched by min_priority; None when every priority is
+    pub(crate) fn priority_names(&self) -> Option<Vec<&'static str>> {
+        let min_priority = self.min_priority.filter(|x| *x > Priority::None)?;
+        Some(
+            Priority::ALL
+                .iter()
+                .filter(|x| **x >= min_priority)
+                .map(Priority::as_str)
+                .collect(),
+        )
+    }
+
+    /// The MongoDB filter document matching the same events as `matches`
+    pub(crate) fn to_mongo_filter(&self) -> Document {
+        let mut conditions = vec![doc! { "deleted_at": null }];
+        let mut date_time = Document::new();
+        if let Some(from) = &self.from {
+            date_time.insert("$gte", crate::mongo::to_bson_date(from));
+        }
+        if let Some(until) = &self.until {
+            date_time.insert("$lt", crate::mongo::to_bson_date(until));
+        }
+        if !date_time.is_empty() {
+            conditions.push(doc! { "date_time": date_time });
+        }
+        match self.has_date {
+            Some(true) => conditions.push(doc! { "date_time": { "$ne": null } }),
+            Some(false) => conditions.push(doc! { "date_time": null }),
+            None => {}
+        }
+        if let Some(is_done) = self.is_done {
+            conditions.push(doc! { "is_done": is_done });
+        }
+        if let Some(names) = self.priority_names() {
+            conditions.push(doc! { "priority": { "$in": names } });
+        }
+        if let Some(project_id) = self.project {
+            // null also matches events stored before projects were introduced
+            conditions.push(doc! { "project_id": project_id });
+        }
+        for tag in self.tag_names() {
+            // Matches the arrays holding the tag
+            conditions.push(doc! { "tags": tag });
+        }
+        if let Some(text) = &self.text {
+            let pattern = escape_regex(text);
+            conditions.push(doc! { "$or": [
+                { "title": case_insensitive_regex(pattern.clone()) },
+                { "details": case_insensitive_regex(pattern.clone()) },
+                { "tags": case_insensitive_regex(pattern) },
+            ] });
+        }
+        doc! { "$and": conditions }
+    }
+
+    /// The MongoDB sort document matching `compare`
+    pub(crate) fn to_mongo_sort(&self) -> Document {
+        let direction = match self.sort_direction {
+            SortDirection::Ascending => 1,
+            SortDirection::Descending => -1,
+        };
+        doc! { self.sort_key.field(): direction, "_id": 1 }
+    }
+}
+
+/// A BSON regular expression ignoring case
+fn case_insensitive_regex(pattern: String) -> Bson {
+    Bson::RegularExpression(Regex {
+        pattern,
+        options: String::from("i"),
+    })
+}
+
+/// Escapes the characters which have a meaning in a regular expression
+pub(crate) fn escape_regex(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if "\\^$.|?*+()[]{}-/#".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample_entry;
+    use chrono::TimeZone;
+    use mongodb::bson::oid::ObjectId;
+
+    /// Noon of the given day of June 2023
+    fn june(day: u32) -> Option<DateTime<Utc>> {
+        Utc.with_ymd_and_hms(2023, 6, day, 12, 0, 0).single()
+    }
+
+    #[test]
+    fn test_apply() {
+        let entries = vec![
+            EventEntry {
+                date_time: june(3),
+                tags: parse_tags("Home, Errands"),
+                ..sample_entry("Groceries")
+            },
+            EventEntry {
+                date_time: june(1),
+                ..sample_entry("Report")
+            },
+            EventEntry {
+                date_time: june(2),
+                is_done: true,
+                ..sample_entry("Standup")
+            },
+            EventEntry {
+                date_time: june(4),
+                tags: parse_tags("School"),
+                ..sample_entry("Homework")
+            },
+        ];
+        let titles = |query: EventQuery| -> Vec<String> {
+            query
+                .apply(entries.clone(), &UrgencyConfig::default(), Utc::now())
+                .into_iter()
+                .map(|x| x.title)
+                .collect()
+        };
+
+        assert_eq!(
+            titles(EventQuery::new()),
+            vec!["Report", "Standup", "Groceries", "Homework"]
+        );
+        assert_eq!(
+            titles(EventQuery::new().tag("WORK")),
+            vec!["Report", "Standup"]
+        );
+        // "Home" is a whole tag, so "Homework" only matches it as text
+        assert_eq!(titles(EventQuery::new().tag("home")), vec!["Groceries"]);
+        assert_eq!(
+            titles(EventQuery::new().text("home")),
+            vec!["Groceries", "Homework"]
+        );
+        assert_eq!(
+            titles(
+                EventQuery::new()
+                    .done(false)
+                    .sort_by(SortKey::Title, SortDirection::Descending)
+            ),
+            vec!["Report", "Homework", "Groceries"]
+        );
+        let from = Utc.with_ymd_and_hms(2023, 6, 2, 0, 0, 0).unwrap();
+        let until = Utc.with_ymd_and_hms(2023, 6, 4, 0, 0, 0).unwrap();
+        assert_eq!(
+            titles(EventQuery::new().date_range(from, until)),
+            vec!["Standup", "Groceries"]
+        );
+        assert_eq!(
+            titles(EventQuery::new().skip(1).limit(2)),
+            vec!["Standup", "Groceries"]
+        );
+
+        // Someday / maybe events have no date, so they only match queries without a date range
+        let mut entries = entries.clone();
+        entries.push(EventEntry {
+            date_time: None,
+            details: None,
+            tags: parse_tags("Home"),
+            ..sample_entry("Learn the cello")
+        });
+        let titles = |query: EventQuery| -> Vec<String> {
+            query
+                .apply(entries.clone(), &UrgencyConfig::default(), Utc::now())
+                .into_iter()
+                .map(|x| x.title)
+                .collect()
+        };
+        assert_eq!(
+            titles(EventQuery::new().dated(false)),
+            vec!["Learn the cello"]
+        );
+        assert_eq!(
+            titles(EventQuery::new().tag("home")),
+            vec!["Learn the cello", "Groceries"]
+        );
+        assert_eq!(
+            titles(EventQuery::new().date_range(from, until)),
+            vec!["Standup", "Groceries"]
+        );
+    }
+
+    #[test]
+    fn test_sort_by_urgency() {
+        let now = Utc.with_ymd_and_hms(2023, 6, 1, 12, 0, 0).unwrap();
+        let entries = vec![
+            EventEntry {
+                date_time: june(1),
+                ..sample_entry("Report")
+            },
+            EventEntry {
+                priority: Priority::Critical,
+                date_time: june(2),
+                ..sample_entry("Release")
+            },
+            EventEntry {
+                priority: Priority::Low,
+                date_time: june(3),
+                tags: parse_tags("Home"),
+                ..sample_entry("Groceries")
+            },
+        ];
+        let titles = |query: EventQuery| -> Vec<String> {
+            query
+                .apply(entries.clone(), &UrgencyConfig::default(), now)
+                .into_iter()
+                .map(|x| x.title)
+                .collect()
+        };
+
+        // The critical release due tomorrow comes before the report due today
+        assert_eq!(
+            titles(EventQuery::new().sort_by(SortKey::Urgency, SortDirection::Descending)),
+            vec!["Release", "Groceries", "Report"]
+        );
+        assert_eq!(
+            titles(EventQuery::new().min_priority(Priority::Low)),
+            vec!["Release", "Groceries"]
+        );
+        assert_eq!(
+            EventQuery::new()
+                .min_priority(Priority::High)
+                .priority_names(),
+            Some(vec!["high", "critical"])
+        );
+        assert_eq!(
+            EventQuery::new()
+                .min_priority(Priority::None)
+                .priority_names(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_project() {
+        let project_id = ObjectId::new();
+        let entries = vec![
+            EventEntry {
+                project_id: Some(project_id),
+                date_time: june(1),
+                ..sample_entry("Report")
+            },
+            EventEntry {
+                date_time: june(2),
+                tags: parse_tags("Home"),
+                ..sample_entry("Groceries")
+            },
+        ];
+        let titles = |query: EventQuery| -> Vec<String> {
+            query
+                .apply(entries.clone(), &UrgencyConfig::default(), Utc::now())
+                .into_iter()
+                .map(|x| x.title)
+                .collect()
+        };
+        assert_eq!(
+            titles(EventQuery::new().in_project(project_id)),
+            vec!["Report"]
+        );
+        assert_eq!(
+            titles(EventQuery::new().without_project()),
+            vec!["Groceries"]
+        );
+        assert!(titles(EventQuery::new().in_project(ObjectId::new())).is_empty());
+
+        let filter = EventQuery::new().without_project().to_mongo_filter();
+        let conditions = filter.get_array("$and").unwrap();
+        assert_eq!(
+            conditions[1].as_document().unwrap(),
+            &doc! { "project_id": null }
+        );
+    }
+
+    #[test]
+    fn test_to_mongo_filter() {
+        let filter = EventQuery::new().done(true).text("a.b").to_mongo_filter();
+        let conditions = filter.get_array("$and").unwrap();
+        assert_eq!(
+            conditions[0].as_document().unwrap(),
+            &doc! { "deleted_at": null }
+        );
+        assert_eq!(
+            conditions[1].as_document().unwrap(),
+            &doc! { "is_done": true }
+        );
+        let text = conditions[2]
+            .as_document()
+            .unwrap()
+            .get_array("$or")
+            .unwrap();
+        assert_eq!(
+            text[0].as_document().unwrap(),
+            &doc! { "title": case_insensitive_regex(String::from(r"a\.b")) }
+        );
+
+        let filter = EventQuery::new().tag(" Work ").to_mongo_filter();
+        let conditions = filter.get_array("$and").unwrap();
+        assert_eq!(
+            conditions[1].as_document().unwrap(),
+            &doc! { "tags": "work" }
+        );
+    }
+}
@@ -0,0 +1,692 @@
+//! Recurring events, following the RRULE and EXDATE properties of RFC 5545 (iCalendar).
+//! A recurring event keeps the date of its next occurrence in date_time; the rule works out the
+//! occurrences after it, in the time zone of the store so an event keeps its time of day when
+//! the clocks change. Completing an occurrence moves the event on to the one after.
+
+use crate::timezone::local_to_utc;
+use crate::{EventEntry, MiddlewareError, Tz};
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, Utc, Weekday};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fmt;
+
+/// Periods in a row without any occurrence after which a rule is taken to have none left, e.g.
+/// "every 12 months on the 30th" started on a February 30th which doesn't exist
+const MAX_EMPTY_PERIODS: u32 = 1000;
+
+/// How often an event repeats
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+impl Frequency {
+    /// Name of the frequency in an RRULE
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Frequency::Daily => "DAILY",
+            Frequency::Weekly => "WEEKLY",
+            Frequency::Monthly => "MONTHLY",
+            Frequency::Yearly => "YEARLY",
+        }
+    }
+}
+
+/// ByDay is one entry of the BYDAY part of a rule: a day of the week, or with nth set the nth
+/// such day of the month (MONTHLY) or year (YEARLY); a negative nth counts from the end, so
+/// `ByDay::nth(-1, Weekday::Fri)` is the last Friday
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByDay {
+    pub nth: Option<i32>,
+    pub weekday: Weekday,
+}
+
+impl ByDay {
+    /// Every such day of the week
+    pub fn every(weekday: Weekday) -> Self {
+        ByDay { nth: None, weekday }
+    }
+
+    /// The nth such day of the month or year
+    pub fn nth(nth: i32, weekday: Weekday) -> Self {
+        ByDay {
+            nth: Some(nth),
+            weekday,
+        }
+    }
+}
+
+/// Recurrence is the rule an event repeats by, built with chained calls, e.g.
+/// `Recurrence::new(Frequency::Weekly).on(Weekday::Mon).on(Weekday::Wed).count(10)`.
+/// It is stored as its RRULE text, with an EXDATE line listing the exceptions.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Recurrence {
+    /// How often the event repeats
+    pub frequency: Frequency,
+    /// Repeats every interval days, weeks, months or years; at least 1
+    pub interval: u32,
+    /// Days of the week the event falls on; empty for the day of the first occurrence
+    pub by_day: Vec<ByDay>,
+    /// Number of occurrences, counting the skipped ones
+    pub count: Option<u32>,
+    /// Time of the last possible occurrence
+    pub until: Option<DateTime<Utc>>,
+    /// Occurrences which are skipped
+    pub exceptions: Vec<DateTime<Utc>>,
+}
+
+impl Recurrence {
+    /// Repeats every day, week, month or year, forever
+    pub fn new(frequency: Frequency) -> Self {
+        Recurrence {
+            frequency,
+            interval: 1,
+            by_day: Vec::new(),
+            count: None,
+            until: None,
+            exceptions: Vec::new(),
+        }
+    }
+
+    /// Repeats every interval periods instead of every one
+    pub fn interval(mut self, interval: u32) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Falls on every such day of the week; can be called several times for several days
+    pub fn on(mut self, weekday: Weekday) -> Self {
+        self.by_day.push(ByDay::every(weekday));
+        self
+    }
+
+    /// Falls on the nth such day of the month or year
+    pub fn on_nth(mut self, nth: i32, weekday: Weekday) -> Self {
+        self.by_day.push(ByDay::nth(nth, weekday));
+        self
+    }
+
+    /// Ends after this many occurrences
+    pub fn count(mut self, count: u32) -> Self {
+        self.count = Some(count);
+        self
+    }
+
+    /// Ends at this time; an occurrence at exactly this time still happens
+    pub fn until(mut self, until: DateTime<Utc>) -> Self {
+        self.until = Some(until);
+        self
+    }
+
+    /// Skips the occurrence at this time
+    pub fn except(mut self, date_time: DateTime<Utc>) -> Self {
+        self.exceptions.push(date_time);
+        self
+    }
+
+    /// The occurrences of an event first occurring at start, in order, leaving out the exceptions;
+    /// start itself is the first one even if the rule wouldn't pick it
+    pub fn occurrences(
+        &self,
+        start: DateTime<Utc>,
+        tz: &Tz,
+    ) -> impl Iterator<Item = DateTime<Utc>> + '_ {
+        Candidates::new(self, start, tz).filter(|x| !self.exceptions.contains(x))
+    }
+
+    /// Checks the parts of the rule which RFC 5545 restricts
+    fn validate(&self) -> Result<(), MiddlewareError> {
+        let invalid = |message: &str| Err(MiddlewareError::Validation(message.to_string()));
+        if self.interval == 0 {
+            return invalid("INTERVAL must be at least 1");
+        }
+        if self.count.is_some() && self.until.is_some() {
+            return invalid("COUNT and UNTIL can't both be set");
+        }
+        let has_nth = self.by_day.iter().any(|x| x.nth.is_some());
+        if has_nth && matches!(self.frequency, Frequency::Daily | Frequency::Weekly) {
+            return invalid("BYDAY can only number days in a MONTHLY or YEARLY rule");
+        }
+        match self.by_day.iter().find_map(|x| x.nth) {
+            Some(nth) if nth == 0 || nth.abs() > 53 => invalid("BYDAY numbers run from 1 to 53"),
+            _ => Ok(()),
+        }
+    }
+
+    /// Days of the period the given number of intervals after the one of start, in order;
+    /// None once the dates run past what chrono can represent
+    fn period_dates(&self, start: NaiveDate, period: i64) -> Option<Vec<NaiveDate>> {
+        let step = i64::from(self.interval).checked_mul(period)?;
+        let is_on_by_day = |date: &NaiveDate| {
+            self.by_day.is_empty() || self.by_day.iter().any(|x| x.weekday == date.weekday())
+        };
+        let dates = match self.frequency {
+            Frequency::Daily => {
+                let date = start.checked_add_signed(Duration::try_days(step)?)?;
+                vec![date].into_iter().filter(is_on_by_day).collect()
+            }
+            Frequency::Weekly => {
+                let offset = i64::from(start.weekday().num_days_from_monday());
+                let monday = start
+                    .checked_sub_signed(Duration::days(offset))?
+                    .checked_add_signed(Duration::try_weeks(step)?)?;
+                if self.by_day.is_empty() {
+                    vec![monday + Duration::days(offset)]
+                } else {
+                    (0..7)
+                        .map(|x| monday + Duration::days(x))
+                        .filter(is_on_by_day)
+                        .collect()
+                }
+            }
+            Frequency::Monthly => {
+                let months = i64::from(start.year()) * 12 + i64::from(start.month0()) + step;
+                let year = i32::try_from(months.div_euclid(12)).ok()?;
+                let month = months.rem_euclid(12) as u32 + 1;
+                let first = NaiveDate::from_ymd_opt(year, month, 1)?;
+                if self.by_day.is_empty() {
+                    // Months without the day, like February 30th, are skipped
+                    NaiveDate::from_ymd_opt(year, month, start.day())
+                        .into_iter()
+                        .collect()
+                } else {
+                    self.days_in(first, first.checked_add_months(chrono::Months::new(1))?)
+                }
+            }
+            Frequency::Yearly => {
+                let year = i32::try_from(i64::from(start.year()) + step).ok()?;
+                let first = NaiveDate::from_ymd_opt(year, 1, 1)?;
+                if self.by_day.is_empty() {
+                    NaiveDate::from_ymd_opt(year, start.month(), start.day())
+                        .into_iter()
+                        .collect()
+                } else {
+                    self.days_in(first, NaiveDate::from_ymd_opt(year + 1, 1, 1)?)
+                }
+            }
+        };
+        Some(dates)
+    }
+
+    /// Days from first up to end (excluded) picked by BYDAY, in order
+    fn days_in(&self, first: NaiveDate, end: NaiveDate) -> Vec<NaiveDate> {
+        let days: Vec<NaiveDate> = first.iter_days().take_while(|x| *x < end).collect();
+        let mut picked: Vec<NaiveDate> = Vec::new();
+        for by_day in &self.by_day {
+            let matching: Vec<NaiveDate> = days
+                .iter()
+                .copied()
+                .filter(|x| x.weekday() == by_day.weekday)
+                .collect();
+            match by_day.nth {
+                None => picked.extend(matching),
+                Some(nth) if nth > 0 => picked.extend(matching.get(nth as usize - 1)),
+                Some(nth) => picked.extend(
+                    matching
+                        .len()
+                        .checked_sub(nth.unsigned_abs() as usize)
+                        .map(|x| matching[x]),
+                ),
+            }
+        }
+        picked.sort();
+        picked.dedup();
+        picked
+    }
+}
+
+/// Every occurrence picked by the rule, the exceptions included, up to COUNT and UNTIL
+struct Candidates<'a> {
+    rule: &'a Recurrence,
+    tz: Tz,
+    /// The first occurrence, until it has been returned
+    start: Option<DateTime<Utc>>,
+    start_date: NaiveDate,
+    time: NaiveTime,
+    /// Next period to work out the dates of
+    period: i64,
+    /// Dates of the current period still to return
+    pending: VecDeque<NaiveDate>,
+    /// Occurrences returned so far, for COUNT
+    returned: u32,
+    /// Periods in a row without any date
+    empty_periods: u32,
+}
+
+impl<'a> Candidates<'a> {
+    fn new(rule: &'a Recurrence, start: DateTime<Utc>, tz: &Tz) -> Self {
+        let local = start.with_timezone(tz).naive_local();
+        Candidates {
+            rule,
+            tz: *tz,
+            start: Some(start),
+            start_date: local.date(),
+            time: local.time(),
+            period: 0,
+            pending: VecDeque::new(),
+            returned: 0,
+            empty_periods: 0,
+        }
+    }
+
+    /// The next date picked by the rule, ignoring COUNT and UNTIL
+    fn next_date_time(&mut self) -> Option<DateTime<Utc>> {
+        if let Some(start) = self.start.take() {
+            return Some(start);
+        }
+        while self.pending.is_empty() {
+            if self.empty_periods >= MAX_EMPTY_PERIODS {
+                return None;
+            }
+            let dates = self.rule.period_dates(self.start_date, self.period)?;
+            self.period += 1;
+            // The period of start also holds the days before it
+            self.pending
+                .extend(dates.into_iter().filter(|x| *x > self.start_date));
+            if self.pending.is_empty() {
+                self.empty_periods += 1;
+            } else {
+                self.empty_periods = 0;
+            }
+        }
+        let date = self.pending.pop_front()?;
+        Some(local_to_utc(NaiveDateTime::new(date, self.time), &self.tz))
+    }
+}
+
+impl Iterator for Candidates<'_> {
+    type Item = DateTime<Utc>;
+
+    fn next(&mut self) -> Option<DateTime<Utc>> {
+        if matches!(self.rule.count, Some(count) if self.returned >= count) {
+            return None;
+        }
+        let date_time = self.next_date_time()?;
+        if matches!(self.rule.until, Some(until) if date_time > until) {
+            return None;
+        }
+        self.returned += 1;
+        Some(date_time)
+    }
+}
+
+/// Parses an RRULE date: a UTC time such as 20231231T235959Z, or a date such as 20231231 which
+/// lasts until the end of that day in UTC
+fn parse_rule_date(value: &str) -> Option<DateTime<Utc>> {
+    if let Ok(date_time) = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%SZ") {
+        return Some(date_time.and_utc());
+    }
+    let date = NaiveDate::parse_from_str(value, "%Y%m%d").ok()?;
+    Some(date.and_hms_opt(23, 59, 59)?.and_utc())
+}
+
+fn format_rule_date(date_time: &DateTime<Utc>) -> String {
+    date_time.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+fn parse_weekday(value: &str) -> Option<Weekday> {
+    Some(match value {
+        "MO" => Weekday::Mon,
+        "TU" => Weekday::Tue,
+        "WE" => Weekday::Wed,
+        "TH" => Weekday::Thu,
+        "FR" => Weekday::Fri,
+        "SA" => Weekday::Sat,
+        "SU" => Weekday::Sun,
+        _ => return None,
+    })
+}
+
+fn format_weekday(weekday: Weekday) -> &'static str {
+    match weekday {
+        Weekday::Mon => "MO",
+        Weekday::Tue => "TU",
+        Weekday::Wed => "WE",
+        Weekday::Thu => "TH",
+        Weekday::Fri => "FR",
+        Weekday::Sat => "SA",
+        Weekday::Sun => "SU",
+    }
+}
+
+impl fmt::Display for Recurrence {
+    /// Writes the rule as `RRULE:FREQ=...`, followed by an `EXDATE:` line when it has exceptions
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "RRULE:FREQ={}", self.frequency.as_str())?;
+        if self.interval != 1 {
+            write!(f, ";INTERVAL={}", self.interval)?;
+        }
+        if !self.by_day.is_empty() {
+            let days: Vec<String> = self
+                .by_day
+                .iter()
+                .map(|x| {
+                    let nth = x.nth.map(|nth| nth.to_string()).unwrap_or_default();
+                    format!("{}{}", nth, format_weekday(x.weekday))
+                })
+                .collect();
+            write!(f, ";BYDAY={}", days.join(","))?;
+        }
+        if let Some(count) = self.count {
+            write!(f, ";COUNT={}", count)?;
+        }
+        if let Some(until) = &self.until {
+            write!(f, ";UNTIL={}", format_rule_date(until))?;
+        }
+        if !self.exceptions.is_empty() {
+            let dates: Vec<String> = self.exceptions.iter().map(format_rule_date).collect();
+            write!(f, "\nEXDATE:{}", dates.join(","))?;
+        }
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for Recurrence {
+    type Err = MiddlewareError;
+
+    /// Reads an RRULE line, with or without its `RRULE:` name, and the `EXDATE:` lines after it;
+    /// parts of RFC 5545 which Krabby Do can't expand, like BYMONTH, are refused
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |part: &str| {
+            MiddlewareError::Validation(format!("Unsupported recurrence rule part \"{}\"", part))
+        };
+        let mut lines = s.lines().map(str::trim).filter(|x| !x.is_empty());
+        let rule = lines.next().unwrap_or_default();
+        let rule = rule.strip_prefix("RRULE:").unwrap_or(rule);
+
+        let mut frequency = None;
+        let mut recurrence = Recurrence::new(Frequency::Daily);
+        for part in rule.split(';').filter(|x| !x.is_empty()) {
+            let (name, value) = part.split_once('=').ok_or_else(|| invalid(part))?;
+            match name.to_ascii_uppercase().as_str() {
+                "FREQ" => {
+                    frequency = Some(match value.to_ascii_uppercase().as_str() {
+                        "DAILY" => Frequency::Daily,
+                        "WEEKLY" => Frequency::Weekly,
+                        "MONTHLY" => Frequency::Monthly,
+                        "YEARLY" => Frequency::Yearly,
+                        _ => return Err(invalid(part)),
+                    })
+                }
+                "INTERVAL" => recurrence.interval = value.parse().map_err(|_| invalid(part))?,
+                "COUNT" => recurrence.count = Some(value.parse().map_err(|_| invalid(part))?),
+                "UNTIL" => {
+                    recurrence.until = Some(parse_rule_date(value).ok_or_else(|| invalid(part))?)
+                }
+                "BYDAY" => {
+                    for day in value.split(',') {
+                        let day = day.trim().to_ascii_uppercase();
+                        let (nth, weekday) = day.split_at(day.len().saturating_sub(2));
+                        let weekday = parse_weekday(weekday).ok_or_else(|| invalid(part))?;
+                        let nth = match nth {
+                            "" => None,
+                            nth => Some(
+                                nth.trim_start_matches('+')
+                                    .parse()
+                                    .map_err(|_| invalid(part))?,
+                            ),
+                        };
+                        recurrence.by_day.push(ByDay { nth, weekday });
+                    }
+                }
+                // Weeks start on Monday, which is the default
+                "WKST" if value.eq_ignore_ascii_case("MO") => {}
+                _ => return Err(invalid(part)),
+            }
+        }
+        recurrence.frequency = frequency.ok_or_else(|| invalid(rule))?;
+
+        for line in lines {
+            let dates = line.strip_prefix("EXDATE:").ok_or_else(|| invalid(line))?;
+            for date in dates.split(',') {
+                recurrence
+                    .exceptions
+                    .push(parse_rule_date(date.trim()).ok_or_else(|| invalid(line))?);
+            }
+        }
+        recurrence.validate()?;
+        Ok(recurrence)
+    }
+}
+
+impl TryFrom<String> for Recurrence {
+    type Error = MiddlewareError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Recurrence> for String {
+    fn from(value: Recurrence) -> Self {
+        value.to_string()
+    }
+}
+
+/// Occurrence is one occurrence of an event, as listed by EventStore::expand_occurrences
+#[derive(Debug, Clone, PartialEq)]
+pub struct Occurrence {
+    /// The event which occurs
+    pub entry: EventEntry,
+    /// When it occurs
+    pub date_time: DateTime<Utc>,
+}
+
+/// Lists the occurrences of the event from (inclusive) until (exclusive). Events done or without
+/// a recurrence occur once, at their date; events without a date never occur.
+pub fn occurrences_between(
+    entry: &EventEntry,
+    from: DateTime<Utc>,
+    until: DateTime<Utc>,
+    tz: &Tz,
+) -> Vec<Occurrence> {
+    let Some(start) = entry.date_time else {
+        return Vec::new();
+    };
+    let dates: Vec<DateTime<Utc>> = match &entry.recurrence {
+        Some(recurrence) if !entry.is_done => recurrence
+            .occurrences(start, tz)
+            .take_while(|x| *x < until)
+            .filter(|x| *x >= from)
+            .collect(),
+        _ => vec![start]
+            .into_iter()
+            .filter(|x| (from..until).contains(x))
+            .collect(),
+    };
+    dates
+        .into_iter()
+        .map(|date_time| Occurrence {
+            entry: entry.clone(),
+            date_time,
+        })
+        .collect()
+}
+
+/// The event once its current occurrence is done: a recurring event moves on to its next
+/// occurrence and stays not done, with its COUNT lowered by the occurrences it went past, the
+/// exceptions left behind dropped and its subtasks open again. The event is marked done when it
+/// has no occurrence left.
+pub fn complete_occurrence(entry: &EventEntry, tz: &Tz) -> EventEntry {
+    let mut completed = entry.clone();
+    let (Some(recurrence), Some(start)) = (&entry.recurrence, entry.date_time) else {
+        completed.is_done = true;
+        return completed;
+    };
+    let next = Candidates::new(recurrence, start, tz)
+        .enumerate()
+        .skip(1)
+        .find(|(_, x)| !recurrence.exceptions.contains(x));
+    match next {
+        Some((passed, next)) => {
+            let mut advanced = recurrence.clone();
+            advanced.count = recurrence.count.map(|x| x - passed as u32);
+            advanced.exceptions.retain(|x| *x > next);
+            completed.date_time = Some(next);
+            completed.recurrence = Some(advanced);
+            completed.is_done = false;
+            for subtask in &mut completed.subtasks {
+                subtask.is_done = false;
+            }
+        }
+        None => completed.is_done = true,
+    }
+    completed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use mongodb::bson::oid::ObjectId;
+
+    fn utc(year: i32, month: u32, day: u32, hour: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(year, month, day, hour, 0, 0).unwrap()
+    }
+
+    fn first(rule: &str, start: DateTime<Utc>, n: usize) -> Vec<DateTime<Utc>> {
+        let recurrence: Recurrence = rule.parse().unwrap();
+        recurrence.occurrences(start, &Tz::UTC).take(n).collect()
+    }
+
+    #[test]
+    fn test_parse_and_format() {
+        let text = "RRULE:FREQ=MONTHLY;INTERVAL=2;BYDAY=MO,-1FR;COUNT=10\nEXDATE:20230612T170000Z";
+        let recurrence: Recurrence = text.parse().unwrap();
+        assert_eq!(
+            recurrence,
+            Recurrence::new(Frequency::Monthly)
+                .interval(2)
+                .on(Weekday::Mon)
+                .on_nth(-1, Weekday::Fri)
+                .count(10)
+                .except(utc(2023, 6, 12, 17))
+        );
+        assert_eq!(recurrence.to_string(), text);
+        assert_eq!(
+            "FREQ=weekly;UNTIL=20231231".parse::<Recurrence>().unwrap(),
+            Recurrence::new(Frequency::Weekly)
+                .until(Utc.with_ymd_and_hms(2023, 12, 31, 23, 59, 59).unwrap())
+        );
+        for invalid in [
+            "FREQ=HOURLY",
+            "FREQ=DAILY;BYMONTH=1",
+            "FREQ=DAILY;INTERVAL=0",
+            "FREQ=DAILY;COUNT=2;UNTIL=20231231",
+            "FREQ=WEEKLY;BYDAY=1MO",
+            "INTERVAL=2",
+        ] {
+            assert!(invalid.parse::<Recurrence>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_occurrences() {
+        // Friday June 9th 2023
+        let start = utc(2023, 6, 9, 17);
+        assert_eq!(
+            first("FREQ=DAILY;INTERVAL=3", start, 3),
+            vec![start, utc(2023, 6, 12, 17), utc(2023, 6, 15, 17)]
+        );
+        // Weekdays only
+        assert_eq!(
+            first("FREQ=DAILY;BYDAY=MO,TU,WE,TH,FR", start, 3),
+            vec![start, utc(2023, 6, 12, 17), utc(2023, 6, 13, 17)]
+        );
+        // Every other week on Monday and Friday, from the week of start
+        assert_eq!(
+            first("FREQ=WEEKLY;INTERVAL=2;BYDAY=MO,FR", start, 4),
+            vec![
+                start,
+                utc(2023, 6, 19, 17),
+                utc(2023, 6, 23, 17),
+                utc(2023, 7, 3, 17)
+            ]
+        );
+        // Months without a 31st are skipped
+        assert_eq!(
+            first("FREQ=MONTHLY", utc(2023, 1, 31, 9), 3),
+            vec![
+                utc(2023, 1, 31, 9),
+                utc(2023, 3, 31, 9),
+                utc(2023, 5, 31, 9)
+            ]
+        );
+        // The last Friday of the month
+        assert_eq!(
+            first("FREQ=MONTHLY;BYDAY=-1FR", start, 3),
+            vec![start, utc(2023, 6, 30, 17), utc(2023, 7, 28, 17)]
+        );
+        // February 29th only comes in leap years
+        assert_eq!(
+            first("FREQ=YEARLY", utc(2024, 2, 29, 9), 2),
+            vec![utc(2024, 2, 29, 9), utc(2028, 2, 29, 9)]
+        );
+        // COUNT includes the skipped occurrence, UNTIL includes its own time
+        assert_eq!(
+            first("FREQ=DAILY;COUNT=3\nEXDATE:20230610T170000Z", start, 10),
+            vec![start, utc(2023, 6, 11, 17)]
+        );
+        assert_eq!(
+            first("FREQ=DAILY;UNTIL=20230610T170000Z", start, 10),
+            vec![start, utc(2023, 6, 10, 17)]
+        );
+    }
+
+    #[test]
+    fn test_occurrences_keep_local_time() {
+        let tz: Tz = "America/Los_Angeles".parse().unwrap();
+        // 9 AM in Portland on the Saturday before the clocks go back
+        let start = utc(2023, 11, 4, 16);
+        let weekly = Recurrence::new(Frequency::Weekly);
+        let dates: Vec<DateTime<Utc>> = weekly.occurrences(start, &tz).take(2).collect();
+        assert_eq!(dates, vec![start, utc(2023, 11, 11, 17)]);
+    }
+
+    #[test]
+    fn test_complete_occurrence() {
+        let tz = Tz::UTC;
+        let start = utc(2023, 6, 9, 17);
+        let entry = EventEntry {
+            recurrence: Some(
+                Recurrence::new(Frequency::Daily)
+                    .count(4)
+                    .except(utc(2023, 6, 10, 17)),
+            ),
+            ..EventEntry::new(
+                ObjectId::new(),
+                String::from("Standup"),
+                String::from("Details"),
+                start,
+                false,
+                String::from("Work"),
+            )
+        };
+        assert_eq!(
+            occurrences_between(&entry, start, utc(2023, 6, 11, 17), &tz)
+                .iter()
+                .map(|x| x.date_time)
+                .collect::<Vec<_>>(),
+            vec![start]
+        );
+
+        // The skipped 10th is passed over and counted
+        let second = complete_occurrence(&entry, &tz);
+        assert!(!second.is_done);
+        assert_eq!(second.date_time, Some(utc(2023, 6, 11, 17)));
+        assert_eq!(
+            second.recurrence,
+            Some(Recurrence::new(Frequency::Daily).count(2))
+        );
+        let third = complete_occurrence(&second, &tz);
+        assert_eq!(third.date_time, Some(utc(2023, 6, 12, 17)));
+        // The last occurrence completes the event
+        let last = complete_occurrence(&third, &tz);
+        assert!(last.is_done);
+        assert_eq!(last.date_time, third.date_time);
+    }
+}
@@ -0,0 +1,136 @@
+//! Full-text search over the title, details and tags of the events.
+//! MongoDB and SQLite rank the hits with their own text index; MemoryStore scores them here,
+//! with the same weights per field and a simple English stemmer.
+
+use crate::tags::tags_to_text;
+use crate::EventEntry;
+
+/// SearchHit is an event matched by a search along with how well it matched
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchHit {
+    /// The matching event
+    pub entry: EventEntry,
+    /// Relevance of the event; higher is better and only comparable within one search
+    pub score: f64,
+}
+
+/// Weight of a word found in the title
+pub(crate) const TITLE_WEIGHT: f64 = 10.0;
+/// Weight of a word found in the tags
+pub(crate) const TAGS_WEIGHT: f64 = 5.0;
+/// Weight of a word found in the details
+pub(crate) const DETAILS_WEIGHT: f64 = 1.0;
+
+/// Splits the text into lowercase words; punctuation separates words and is dropped
+pub(crate) fn search_terms(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+        .collect()
+}
+
+/// Reduces an English word to its stem, so "meetings" and "meeting" both match "meet", and
+/// "parties" matches "party"
+fn stem(word: &str) -> String {
+    if word.len() > 4 && word.ends_with("ies") {
+        return format!("{}y", &word[..word.len() - 3]);
+    }
+    let word = if word.len() > 3 && word.ends_with('s') && !word.ends_with("ss") {
+        &word[..word.len() - 1]
+    } else {
+        word
+    };
+    for suffix in ["ing", "ed"] {
+        if word.len() > suffix.len() + 2 && word.ends_with(suffix) {
+            let stem = &word[..word.len() - suffix.len()];
+            // "planning" is "plan" with its last consonant doubled
+            let mut last = stem.chars().rev();
+            return match (last.next(), last.next()) {
+                (Some(a), Some(b)) if a == b && !"aeioulsz".contains(a) => {
+                    stem[..stem.len() - a.len_utf8()].to_string()
+                }
+                _ => stem.to_string(),
+            };
+        }
+    }
+    word.to_string()
+}
+
+/// Relevance of the event for the search terms; zero when no term matches
+pub(crate) fn score(entry: &EventEntry, terms: &[String]) -> f64 {
+    let stems: Vec<String> = terms.iter().map(|term| stem(term)).collect();
+    let tags = tags_to_text(&entry.tags);
+    [
+        (Some(&entry.title), TITLE_WEIGHT),
+        (Some(&tags), TAGS_WEIGHT),
+        (entry.details.as_ref(), DETAILS_WEIGHT),
+    ]
+    .iter()
+    .filter_map(|(field, weight)| Some((field.as_ref()?, weight)))
+    .map(|(field, weight)| {
+        let matches = search_terms(field)
+            .iter()
+            .filter(|word| stems.contains(&stem(word)))
+            .count();
+        matches as f64 * weight
+    })
+    .sum()
+}
+
+/// Orders the hits with the best match first, then by date
+pub(crate) fn rank(hits: &mut [SearchHit]) {
+    hits.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then_with(|| a.entry.date_time.cmp(&b.entry.date_time))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use mongodb::bson::oid::ObjectId;
+
+    #[test]
+    fn test_score() {
+        let entry = EventEntry::new(
+            ObjectId::new(),
+            String::from("Team meetings"),
+            String::from("Meet the designers, then plan the sprint"),
+            Utc::now(),
+            false,
+            String::from("Work"),
+        );
+        assert_eq!(
+            search_terms("Plan: sprint-review!"),
+            vec!["plan", "sprint", "review"]
+        );
+        assert_eq!(
+            score(&entry, &search_terms("meeting")),
+            TITLE_WEIGHT + DETAILS_WEIGHT
+        );
+        assert_eq!(
+            score(&entry, &search_terms("work planning")),
+            TAGS_WEIGHT + DETAILS_WEIGHT
+        );
+        assert_eq!(score(&entry, &search_terms("groceries")), 0.0);
+
+        let entry = EventEntry::new(
+            ObjectId::new(),
+            String::from("Plan the parties"),
+            String::from("Invite everyone to the party"),
+            Utc::now(),
+            false,
+            String::from("Home"),
+        );
+        assert_eq!(
+            score(&entry, &search_terms("party")),
+            TITLE_WEIGHT + DETAILS_WEIGHT
+        );
+        assert_eq!(
+            score(&entry, &search_terms("parties")),
+            TITLE_WEIGHT + DETAILS_WEIGHT
+        );
+    }
+}
@@ -0,0 +1,1837 @@
+//! SQLite implementation of the EventStore, meant for single-user installs which don't run MongoDB.
+//! The schema is created automatically when the database file is opened, and upgraded by versioned
+//! steps whose progress is recorded in `PRAGMA user_version`.
+
+use crate::bulk::selected_ids;
+use crate::dependencies::{blockers_from_text, blockers_to_text, check_new_blockers};
+use crate::history::{
+    default_actor, has_changes, update_action, FieldChange, Revision, RevisionAction,
+};
+use crate::query::SortDirection;
+use crate::search::{search_terms, DETAILS_WEIGHT, TAGS_WEIGHT, TITLE_WEIGHT};
+use crate::tags::{parse_tags, tags_to_text};
+use crate::timezone::{day_bounds, local_timezone, today};
+use crate::{
+    EventEntry, EventPatch, EventQuery, EventSelection, EventStore, IndexReport, MiddlewareError,
+    MigrationReport, Project, SearchHit, SortKey, StepReport, Subtask, Tz, UrgencyConfig,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use mongodb::bson::oid::ObjectId;
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection, ErrorCode, Row};
+use std::path::Path;
+use std::sync::Mutex;
+
+/// Creates the "todos" table; date_time is stored as milliseconds since the Unix epoch in UTC
+/// so that range queries compare real instants
+const CREATE_TODOS: &str = "
+    CREATE TABLE IF NOT EXISTS todos (
+        id        TEXT PRIMARY KEY NOT NULL,
+        title     TEXT NOT NULL,
+        details   TEXT NOT NULL,
+        date_time INTEGER NOT NULL,
+        is_done   INTEGER NOT NULL,
+        tags      TEXT NOT NULL
+    );
+";
+
+/// Adds the time an event was moved to the trash, in milliseconds since the Unix epoch;
+/// NULL while the event isn't in the trash
+const ADD_DELETED_AT: &str = "
+    ALTER TABLE todos ADD COLUMN deleted_at INTEGER;
+";
+
+/// Creates the tables of the revision history; the changed fields of a revision are kept
+/// in their own table, one row per field, in the order they were listed
+const CREATE_REVISIONS: &str = "
+    CREATE TABLE IF NOT EXISTS revisions (
+        id        TEXT PRIMARY KEY NOT NULL,
+        event_id  TEXT NOT NULL,
+        action    TEXT NOT NULL,
+        timestamp INTEGER NOT NULL,
+        actor     TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS revisions_event_id ON revisions (event_id, timestamp);
+    CREATE TABLE IF NOT EXISTS revision_changes (
+        revision_id TEXT NOT NULL REFERENCES revisions (id),
+        position    INTEGER NOT NULL,
+        field       TEXT NOT NULL,
+        old_value   TEXT NOT NULL,
+        new_value   TEXT NOT NULL,
+        PRIMARY KEY (revision_id, position)
+    );
+";
+
+/// Creates the full-text index of the events, kept up to date by triggers on the todos table.
+/// The porter tokenizer reduces words to their stem so that other forms of a word match.
+const CREATE_SEARCH_INDEX: &str = "
+    CREATE VIRTUAL TABLE IF NOT EXISTS todos_search USING fts5(
+        title, details, tags,
+        content = 'todos', content_rowid = 'rowid', tokenize = 'porter unicode61'
+    );
+    CREATE TRIGGER IF NOT EXISTS todos_search_insert AFTER INSERT ON todos BEGIN
+        INSERT INTO todos_search (rowid, title, details, tags)
+        VALUES (new.rowid, new.title, new.details, new.tags);
+    END;
+    CREATE TRIGGER IF NOT EXISTS todos_search_delete AFTER DELETE ON todos BEGIN
+        INSERT INTO todos_search (todos_search, rowid, title, details, tags)
+        VALUES ('delete', old.rowid, old.title, old.details, old.tags);
+    END;
+    CREATE TRIGGER IF NOT EXISTS todos_search_update AFTER UPDATE OF title, details, tags ON todos
+    BEGIN
+        INSERT INTO todos_search (todos_search, rowid, title, details, tags)
+        VALUES ('delete', old.rowid, old.title, old.details, old.tags);
+        INSERT INTO todos_search (rowid, title, details, tags)
+        VALUES (new.rowid, new.title, new.details, new.tags);
+    END;
+    INSERT INTO todos_search (todos_search) VALUES ('rebuild');
+";
+
+/// Adds the number of times an event was updated, checked by updates so they don't overwrite
+/// changes made since the event was loaded
+const ADD_VERSION: &str = "
+    ALTER TABLE todos ADD COLUMN version INTEGER NOT NULL DEFAULT 0;
+";
+
+/// Lets details, date_time and tags be NULL for events which have none. SQLite can't change a
+/// column constraint in place, so the table is copied into a new one; the rowids are kept since the
+/// full-text index refers to them, and its triggers, dropped along with the old table, come back
+/// with the next step.
+const OPTIONAL_FIELDS: &str = "
+    CREATE TABLE todos_optional (
+        id         TEXT PRIMARY KEY NOT NULL,
+        title      TEXT NOT NULL,
+        details    TEXT,
+        date_time  INTEGER,
+        is_done    INTEGER NOT NULL,
+        tags       TEXT,
+        deleted_at INTEGER,
+        version    INTEGER NOT NULL DEFAULT 0
+    );
+    INSERT INTO todos_optional (rowid, id, title, details, date_time, is_done, tags, deleted_at, version)
+    SELECT rowid, id, title, details, date_time, is_done, tags, deleted_at, version FROM todos;
+    DROP TABLE todos;
+    ALTER TABLE todos_optional RENAME TO todos;
+";
+
+/// Adds the rule recurring events repeat by, kept as its RRULE text
+const ADD_RECURRENCE: &str = "
+    ALTER TABLE todos ADD COLUMN recurrence TEXT;
+";
+
+/// Creates the table of the subtasks, one row per step in the order of the checklist, removed
+/// along with their event by a trigger; and adds the flag completing an event with its subtasks
+const CREATE_SUBTASKS: &str = "
+    CREATE TABLE subtasks (
+        event_id TEXT NOT NULL,
+        position INTEGER NOT NULL,
+        id       TEXT NOT NULL,
+        title    TEXT NOT NULL,
+        is_done  INTEGER NOT NULL,
+        PRIMARY KEY (event_id, position)
+    );
+    CREATE TRIGGER todos_subtasks_delete AFTER DELETE ON todos BEGIN
+        DELETE FROM subtasks WHERE event_id = old.id;
+    END;
+    ALTER TABLE todos ADD COLUMN auto_complete INTEGER NOT NULL DEFAULT 0;
+";
+
+/// Adds the ids of the events an event waits for, separated by commas
+const ADD_BLOCKED_BY: &str = "
+    ALTER TABLE todos ADD COLUMN blocked_by TEXT NOT NULL DEFAULT '';
+";
+
+/// Adds the priority, stored by name
+const ADD_PRIORITY: &str = "
+    ALTER TABLE todos ADD COLUMN priority TEXT NOT NULL DEFAULT 'none';
+";
+
+/// Tags used to be free text like "Work, home" and NULL for none; they are kept as the text form
+/// of the normalized tags, "work, home", and empty for none. Splitting them is done by split_tags
+/// after this statement.
+const SPLIT_TAGS: &str = "
+    UPDATE todos SET tags = '' WHERE tags IS NULL;
+";
+
+/// Creates the table of the projects, and adds the id of the project an event belongs to; NULL
+/// for the events in the Inbox
+const CREATE_PROJECTS: &str = "
+    CREATE TABLE projects (
+        id       TEXT PRIMARY KEY NOT NULL,
+        name     TEXT NOT NULL,
+        color    TEXT NOT NULL,
+        archived INTEGER NOT NULL DEFAULT 0,
+        position INTEGER NOT NULL DEFAULT 0
+    );
+    ALTER TABLE todos ADD COLUMN project_id TEXT;
+";
+
+/// Drops the index on the tags text which older versions created: the tag filter matches inside
+/// the text with LIKE, which a B-tree index can't serve
+const DROP_TAGS_INDEX: &str = "
+    DROP INDEX IF EXISTS todos_tags;
+";
+
+/// Change SQL can't express, run after the SQL of its step in the same transaction; returns the
+/// ids of the events it changed
+type MigrationFn = fn(&Connection) -> Result<Vec<ObjectId>, MiddlewareError>;
+
+/// An upgrade step: what it does, its SQL, and the function changing what SQL can't
+type Migration = (&'static str, &'static str, Option<MigrationFn>);
+
+/// Upgrade steps of the schema; the version a step upgrades to is its position plus one
+const MIGRATIONS: &[Migration] = &[
+    ("Create the todos table", CREATE_TODOS, None),
+    ("Add the deleted_at column", ADD_DELETED_AT, None),
+    ("Create the revision history tables", CREATE_REVISIONS, None),
+    (
+        "Create the full-text search index",
+        CREATE_SEARCH_INDEX,
+        None,
+    ),
+    ("Add the version column", ADD_VERSION, None),
+    (
+        "Make details, date_time and tags optional",
+        OPTIONAL_FIELDS,
+        None,
+    ),
+    (
+        "Recreate the full-text search triggers",
+        CREATE_SEARCH_INDEX,
+        None,
+    ),
+    ("Add the recurrence column", ADD_RECURRENCE, None),
+    ("Create the subtasks table", CREATE_SUBTASKS, None),
+    ("Add the blocked_by column", ADD_BLOCKED_BY, None),
+    ("Add the priority column", ADD_PRIORITY, None),
+    (
+        "Split the tags into normalized tags",
+        SPLIT_TAGS,
+        Some(split_tags),
+    ),
+    ("Create the projects table", CREATE_PROJECTS, None),
+    ("Drop the index on the tags", DROP_TAGS_INDEX, None),
+];
+
+/// Indexes of the todos table with the statements creating them, created by ensure_indexes when
+/// missing; the full-text index normally exists already since a migration creates it. The tags
+/// have no index since the tag filter looks for a tag inside their text, which scans the table.
+const INDEXES: &[(&str, &str)] = &[
+    (
+        "todos_date_time",
+        "CREATE INDEX todos_date_time ON todos (date_time);",
+    ),
+    (
+        "todos_is_done_date_time",
+        "CREATE INDEX todos_is_done_date_time ON todos (is_done, date_time);",
+    ),
+    (
+        "todos_project_id",
+        "CREATE INDEX todos_project_id ON todos (project_id);",
+    ),
+    ("todos_search", CREATE_SEARCH_INDEX),
+];
+
+const SELECT_EVENTS: &str =
+    "SELECT id, title, details, date_time, is_done, tags, deleted_at, version,
+                                    recurrence, auto_complete, blocked_by, priority, project_id
+                             FROM todos";
+
+/// SqliteStore keeps the events in the "todos" table of a SQLite database file
+#[derive(Debug)]
+pub struct SqliteStore {
+    connection: Mutex<Connection>,
+    /// Recorded as the author of every revision
+    actor: String,
+    /// Zone in which days start and end
+    timezone: Tz,
+    /// Coefficients of the urgency score
+    urgency: UrgencyConfig,
+}
+
+impl SqliteStore {
+    /// Opens (or creates) the database file at the given path
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, MiddlewareError> {
+        if let Some(parent) = path.as_ref().parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        Self::from_connection(Connection::open(path)?)
+    }
+
+    /// Opens a database which only lives in memory, used by tests
+    pub fn open_in_memory() -> Result<Self, MiddlewareError> {
+        Self::from_connection(Connection::open_in_memory()?)
+    }
+
+    fn from_connection(connection: Connection) -> Result<Self, MiddlewareError> {
+        migrate_connection(&connection, false)?;
+        Ok(SqliteStore {
+            connection: Mutex::new(connection),
+            actor: default_actor(),
+            timezone: local_timezone(),
+            urgency: UrgencyConfig::default(),
+        })
+    }
+
+    /// Sets the name recorded as the author of the revisions
+    pub fn with_actor(mut self, actor: &str) -> Self {
+        self.actor = actor.to_string();
+        self
+    }
+
+    /// Sets the zone in which days start and end
+    pub fn with_timezone(mut self, timezone: Tz) -> Self {
+        self.timezone = timezone;
+        self
+    }
+
+    /// Sets the coefficients of the urgency score
+    pub fn with_urgency(mut self, urgency: UrgencyConfig) -> Self {
+        self.urgency = urgency;
+        self
+    }
+
+    /// Adds a revision to the history
+    fn record(
+        &self,
+        action: RevisionAction,
+        old: Option<&EventEntry>,
+        new: &EventEntry,
+    ) -> Result<(), MiddlewareError> {
+        let revision = Revision::new(action, old, new, &self.actor);
+        let connection = self.connection.lock().unwrap();
+        let transaction = connection.unchecked_transaction()?;
+        insert_revision(&transaction, &revision)?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Runs a SELECT over the "todos" table with the given WHERE clause
+    fn select_events(
+        &self,
+        where_clause: &str,
+        params: impl rusqlite::Params,
+    ) -> Result<Vec<EventEntry>, MiddlewareError> {
+        select_rows(&self.connection.lock().unwrap(), where_clause, params)
+    }
+}
+
+#[async_trait]
+impl EventStore for SqliteStore {
+    async fn add_event(&self, entry: &EventEntry) -> Result<EventEntry, MiddlewareError> {
+        #[cfg(feature = "print_debug_log")]
+        println!("Event added to SQLite");
+
+        if !entry.blocked_by.is_empty() {
+            check_new_blockers(entry, &[], &self.get_all_tasks().await?)?;
+        }
+        // The event and its subtasks are inserted together
+        let result = {
+            let connection = self.connection.lock().unwrap();
+            let transaction = connection.unchecked_transaction()?;
+            let result = transaction
+                .execute(
+                    "INSERT INTO todos (id, title, details, date_time, is_done, tags, deleted_at,
+                                        version, recurrence, auto_complete, blocked_by, priority,
+                                        project_id)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+                    params![
+                        entry.unique_id.to_hex(),
+                        entry.title,
+                        entry.details,
+                        entry.date_time.map(|x| x.timestamp_millis()),
+                        entry.is_done,
+                        tags_to_text(&entry.tags),
+                        entry.deleted_at.map(|x| x.timestamp_millis()),
+                        entry.version,
+                        entry.recurrence.as_ref().map(ToString::to_string),
+                        entry.auto_complete,
+                        blockers_to_text(&entry.blocked_by),
+                        entry.priority.as_str(),
+                        entry.project_id.map(|id| id.to_hex()),
+                    ],
+                )
+                .and_then(|_| save_subtasks(&transaction, entry));
+            if result.is_ok() {
+                transaction.commit()?;
+            }
+            result
+        };
+        match result {
+            Err(error) if error.sqlite_error_code() == Some(ErrorCode::ConstraintViolation) => {
+                Err(MiddlewareError::Conflict {
+                    id: entry.unique_id,
+                })
+            }
+            Err(error) => Err(error.into()),
+            Ok(_) => {
+                // Read the row back so the caller gets the date as stored, truncated to milliseconds
+                let stored = self.get_event(&entry.unique_id).await?;
+                self.record(RevisionAction::Create, None, &stored)?;
+                Ok(stored)
+            }
+        }
+    }
+
+    async fn update_task(&self, entry: &EventEntry) -> Result<EventEntry, MiddlewareError> {
+        #[cfg(feature = "print_debug_log")]
+        println!("Updating event with unique_id: {}", entry.unique_id);
+
+        let old = self
+            .select_events(
+                "WHERE id = ?1 AND deleted_at IS NULL",
+                params![entry.unique_id.to_hex()],
+            )?
+            .pop()
+            .ok_or(MiddlewareError::NotFound {
+                id: entry.unique_id,
+            })?;
+        if entry.blocked_by != old.blocked_by {
+            check_new_blockers(entry, &old.blocked_by, &self.get_all_tasks().await?)?;
+        }
+        // The version is checked again by the UPDATE in case the row changed since it was read;
+        // the subtasks are replaced in the same transaction
+        let changed = {
+            let connection = self.connection.lock().unwrap();
+            let transaction = connection.unchecked_transaction()?;
+            let changed = update_row(&transaction, entry)?;
+            if changed > 0 {
+                save_subtasks(&transaction, entry)?;
+            }
+            transaction.commit()?;
+            changed
+        };
+        if old.version != entry.version || changed == 0 {
+            return Err(MiddlewareError::Conflict {
+                id: entry.unique_id,
+            });
+        }
+        let new = self.get_event(&entry.unique_id).await?;
+        if has_changes(&old, &new) {
+            self.record(update_action(&old, &new), Some(&old), &new)?;
+        }
+        Ok(new)
+    }
+
+    async fn delete_event(&self, entry: &EventEntry) -> Result<(), MiddlewareError> {
+        let changed = self.connection.lock().unwrap().execute(
+            "UPDATE todos SET deleted_at = ?2 WHERE id = ?1 AND deleted_at IS NULL",
+            params![entry.unique_id.to_hex(), Utc::now().timestamp_millis()],
+        )?;
+        if changed == 0 {
+            return Err(MiddlewareError::NotFound {
+                id: entry.unique_id,
+            });
+        }
+        self.record(RevisionAction::Delete, Some(entry), entry)?;
+        Ok(())
+    }
+
+    async fn get_event(&self, id: &ObjectId) -> Result<EventEntry, MiddlewareError> {
+        self.select_events("WHERE id = ?1", params![id.to_hex()])?
+            .pop()
+            .ok_or(MiddlewareError::NotFound { id: *id })
+    }
+
+    async fn get_all_tasks(&self) -> Result<Vec<EventEntry>, MiddlewareError> {
+        self.select_events("WHERE deleted_at IS NULL", [])
+    }
+
+    fn timezone(&self) -> Tz {
+        self.timezone
+    }
+
+    fn urgency(&self) -> &UrgencyConfig {
+        &self.urgency
+    }
+
+    async fn get_today_events(&self) -> Result<Vec<EventEntry>, MiddlewareError> {
+        let (today, tomorrow) = day_bounds(today(&self.timezone), &self.timezone);
+
+        self.select_events(
+            "WHERE date_time >= ?1 AND date_time < ?2 AND deleted_at IS NULL",
+            params![today.timestamp_millis(), tomorrow.timestamp_millis()],
+        )
+    }
+
+    async fn query_events(&self, query: &EventQuery) -> Result<Vec<EventEntry>, MiddlewareError> {
+        // The urgency isn't stored, so the matching events are sorted and paged here
+        if query.sort_key == SortKey::Urgency {
+            let events = self.query_events(&query.unordered()).await?;
+            return Ok(query.apply(events, &self.urgency, Utc::now()));
+        }
+        let (clause, values) = query_to_sql(query);
+        self.select_events(&clause, params_from_iter(values))
+    }
+
+    async fn search_events(&self, text: &str) -> Result<Vec<SearchHit>, MiddlewareError> {
+        // Every word is quoted so FTS5 doesn't read it as an operator; any of them may match
+        let terms = search_terms(text);
+        if terms.is_empty() {
+            return Ok(Vec::new());
+        }
+        let pattern = terms
+            .iter()
+            .map(|term| format!("\"{}\"", term))
+            .collect::<Vec<String>>()
+            .join(" OR ");
+
+        // bm25 is lower for better matches and takes the weights in the column order of the index
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(
+            "SELECT todos.id, todos.title, todos.details, todos.date_time, todos.is_done,
+                    todos.tags, todos.deleted_at, todos.version, todos.recurrence,
+                    todos.auto_complete, todos.blocked_by, todos.priority, todos.project_id,
+                    -bm25(todos_search, ?2, ?3, ?4) AS score
+             FROM todos_search JOIN todos ON todos.rowid = todos_search.rowid
+             WHERE todos_search MATCH ?1 AND todos.deleted_at IS NULL
+             ORDER BY score DESC, todos.date_time ASC",
+        )?;
+        let mut rows =
+            statement.query(params![pattern, TITLE_WEIGHT, DETAILS_WEIGHT, TAGS_WEIGHT])?;
+
+        let mut hits = Vec::new();
+        while let Some(row) = rows.next()? {
+            let mut entry = row_to_event(row)?;
+            entry.subtasks = load_subtasks(&connection, &entry.unique_id)?;
+            hits.push(SearchHit {
+                entry,
+                score: row.get(13)?,
+            });
+        }
+        Ok(hits)
+    }
+
+    async fn get_trash(&self) -> Result<Vec<EventEntry>, MiddlewareError> {
+        self.select_events("WHERE deleted_at IS NOT NULL", [])
+    }
+
+    async fn restore_event(&self, entry: &EventEntry) -> Result<EventEntry, MiddlewareError> {
+        let changed = self.connection.lock().unwrap().execute(
+            "UPDATE todos SET deleted_at = NULL WHERE id = ?1 AND deleted_at IS NOT NULL",
+            params![entry.unique_id.to_hex()],
+        )?;
+        if changed == 0 {
+            return Err(MiddlewareError::NotFound {
+                id: entry.unique_id,
+            });
+        }
+        let restored = self.get_event(&entry.unique_id).await?;
+        self.record(RevisionAction::Restore, Some(&restored), &restored)?;
+        Ok(restored)
+    }
+
+    async fn purge_event(&self, entry: &EventEntry) -> Result<(), MiddlewareError> {
+        let changed = self.connection.lock().unwrap().execute(
+            "DELETE FROM todos WHERE id = ?1 AND deleted_at IS NOT NULL",
+            params![entry.unique_id.to_hex()],
+        )?;
+        if changed == 0 {
+            return Err(MiddlewareError::NotFound {
+                id: entry.unique_id,
+            });
+        }
+        Ok(())
+    }
+
+    async fn get_history(&self, event_id: &ObjectId) -> Result<Vec<Revision>, MiddlewareError> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(
+            "SELECT id, action, timestamp, actor FROM revisions
+             WHERE event_id = ?1 ORDER BY timestamp, rowid",
+        )?;
+        let mut rows = statement.query(params![event_id.to_hex()])?;
+        let mut revisions = Vec::new();
+        while let Some(row) = rows.next()? {
+            let id: String = row.get(0)?;
+            let revision_id =
+                ObjectId::parse_str(&id).map_err(|_| MiddlewareError::decode("_id", None))?;
+            let action: String = row.get(1)?;
+            let millis: i64 = row.get(2)?;
+            revisions.push(Revision {
+                revision_id,
+                event_id: *event_id,
+                action: action.parse()?,
+                changes: Vec::new(),
+                timestamp: Utc
+                    .timestamp_millis_opt(millis)
+                    .single()
+                    .ok_or_else(|| MiddlewareError::decode("timestamp", Some(&revision_id)))?,
+                actor: row.get(3)?,
+            });
+        }
+
+        let mut statement = connection.prepare(
+            "SELECT field, old_value, new_value FROM revision_changes
+             WHERE revision_id = ?1 ORDER BY position",
+        )?;
+        for revision in revisions.iter_mut() {
+            let mut rows = statement.query(params![revision.revision_id.to_hex()])?;
+            while let Some(row) = rows.next()? {
+                revision.changes.push(FieldChange {
+                    field: row.get(0)?,
+                    old_value: row.get(1)?,
+                    new_value: row.get(2)?,
+                });
+            }
+        }
+        Ok(revisions)
+    }
+
+    async fn purge_trash(&self, retention: Duration) -> Result<u64, MiddlewareError> {
+        let cutoff = Utc::now() - retention;
+        let changed = self.connection.lock().unwrap().execute(
+            "DELETE FROM todos WHERE deleted_at <= ?1",
+            params![cutoff.timestamp_millis()],
+        )?;
+        Ok(changed as u64)
+    }
+
+    async fn migrate(&self, dry_run: bool) -> Result<MigrationReport, MiddlewareError> {
+        migrate_connection(&self.connection.lock().unwrap(), dry_run)
+    }
+
+    async fn update_many(
+        &self,
+        selection: &EventSelection,
+        patch: &EventPatch,
+    ) -> Result<Vec<(EventEntry, EventEntry)>, MiddlewareError> {
+        let ids = selected_ids(self, selection).await?;
+        // All the events are changed in one transaction, so an error leaves every one as it was;
+        // the lock keeps other writers out, so the versions read here can't go stale
+        let connection = self.connection.lock().unwrap();
+        let transaction = connection.unchecked_transaction()?;
+        let mut changes = Vec::new();
+        for id in ids {
+            let Some(before) = select_rows(
+                &transaction,
+                "WHERE id = ?1 AND deleted_at IS NULL",
+                params![id.to_hex()],
+            )?
+            .pop() else {
+                continue;
+            };
+            let after = patch.apply(&before, &self.timezone);
+            if !has_changes(&before, &after) {
+                continue;
+            }
+            update_row(&transaction, &after)?;
+            save_subtasks(&transaction, &after)?;
+            let stored = select_rows(&transaction, "WHERE id = ?1", params![id.to_hex()])?
+                .pop()
+                .ok_or(MiddlewareError::NotFound { id })?;
+            let revision = Revision::new(
+                update_action(&before, &stored),
+                Some(&before),
+                &stored,
+                &self.actor,
+            );
+            insert_revision(&transaction, &revision)?;
+            changes.push((before, stored));
+        }
+        transaction.commit()?;
+        Ok(changes)
+    }
+
+    async fn delete_many(
+        &self,
+        selection: &EventSelection,
+    ) -> Result<Vec<EventEntry>, MiddlewareError> {
+        let ids = selected_ids(self, selection).await?;
+        // Like update_many, either all the events go to the trash or none does
+        let connection = self.connection.lock().unwrap();
+        let transaction = connection.unchecked_transaction()?;
+        let deleted_at = Utc::now().timestamp_millis();
+        let mut deleted = Vec::new();
+        for id in ids {
+            let Some(entry) = select_rows(
+                &transaction,
+                "WHERE id = ?1 AND deleted_at IS NULL",
+                params![id.to_hex()],
+            )?
+            .pop() else {
+                continue;
+            };
+            transaction.execute(
+                "UPDATE todos SET deleted_at = ?2 WHERE id = ?1",
+                params![id.to_hex(), deleted_at],
+            )?;
+            let revision = Revision::new(RevisionAction::Delete, Some(&entry), &entry, &self.actor);
+            insert_revision(&transaction, &revision)?;
+            deleted.push(entry);
+        }
+        transaction.commit()?;
+        Ok(deleted)
+    }
+
+    async fn add_project(&self, project: &Project) -> Result<Project, MiddlewareError> {
+        project.check()?;
+        let result = self.connection.lock().unwrap().execute(
+            "INSERT INTO projects (id, name, color, archived, position) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                project.id.to_hex(),
+                project.name,
+                project.color,
+                project.archived,
+                project.position,
+            ],
+        );
+        match result {
+            Err(error) if error.sqlite_error_code() == Some(ErrorCode::ConstraintViolation) => {
+                Err(MiddlewareError::Conflict { id: project.id })
+            }
+            Err(error) => Err(error.into()),
+            Ok(_) => Ok(project.clone()),
+        }
+    }
+
+    async fn update_project(&self, project: &Project) -> Result<Project, MiddlewareError> {
+        project.check()?;
+        let changed = self.connection.lock().unwrap().execute(
+            "UPDATE projects SET name = ?2, color = ?3, archived = ?4, position = ?5 WHERE id = ?1",
+            params![
+                project.id.to_hex(),
+                project.name,
+                project.color,
+                project.archived,
+                project.position,
+            ],
+        )?;
+        if changed == 0 {
+            return Err(MiddlewareError::NotFound { id: project.id });
+        }
+        Ok(project.clone())
+    }
+
+    async fn remove_project(&self, project_id: &ObjectId) -> Result<(), MiddlewareError> {
+        let changed = self.connection.lock().unwrap().execute(
+            "DELETE FROM projects WHERE id = ?1",
+            params![project_id.to_hex()],
+        )?;
+        if changed == 0 {
+            return Err(MiddlewareError::NotFound { id: *project_id });
+        }
+        Ok(())
+    }
+
+    async fn get_projects(&self) -> Result<Vec<Project>, MiddlewareError> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(
+            "SELECT id, name, color, archived, position FROM projects ORDER BY position, name",
+        )?;
+        let mut rows = statement.query([])?;
+        let mut projects = Vec::new();
+        while let Some(row) = rows.next()? {
+            let id: String = row.get(0)?;
+            projects.push(Project {
+                id: ObjectId::parse_str(&id).map_err(|_| MiddlewareError::decode("_id", None))?,
+                name: row.get(1)?,
+                color: row.get(2)?,
+                archived: row.get(3)?,
+                position: row.get(4)?,
+            });
+        }
+        Ok(projects)
+    }
+
+    async fn ensure_indexes(&self) -> Result<IndexReport, MiddlewareError> {
+        let connection = self.connection.lock().unwrap();
+        let mut report = IndexReport::default();
+        for (name, sql) in INDEXES {
+            let present: bool = connection.query_row(
+                "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE name = ?1)",
+                params![name],
+                |row| row.get(0),
+            )?;
+            if !present {
+                connection.execute_batch(sql)?;
+            }
+            report.add(name, !present);
+        }
+        Ok(report)
+    }
+}
+
+/// Runs the steps above the version recorded in the database, each one in its own transaction
+fn migrate_connection(
+    connection: &Connection,
+    dry_run: bool,
+) -> Result<MigrationReport, MiddlewareError> {
+    let from_version: u32 = connection.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    let mut report = MigrationReport {
+        dry_run,
+        from_version,
+        to_version: from_version,
+        steps: Vec::new(),
+    };
+
+    // A dry run applies the steps in one transaction which is rolled back, so each step sees the
+    // steps before it and reports the events it would change
+    let dry_run_transaction = if dry_run {
+        Some(connection.unchecked_transaction()?)
+    } else {
+        None
+    };
+    for (index, (description, sql, step)) in MIGRATIONS.iter().enumerate() {
+        let version = index as u32 + 1;
+        if version <= from_version {
+            continue;
+        }
+        let documents = match &dry_run_transaction {
+            Some(transaction) => apply_step(transaction, sql, *step)?,
+            None => {
+                let transaction = connection.unchecked_transaction()?;
+                let documents = apply_step(&transaction, sql, *step)?;
+                transaction.pragma_update(None, "user_version", version)?;
+                transaction.commit()?;
+                documents
+            }
+        };
+        report.to_version = version;
+        report.steps.push(StepReport {
+            version,
+            description: description.to_string(),
+            documents,
+        });
+    }
+    if let Some(transaction) = dry_run_transaction {
+        transaction.rollback()?;
+    }
+    Ok(report)
+}
+
+/// Runs the SQL of a step and then its function, if any; returns the ids of the events the
+/// function changed
+fn apply_step(
+    connection: &Connection,
+    sql: &str,
+    step: Option<MigrationFn>,
+) -> Result<Vec<ObjectId>, MiddlewareError> {
+    connection.execute_batch(sql)?;
+    match step {
+        Some(step) => step(connection),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Rewrites the tags of every event as the text form of the tags parse_tags splits them into;
+/// returns the ids of the events whose tags were rewritten
+fn split_tags(connection: &Connection) -> Result<Vec<ObjectId>, MiddlewareError> {
+    let mut statement = connection.prepare("SELECT id, tags FROM todos")?;
+    let rows = statement
+        .query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?
+        .collect::<rusqlite::Result<Vec<(String, String)>>>()?;
+    let mut changed = Vec::new();
+    for (id, tags) in rows {
+        let split = tags_to_text(&parse_tags(&tags));
+        if split != tags {
+            connection.execute(
+                "UPDATE todos SET tags = ?2 WHERE id = ?1",
+                params![id, split],
+            )?;
+            changed
+                .push(ObjectId::parse_str(&id).map_err(|_| MiddlewareError::decode("id", None))?);
+        }
+    }
+    Ok(changed)
+}
+
+/// Translates the query into a WHERE, ORDER BY and LIMIT clause with its parameters
+fn query_to_sql(query: &EventQuery) -> (String, Vec<Value>) {
+    let mut conditions = vec![String::from("deleted_at IS NULL")];
+    let mut values = Vec::new();
+    if let Some(from) = &query.from {
+        values.push(Value::Integer(from.timestamp_millis()));
+        conditions.push(format!("date_time >= ?{}", values.len()));
+    }
+    if let Some(until) = &query.until {
+        values.push(Value::Integer(until.timestamp_millis()));
+        conditions.push(format!("date_time < ?{}", values.len()));
+    }
+    match query.has_date {
+        Some(true) => conditions.push(String::from("date_time IS NOT NULL")),
+        Some(false) => conditions.push(String::from("date_time IS NULL")),
+        None => {}
+    }
+    if let Some(is_done) = query.is_done {
+        values.push(Value::Integer(is_done.into()));
+        conditions.push(format!("is_done = ?{}", values.len()));
+    }
+    match query.project {
+        Some(Some(project_id)) => {
+            values.push(Value::Text(project_id.to_hex()));
+            conditions.push(format!("project_id = ?{}", values.len()));
+        }
+        Some(None) => conditions.push(String::from("project_id IS NULL")),
+        None => {}
+    }
+    if let Some(names) = query.priority_names() {
+        let placeholders: Vec<String> = names
+            .iter()
+            .map(|name| {
+                values.push(Value::Text(name.to_string()));
+                format!("?{}", values.len())
+            })
+            .collect();
+        conditions.push(format!("priority IN ({})", placeholders.join(", ")));
+    }
+    for tag in query.tag_names() {
+        // Wrapping the list in separators turns each whole tag into ", tag, "
+        values.push(Value::Text(format!("%, {}, %", escape_like(&tag))));
+        conditions.push(format!(
+            "(', ' || tags || ', ') LIKE ?{} ESCAPE '\\'",
+            values.len()
+        ));
+    }
+    if let Some(text) = &query.text {
+        values.push(Value::Text(format!("%{}%", escape_like(text))));
+        let n = values.len();
+        conditions.push(format!(
+            "(title LIKE ?{n} ESCAPE '\\' OR details LIKE ?{n} ESCAPE '\\' OR tags LIKE ?{n} ESCAPE '\\')"
+        ));
+    }
+
+    let direction = match query.sort_direction {
+        SortDirection::Ascending => "ASC",
+        SortDirection::Descending => "DESC",
+    };
+    // SQLite takes -1 as no limit
+    values.push(Value::Integer(query.limit.map_or(-1, |limit| limit as i64)));
+    values.push(Value::Integer(query.skip as i64));
+    let clause = format!(
+        "WHERE {} ORDER BY {} {}, id ASC LIMIT ?{} OFFSET ?{}",
+        conditions.join(" AND "),
+        query.sort_key.field(),
+        direction,
+        values.len() - 1,
+        values.len()
+    );
+    (clause, values)
+}
+
+/// Escapes the wildcards of a LIKE pattern, using backslash as the escape character
+fn escape_like(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+/// Builds an EventEntry out of one row of the "todos" table
+fn row_to_event(row: &Row<'_>) -> Result<EventEntry, MiddlewareError> {
+    let id: String = row.get(0)?;
+    let unique_id = ObjectId::parse_str(&id).map_err(|_| MiddlewareError::decode("_id", None))?;
+    let date_time = |index: usize, field: &str| -> Result<Option<DateTime<Utc>>, MiddlewareError> {
+        match row.get::<_, Option<i64>>(index)? {
+            Some(millis) => {
+                Ok(Some(Utc.timestamp_millis_opt(millis).single().ok_or_else(
+                    || MiddlewareError::decode(field, Some(&unique_id)),
+                )?))
+            }
+            None => Ok(None),
+        }
+    };
+
+    Ok(EventEntry {
+        unique_id,
+        title: row.get(1)?,
+        details: row.get(2)?,
+        date_time: date_time(3, "date_time")?,
+        is_done: row.get(4)?,
+        tags: parse_tags(&row.get::<_, String>(5)?),
+        deleted_at: date_time(6, "deleted_at")?,
+        version: row.get(7)?,
+        recurrence: row
+            .get::<_, Option<String>>(8)?
+            .map(|rule| rule.parse())
+            .transpose()
+            .map_err(|_| MiddlewareError::decode("recurrence", Some(&unique_id)))?,
+        // Loaded from their own table by load_subtasks
+        subtasks: Vec::new(),
+        auto_complete: row.get(9)?,
+        blocked_by: blockers_from_text(&row.get::<_, String>(10)?)
+            .ok_or_else(|| MiddlewareError::decode("blocked_by", Some(&unique_id)))?,
+        priority: row
+            .get::<_, String>(11)?
+            .parse()
+            .map_err(|_| MiddlewareError::decode("priority", Some(&unique_id)))?,
+        project_id: row
+            .get::<_, Option<String>>(12)?
+            .map(ObjectId::parse_str)
+            .transpose()
+            .map_err(|_| MiddlewareError::decode("project_id", Some(&unique_id)))?,
+    })
+}
+
+/// Runs a SELECT over the "todos" table with the given WHERE clause and loads the subtasks of
+/// the events it finds
+fn select_rows(
+    connection: &Connection,
+    where_clause: &str,
+    params: impl rusqlite::Params,
+) -> Result<Vec<EventEntry>, MiddlewareError> {
+    let mut statement = connection.prepare(&format!("{} {}", SELECT_EVENTS, where_clause))?;
+    let mut rows = statement.query(params)?;
+
+    let mut tasks = Vec::new();
+    while let Some(row) = rows.next()? {
+        tasks.push(row_to_event(row)?);
+    }
+    for task in &mut tasks {
+        task.subtasks = load_subtasks(connection, &task.unique_id)?;
+    }
+    Ok(tasks)
+}
+
+/// Writes the entry over the stored event if it still has the version of the entry, and returns
+/// the number of rows changed
+fn update_row(connection: &Connection, entry: &EventEntry) -> rusqlite::Result<usize> {
+    connection.execute(
+        "UPDATE todos SET title = ?2, details = ?3, date_time = ?4, is_done = ?5, tags = ?6,
+                          recurrence = ?8, auto_complete = ?9, blocked_by = ?10,
+                          priority = ?11, project_id = ?12, version = version + 1
+         WHERE id = ?1 AND deleted_at IS NULL AND version = ?7",
+        params![
+            entry.unique_id.to_hex(),
+            entry.title,
+            entry.details,
+            entry.date_time.map(|x| x.timestamp_millis()),
+            entry.is_done,
+            tags_to_text(&entry.tags),
+            entry.version,
+            entry.recurrence.as_ref().map(ToString::to_string),
+            entry.auto_complete,
+            blockers_to_text(&entry.blocked_by),
+            entry.priority.as_str(),
+            entry.project_id.map(|id| id.to_hex()),
+        ],
+    )
+}
+
+/// Inserts the revision and its field changes into the history tables
+fn insert_revision(connection: &Connection, revision: &Revision) -> rusqlite::Result<()> {
+    connection.execute(
+        "INSERT INTO revisions (id, event_id, action, timestamp, actor)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![
+            revision.revision_id.to_hex(),
+            revision.event_id.to_hex(),
+            revision.action.as_str(),
+            revision.timestamp.timestamp_millis(),
+            revision.actor,
+        ],
+    )?;
+    for (position, change) in revision.changes.iter().enumerate() {
+        connection.execute(
+            "INSERT INTO revision_changes (revision_id, position, field, old_value, new_value)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                revision.revision_id.to_hex(),
+                position,
+                change.field,
+                change.old_value,
+                change.new_value,
+            ],
+        )?;
+    }
+    Ok(())
+}
+
+/// Replaces the stored subtasks of the event with the ones of the entry
+fn save_subtasks(connection: &Connection, entry: &EventEntry) -> rusqlite::Result<()> {
+    let event_id = entry.unique_id.to_hex();
+    connection.execute(
+        "DELETE FROM subtasks WHERE event_id = ?1",
+        params![event_id],
+    )?;
+    for (position, subtask) in entry.subtasks.iter().enumerate() {
+        connection.execute(
+            "INSERT INTO subtasks (event_id, position, id, title, is_done)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                event_id,
+                position,
+                subtask.id.to_hex(),
+                subtask.title,
+                subtask.is_done,
+            ],
+        )?;
+    }
+    Ok(())
+}
+
+/// Loads the subtasks of the event in the order of its checklist
+fn load_subtasks(
+    connection: &Connection,
+    event_id: &ObjectId,
+) -> Result<Vec<Subtask>, MiddlewareError> {
+    let mut statement = connection.prepare_cached(
+        "SELECT id, title, is_done FROM subtasks WHERE event_id = ?1 ORDER BY position",
+    )?;
+    let mut rows = statement.query(params![event_id.to_hex()])?;
+    let mut subtasks = Vec::new();
+    while let Some(row) = rows.next()? {
+        let id: String = row.get(0)?;
+        subtasks.push(Subtask {
+            id: ObjectId::parse_str(&id)
+                .map_err(|_| MiddlewareError::decode("subtasks", Some(event_id)))?,
+            title: row.get(1)?,
+            is_done: row.get(2)?,
+        });
+    }
+    Ok(subtasks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample_entry;
+    use crate::{Frequency, Priority, Recurrence, SortKey, TagCount};
+    use chrono::Weekday;
+
+    #[test]
+    fn test_add_update_delete() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        // SQLite keeps millisecond precision
+        let now = Utc
+            .timestamp_millis_opt(Utc::now().timestamp_millis())
+            .unwrap();
+        let mut entry = EventEntry {
+            date_time: Some(now),
+            ..sample_entry("KrabbyDo sqlite store")
+        };
+        let rt = tokio::runtime::Runtime::new().unwrap();
+
+        rt.block_on(async { store.add_event(&entry).await })
+            .unwrap();
+        entry.title = String::from("Updated title");
+        entry.is_done = true;
+        entry = rt
+            .block_on(async { store.update_task(&entry).await })
+            .unwrap();
+        assert_eq!(entry.version, 1);
+        let tasks = rt.block_on(async { store.get_all_tasks().await }).unwrap();
+        assert_eq!(tasks, vec![entry.clone()]);
+
+        rt.block_on(async { store.delete_event(&entry).await })
+            .unwrap();
+        let tasks = rt.block_on(async { store.get_all_tasks().await }).unwrap();
+        assert!(tasks.is_empty());
+        let result = rt.block_on(async { store.delete_event(&entry).await });
+        assert!(matches!(result, Err(MiddlewareError::NotFound { .. })));
+
+        // The deleted event waits in the trash until it is restored or purged
+        let trash = rt.block_on(async { store.get_trash().await }).unwrap();
+        assert_eq!(trash.len(), 1);
+        let restored = rt
+            .block_on(async { store.restore_event(&entry).await })
+            .unwrap();
+        assert_eq!(restored, entry);
+        rt.block_on(async { store.delete_event(&entry).await })
+            .unwrap();
+        let purged = rt
+            .block_on(async { store.purge_trash(Duration::zero()).await })
+            .unwrap();
+        assert_eq!(purged, 1);
+        let result = rt.block_on(async { store.restore_event(&entry).await });
+        assert!(matches!(result, Err(MiddlewareError::NotFound { .. })));
+    }
+
+    #[test]
+    fn test_update_conflict() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        let entry = sample_entry("KrabbyDo sqlite store");
+        let rt = tokio::runtime::Runtime::new().unwrap();
+
+        let loaded = rt
+            .block_on(async { store.add_event(&entry).await })
+            .unwrap();
+        let mut mine = loaded.clone();
+        mine.title = String::from("Mine");
+        let mut theirs = loaded;
+        theirs.is_done = true;
+        let stored = rt
+            .block_on(async { store.update_task(&theirs).await })
+            .unwrap();
+
+        // The event changed since it was loaded, so the update is refused and nothing is overwritten
+        let result = rt.block_on(async { store.update_task(&mine).await });
+        assert!(matches!(result, Err(MiddlewareError::Conflict { .. })));
+        let current = rt
+            .block_on(async { store.get_event(&entry.unique_id).await })
+            .unwrap();
+        assert_eq!(current, stored);
+
+        // Made on the current version it goes through
+        mine.version = current.version;
+        let merged = rt
+            .block_on(async { store.update_task(&mine).await })
+            .unwrap();
+        assert_eq!(merged.version, 2);
+        assert_eq!(merged.title, "Mine");
+    }
+
+    #[test]
+    fn test_update_many_delete_many() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let first = rt
+            .block_on(async { store.add_event(&sample_entry("First")).await })
+            .unwrap();
+        let broken = rt
+            .block_on(async { store.add_event(&sample_entry("Broken")).await })
+            .unwrap();
+        // Any write to the second event fails
+        store
+            .connection
+            .lock()
+            .unwrap()
+            .execute_batch(
+                "CREATE TRIGGER broken BEFORE UPDATE ON todos WHEN OLD.title = 'Broken'
+                 BEGIN SELECT RAISE(ABORT, 'broken'); END;",
+            )
+            .unwrap();
+
+        // The first event was changed before the error, and that change is rolled back too
+        let selection = EventSelection::Ids(vec![first.unique_id, broken.unique_id]);
+        let patch = EventPatch::new().add_tag("sprint");
+        let result = rt.block_on(async { store.update_many(&selection, &patch).await });
+        assert!(result.is_err());
+        let result = rt.block_on(async { store.delete_many(&selection).await });
+        assert!(result.is_err());
+        let tasks = rt.block_on(async { store.get_all_tasks().await }).unwrap();
+        assert_eq!(tasks, vec![first.clone(), broken.clone()]);
+        let history = rt
+            .block_on(async { store.get_history(&first.unique_id).await })
+            .unwrap();
+        assert_eq!(history.len(), 1);
+
+        store
+            .connection
+            .lock()
+            .unwrap()
+            .execute_batch("DROP TRIGGER broken;")
+            .unwrap();
+        let changes = rt
+            .block_on(async { store.update_many(&selection, &patch).await })
+            .unwrap();
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[0].0, first);
+        assert_eq!(changes[0].1.tags, vec!["work", "sprint"]);
+        assert_eq!(changes[0].1.version, 1);
+        let history = rt
+            .block_on(async { store.get_history(&first.unique_id).await })
+            .unwrap();
+        assert_eq!(history.len(), 2);
+        let deleted = rt
+            .block_on(async { store.delete_many(&selection).await })
+            .unwrap();
+        assert_eq!(deleted, vec![changes[0].1.clone(), changes[1].1.clone()]);
+        let tasks = rt.block_on(async { store.get_all_tasks().await }).unwrap();
+        assert!(tasks.is_empty());
+    }
+
+    #[test]
+    fn test_add_event_keeps_id_and_done_state() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        let mut entry = sample_entry("KrabbyDo sqlite store");
+        entry.is_done = true;
+        let rt = tokio::runtime::Runtime::new().unwrap();
+
+        let stored = rt
+            .block_on(async { store.add_event(&entry).await })
+            .unwrap();
+        assert_eq!(stored.unique_id, entry.unique_id);
+        assert!(stored.is_done);
+        // The returned date is the one stored, truncated to milliseconds
+        assert_eq!(
+            stored.date_time.map(|x| x.timestamp_millis()),
+            entry.date_time.map(|x| x.timestamp_millis())
+        );
+        let tasks = rt.block_on(async { store.get_all_tasks().await }).unwrap();
+        assert_eq!(tasks, vec![stored]);
+    }
+
+    #[test]
+    fn test_get_history() {
+        let store = SqliteStore::open_in_memory().unwrap().with_actor("krabby");
+        let mut entry = sample_entry("KrabbyDo sqlite store");
+        let rt = tokio::runtime::Runtime::new().unwrap();
+
+        let first = rt
+            .block_on(async { store.add_event(&entry).await })
+            .unwrap();
+        entry.details = Some(String::from("Changed details"));
+        entry = rt
+            .block_on(async { store.update_task(&entry).await })
+            .unwrap();
+        rt.block_on(async { store.delete_event(&entry).await })
+            .unwrap();
+        rt.block_on(async { store.restore_event(&entry).await })
+            .unwrap();
+
+        let history = rt
+            .block_on(async { store.get_history(&entry.unique_id).await })
+            .unwrap();
+        let actions: Vec<RevisionAction> = history.iter().map(|x| x.action).collect();
+        assert_eq!(
+            actions,
+            vec![
+                RevisionAction::Create,
+                RevisionAction::Update,
+                RevisionAction::Delete,
+                RevisionAction::Restore
+            ]
+        );
+        assert_eq!(
+            history[1].changes,
+            vec![FieldChange {
+                field: String::from("details"),
+                old_value: String::from("Details"),
+                new_value: String::from("Changed details"),
+            }]
+        );
+
+        let reverted = rt
+            .block_on(async {
+                store
+                    .revert_event(&entry.unique_id, &history[0].revision_id)
+                    .await
+            })
+            .unwrap();
+        assert_eq!(
+            reverted,
+            EventEntry {
+                version: 2,
+                ..first
+            }
+        );
+    }
+
+    #[test]
+    fn test_search_events() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let mut details = sample_entry("Groceries");
+        details.details = Some(String::from("Buy coffee before the planning meeting"));
+        let title = sample_entry("Weekly meetings");
+        let trashed = sample_entry("Meeting notes");
+        for entry in [&details, &title, &trashed] {
+            rt.block_on(async { store.add_event(entry).await }).unwrap();
+        }
+        rt.block_on(async { store.delete_event(&trashed).await })
+            .unwrap();
+
+        // A word in the title ranks above the same word in the details, and stems match
+        let hits = rt
+            .block_on(async { store.search_events("meeting").await })
+            .unwrap();
+        let titles: Vec<&str> = hits.iter().map(|x| x.entry.title.as_str()).collect();
+        assert_eq!(titles, vec!["Weekly meetings", "Groceries"]);
+        assert!(hits[0].score > hits[1].score);
+
+        // The index follows updates
+        let mut renamed = title.clone();
+        renamed.title = String::from("Weekly review");
+        rt.block_on(async { store.update_task(&renamed).await })
+            .unwrap();
+        let hits = rt
+            .block_on(async { store.search_events("\"meetings\" OR").await })
+            .unwrap();
+        assert_eq!(hits.len(), 1);
+        assert!(rt
+            .block_on(async { store.search_events("  ").await })
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_query_events() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        let day = Utc.with_ymd_and_hms(2023, 6, 1, 12, 0, 0).unwrap();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        for (offset, title, is_done, tags) in [
+            (2, "Groceries", false, "Home,Errands"),
+            (0, "Report", false, "Work"),
+            (1, "Standup", true, "work"),
+            (3, "Homework 100%", false, "School"),
+        ] {
+            let mut entry = sample_entry(title);
+            entry.date_time = Some(day + Duration::days(offset));
+            entry.is_done = is_done;
+            entry.tags = parse_tags(tags);
+            if title.starts_with("Homework") {
+                entry.priority = Priority::Critical;
+            }
+            rt.block_on(async { store.add_event(&entry).await })
+                .unwrap();
+        }
+        let titles = |query: EventQuery| -> Vec<String> {
+            rt.block_on(async { store.query_events(&query).await })
+                .unwrap()
+                .into_iter()
+                .map(|x| x.title)
+                .collect()
+        };
+
+        assert_eq!(
+            titles(EventQuery::new().tag("WORK")),
+            vec!["Report", "Standup"]
+        );
+        assert_eq!(titles(EventQuery::new().tag("home")), vec!["Groceries"]);
+        assert_eq!(titles(EventQuery::new().text("0%")), vec!["Homework 100%"]);
+        assert_eq!(
+            titles(
+                EventQuery::new()
+                    .done(false)
+                    .sort_by(SortKey::Title, SortDirection::Descending)
+            ),
+            vec!["Report", "Homework 100%", "Groceries"]
+        );
+        assert_eq!(
+            titles(EventQuery::new().date_range(day + Duration::days(1), day + Duration::days(3))),
+            vec!["Standup", "Groceries"]
+        );
+        assert_eq!(
+            titles(EventQuery::new().skip(1).limit(2)),
+            vec!["Standup", "Groceries"]
+        );
+        assert_eq!(
+            titles(EventQuery::new().min_priority(Priority::High)),
+            vec!["Homework 100%"]
+        );
+        // All of them are overdue, so the critical homework and then the two tags of the
+        // groceries make the difference
+        assert_eq!(
+            titles(
+                EventQuery::new()
+                    .done(false)
+                    .sort_by(SortKey::Urgency, SortDirection::Descending)
+                    .limit(2)
+            ),
+            vec!["Homework 100%", "Groceries"]
+        );
+    }
+
+    #[test]
+    fn test_optional_fields() {
+        let connection = Connection::open_in_memory().unwrap();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        // A database from before the fields were optional keeps its events and their search index
+        let before = MIGRATIONS
+            .iter()
+            .position(|(_, sql, _)| *sql == OPTIONAL_FIELDS)
+            .unwrap();
+        for (sql, version) in MIGRATIONS[..before].iter().map(|x| x.1).zip(1..) {
+            connection.execute_batch(sql).unwrap();
+            connection
+                .pragma_update(None, "user_version", version)
+                .unwrap();
+        }
+        let dated = sample_entry("Dentist appointment");
+        connection
+            .execute(
+                "INSERT INTO todos (id, title, details, date_time, is_done, tags)
+                 VALUES (?1, ?2, '', ?3, 0, '')",
+                params![
+                    dated.unique_id.to_hex(),
+                    dated.title,
+                    dated.date_time.unwrap().timestamp_millis()
+                ],
+            )
+            .unwrap();
+        let store = SqliteStore::from_connection(connection).unwrap();
+        let hits = rt
+            .block_on(async { store.search_events("dentist").await })
+            .unwrap();
+        assert_eq!(hits.len(), 1);
+
+        let undated = EventEntry {
+            details: None,
+            date_time: None,
+            tags: Vec::new(),
+            ..sample_entry("Learn the cello")
+        };
+        let stored = rt
+            .block_on(async { store.add_event(&undated).await })
+            .unwrap();
+        assert_eq!(stored, undated);
+        let titles = |query: EventQuery| -> Vec<String> {
+            rt.block_on(async { store.query_events(&query).await })
+                .unwrap()
+                .into_iter()
+                .map(|x| x.title)
+                .collect()
+        };
+        assert_eq!(
+            titles(EventQuery::new().dated(false)),
+            vec!["Learn the cello"]
+        );
+        assert_eq!(
+            titles(EventQuery::new().dated(true)),
+            vec!["Dentist appointment"]
+        );
+        // Events without a date are left out of date ranges and sorted first
+        assert_eq!(
+            titles(EventQuery::new().from(Utc.timestamp_millis_opt(0).unwrap())),
+            vec!["Dentist appointment"]
+        );
+        assert_eq!(
+            titles(EventQuery::new()),
+            vec!["Learn the cello", "Dentist appointment"]
+        );
+    }
+
+    #[test]
+    fn test_split_tags() {
+        let connection = Connection::open_in_memory().unwrap();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        // A database from before the tags were split keeps them as free text
+        let before = MIGRATIONS
+            .iter()
+            .position(|(_, sql, _)| *sql == SPLIT_TAGS)
+            .unwrap();
+        for (sql, version) in MIGRATIONS[..before].iter().map(|x| x.1).zip(1..) {
+            connection.execute_batch(sql).unwrap();
+            connection
+                .pragma_update(None, "user_version", version)
+                .unwrap();
+        }
+        let tagged = sample_entry("Groceries");
+        let untagged = sample_entry("Dentist appointment");
+        for (entry, tags) in [(&tagged, Some("Home  errands,home")), (&untagged, None)] {
+            connection
+                .execute(
+                    "INSERT INTO todos (id, title, details, date_time, is_done, tags)
+                     VALUES (?1, ?2, '', 0, 0, ?3)",
+                    params![entry.unique_id.to_hex(), entry.title, tags],
+                )
+                .unwrap();
+        }
+        // A dry run names the events whose tags would be split
+        let report = migrate_connection(&connection, true).unwrap();
+        assert_eq!(report.steps[0].documents, vec![tagged.unique_id]);
+        let store = SqliteStore::from_connection(connection).unwrap();
+
+        let stored = rt
+            .block_on(async { store.get_event(&tagged.unique_id).await })
+            .unwrap();
+        assert_eq!(stored.tags, vec!["home", "errands"]);
+        let stored = rt
+            .block_on(async { store.get_event(&untagged.unique_id).await })
+            .unwrap();
+        assert!(stored.tags.is_empty());
+        let found = rt
+            .block_on(async { store.query_events(&EventQuery::new().tag("Errands")).await })
+            .unwrap();
+        assert_eq!(found.len(), 1);
+        let tags = rt.block_on(async { store.get_tags().await }).unwrap();
+        assert_eq!(
+            tags,
+            vec![
+                TagCount {
+                    tag: String::from("errands"),
+                    count: 1
+                },
+                TagCount {
+                    tag: String::from("home"),
+                    count: 1
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_recurrence() {
+        let store = SqliteStore::open_in_memory()
+            .unwrap()
+            .with_timezone(Tz::UTC);
+        let start = Utc.with_ymd_and_hms(2023, 6, 9, 17, 0, 0).unwrap();
+        let entry = EventEntry {
+            recurrence: Some(
+                Recurrence::new(Frequency::Monthly)
+                    .on_nth(-1, Weekday::Fri)
+                    .count(3),
+            ),
+            date_time: Some(start),
+            ..sample_entry("Pay the rent")
+        };
+        let rt = tokio::runtime::Runtime::new().unwrap();
+
+        let stored = rt
+            .block_on(async { store.add_event(&entry).await })
+            .unwrap();
+        assert_eq!(stored, entry);
+        let next = rt
+            .block_on(async { store.complete_event(&stored).await })
+            .unwrap();
+        assert_eq!(
+            next.date_time,
+            Some(Utc.with_ymd_and_hms(2023, 6, 30, 17, 0, 0).unwrap())
+        );
+        assert_eq!(next.recurrence.as_ref().and_then(|x| x.count), Some(2));
+        let tasks = rt.block_on(async { store.get_all_tasks().await }).unwrap();
+        assert_eq!(tasks, vec![next.clone()]);
+
+        // The history holds the rule, so reverting brings back the first occurrence
+        let history = rt
+            .block_on(async { store.get_history(&entry.unique_id).await })
+            .unwrap();
+        let reverted = rt
+            .block_on(async {
+                store
+                    .revert_event(&entry.unique_id, &history[0].revision_id)
+                    .await
+            })
+            .unwrap();
+        assert_eq!(reverted.recurrence, entry.recurrence);
+        assert_eq!(reverted.date_time, Some(start));
+    }
+
+    #[test]
+    fn test_subtasks() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        let mut entry = sample_entry("Prepare release");
+        entry.add_subtask("Tag the commit").unwrap();
+        entry.add_subtask("Write the changelog").unwrap();
+        entry.auto_complete = true;
+        let rt = tokio::runtime::Runtime::new().unwrap();
+
+        let stored = rt
+            .block_on(async { store.add_event(&entry).await })
+            .unwrap();
+        assert_eq!(stored.subtasks, entry.subtasks);
+        assert!(stored.auto_complete);
+        let id = stored.subtasks[1].id;
+        let stored = rt
+            .block_on(async { store.move_subtask(&stored, &id, 0).await })
+            .unwrap();
+        let stored = rt
+            .block_on(async { store.toggle_subtask(&stored, &id).await })
+            .unwrap();
+        let tasks = rt.block_on(async { store.get_all_tasks().await }).unwrap();
+        assert_eq!(tasks, vec![stored.clone()]);
+        assert_eq!(tasks[0].subtasks[0].title, "Write the changelog");
+        assert!(tasks[0].subtasks[0].is_done);
+        let hits = rt
+            .block_on(async { store.search_events("release").await })
+            .unwrap();
+        assert_eq!(hits[0].entry, stored);
+
+        // Purging the event removes its subtasks
+        rt.block_on(async { store.delete_event(&stored).await })
+            .unwrap();
+        rt.block_on(async { store.purge_event(&stored).await })
+            .unwrap();
+        let count: i64 = store
+            .connection
+            .lock()
+            .unwrap()
+            .query_row("SELECT COUNT(*) FROM subtasks", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 0);
+    }
+
+    #[test]
+    fn test_blockers() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let tag = rt
+            .block_on(async { store.add_event(&sample_entry("Tag")).await })
+            .unwrap();
+        let announce = rt
+            .block_on(async { store.add_event(&sample_entry("Announce")).await })
+            .unwrap();
+
+        let announce = rt
+            .block_on(async { store.add_blocker(&announce, &tag.unique_id).await })
+            .unwrap();
+        assert_eq!(announce.blocked_by, vec![tag.unique_id]);
+        let blocked = rt
+            .block_on(async { store.get_blocked_events().await })
+            .unwrap();
+        assert_eq!(blocked, vec![announce.clone()]);
+        let result = rt.block_on(async { store.add_blocker(&tag, &announce.unique_id).await });
+        assert!(matches!(result, Err(MiddlewareError::Validation(_))));
+        let cycle = EventEntry {
+            blocked_by: vec![announce.unique_id],
+            ..tag.clone()
+        };
+        let result = rt.block_on(async { store.update_task(&cycle).await });
+        assert!(matches!(result, Err(MiddlewareError::Validation(_))));
+
+        // Reverting the change drops the blocker again
+        let history = rt
+            .block_on(async { store.get_history(&announce.unique_id).await })
+            .unwrap();
+        let reverted = rt
+            .block_on(async {
+                store
+                    .revert_event(&announce.unique_id, &history[0].revision_id)
+                    .await
+            })
+            .unwrap();
+        assert!(reverted.blocked_by.is_empty());
+    }
+
+    #[test]
+    fn test_projects() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let mut home = Project::new("Home");
+        home.set_rgb([200, 60, 40]);
+        let work = Project {
+            position: 1,
+            ..Project::new("Work")
+        };
+        for project in [&home, &work] {
+            rt.block_on(async { store.add_project(project).await })
+                .unwrap();
+        }
+        assert!(matches!(
+            rt.block_on(async { store.add_project(&home).await }),
+            Err(MiddlewareError::Conflict { .. })
+        ));
+        home.archived = true;
+        rt.block_on(async { store.update_project(&home).await })
+            .unwrap();
+        let projects = rt.block_on(async { store.get_projects().await }).unwrap();
+        assert_eq!(projects, vec![home.clone(), work.clone()]);
+
+        let report = EventEntry {
+            project_id: Some(work.id),
+            ..sample_entry("Report")
+        };
+        let stored = rt
+            .block_on(async { store.add_event(&report).await })
+            .unwrap();
+        assert_eq!(stored.project_id, Some(work.id));
+        rt.block_on(async { store.add_event(&sample_entry("Groceries")).await })
+            .unwrap();
+        let titles = |query: EventQuery| -> Vec<String> {
+            rt.block_on(async { store.query_events(&query).await })
+                .unwrap()
+                .into_iter()
+                .map(|x| x.title)
+                .collect()
+        };
+        assert_eq!(
+            titles(EventQuery::new().in_project(work.id)),
+            vec!["Report"]
+        );
+        assert_eq!(
+            titles(EventQuery::new().without_project()),
+            vec!["Groceries"]
+        );
+
+        // The events of a deleted project are moved to the Inbox, which the history records
+        rt.block_on(async { store.delete_project(&work.id).await })
+            .unwrap();
+        assert_eq!(
+            titles(EventQuery::new().without_project()),
+            vec!["Report", "Groceries"]
+        );
+        let history = rt
+            .block_on(async { store.get_history(&report.unique_id).await })
+            .unwrap();
+        assert_eq!(history[1].changes[0].field, "project_id");
+    }
+
+    #[test]
+    fn test_add_event_twice() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        let entry = sample_entry("KrabbyDo sqlite store");
+        let rt = tokio::runtime::Runtime::new().unwrap();
+
+        rt.block_on(async { store.add_event(&entry).await })
+            .unwrap();
+        let result = rt.block_on(async { store.add_event(&entry).await });
+        assert!(matches!(result, Err(MiddlewareError::Conflict { .. })));
+    }
+
+    #[test]
+    fn test_get_today_events() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        let today = Utc::now()
+            .date_naive()
+            .and_hms_opt(12, 0, 0)
+            .unwrap()
+            .and_utc();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        for (title, date_time) in [
+            ("Yesterday", today - chrono::Duration::days(1)),
+            ("Today", today),
+            ("Tomorrow", today + chrono::Duration::days(1)),
+        ] {
+            let entry = EventEntry {
+                date_time: Some(date_time),
+                ..sample_entry(title)
+            };
+            rt.block_on(async { store.add_event(&entry).await })
+                .unwrap();
+        }
+
+        let tasks = rt
+            .block_on(async { store.get_today_events().await })
+            .unwrap();
+        assert_eq!(tasks.len(), 1);
+        assert_eq!(tasks[0].title, "Today");
+    }
+
+    #[test]
+    fn test_ensure_indexes() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+
+        let report = rt.block_on(async { store.ensure_indexes().await }).unwrap();
+        assert_eq!(
+            report.created,
+            vec![
+                "todos_date_time",
+                "todos_is_done_date_time",
+                "todos_project_id"
+            ]
+        );
+        // The full-text index was created by a migration
+        assert_eq!(report.existing, vec!["todos_search"]);
+
+        let report = rt.block_on(async { store.ensure_indexes().await }).unwrap();
+        assert!(report.created.is_empty());
+        assert_eq!(report.existing.len(), INDEXES.len());
+
+        // Date range queries use the index instead of scanning the table
+        let plan: String = store
+            .connection
+            .lock()
+            .unwrap()
+            .query_row(
+                "EXPLAIN QUERY PLAN SELECT id FROM todos WHERE date_time >= 0 AND date_time < 1",
+                [],
+                |row| row.get(3),
+            )
+            .unwrap();
+        assert!(plan.contains("todos_date_time"), "{}", plan);
+    }
+
+    #[test]
+    fn test_migrate() {
+        let connection = Connection::open_in_memory().unwrap();
+        let latest = MIGRATIONS.len() as u32;
+
+        let report = migrate_connection(&connection, true).unwrap();
+        assert_eq!((report.from_version, report.to_version), (0, latest));
+        assert_eq!(report.steps.len(), MIGRATIONS.len());
+        // A dry run leaves the database untouched
+        let version: u32 = connection
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(version, 0);
+
+        migrate_connection(&connection, false).unwrap();
+        let report = migrate_connection(&connection, false).unwrap();
+        assert_eq!((report.from_version, report.to_version), (latest, latest));
+        assert!(report.steps.is_empty());
+    }
+}
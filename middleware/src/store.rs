@@ -0,0 +1,306 @@
+//! The EventStore trait holds every CRUD and query operation on events, so the UI and the
+//! notification crate can work against any storage backend.
+
+use crate::bulk::{delete_each, update_each};
+use crate::history::{event_at_revision, Revision};
+use crate::projects::reorder;
+use crate::recurrence::{complete_occurrence, occurrences_between, Occurrence};
+use crate::tags::count_tags;
+use crate::{
+    EventChangeStream, EventEntry, EventPatch, EventQuery, EventSelection, IndexReport,
+    MiddlewareError, MigrationReport, Project, SearchHit, TagCount, Tz, UrgencyConfig,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use mongodb::bson::oid::ObjectId;
+
+/// EventStore is implemented by every storage backend of Krabby Do.
+/// Deleting an event moves it to the trash, from where it can be restored until it is purged.
+/// Updating or deleting an event which isn't stored, or is in the trash, fails with MiddlewareError::NotFound.
+/// Every create, update, delete and restore is recorded in the revision history of the event.
+#[async_trait]
+pub trait EventStore: Send + Sync {
+    /// This function adds an event to the store, keeping its unique_id and done state,
+    /// and returns the event as it was stored; adding an id which is already stored fails with Conflict
+    async fn add_event(&self, entry: &EventEntry) -> Result<EventEntry, MiddlewareError>;
+
+    /// This function updates an event in the store if it still has the version of the entry, and
+    /// returns the event as stored with its next version; an event updated by someone else since the
+    /// entry was loaded fails with Conflict
+    async fn update_task(&self, entry: &EventEntry) -> Result<EventEntry, MiddlewareError>;
+
+    /// This function moves the event to the trash by setting its deleted_at
+    async fn delete_event(&self, entry: &EventEntry) -> Result<(), MiddlewareError>;
+
+    /// This function fetches one event, whether it is in the trash or not
+    async fn get_event(&self, id: &ObjectId) -> Result<EventEntry, MiddlewareError>;
+
+    /// This function fetches all the events from the store to show on UI, leaving out the trash
+    async fn get_all_tasks(&self) -> Result<Vec<EventEntry>, MiddlewareError>;
+
+    /// This function returns the time zone in which today starts and ends for get_today_events
+    fn timezone(&self) -> Tz;
+
+    /// This function returns the coefficients the store scores the urgency of events with,
+    /// when they are queried sorted by SortKey::Urgency
+    fn urgency(&self) -> &UrgencyConfig;
+
+    /// This function fetches only todays events from the store, leaving out the trash;
+    /// today is the day under way in the time zone of the store
+    async fn get_today_events(&self) -> Result<Vec<EventEntry>, MiddlewareError>;
+
+    /// This function fetches the events matched by the query, sorted and paged as it says
+    async fn query_events(&self, query: &EventQuery) -> Result<Vec<EventEntry>, MiddlewareError>;
+
+    /// This function searches the title, details and tags of the events for the words of the text,
+    /// leaving out the trash; words match their other forms ("meetings" finds "meeting") and the
+    /// hits come best match first
+    async fn search_events(&self, text: &str) -> Result<Vec<SearchHit>, MiddlewareError>;
+
+    /// This function fetches the events in the trash
+    async fn get_trash(&self) -> Result<Vec<EventEntry>, MiddlewareError>;
+
+    /// This function takes the event out of the trash and returns it as stored
+    async fn restore_event(&self, entry: &EventEntry) -> Result<EventEntry, MiddlewareError>;
+
+    /// This function permanently removes one event from the trash
+    async fn purge_event(&self, entry: &EventEntry) -> Result<(), MiddlewareError>;
+
+    /// This function fetches the revision history of the event, oldest first
+    async fn get_history(&self, event_id: &ObjectId) -> Result<Vec<Revision>, MiddlewareError>;
+
+    /// This function brings the event back to its contents right after the given revision;
+    /// the revert itself is recorded as a new revision
+    async fn revert_event(
+        &self,
+        event_id: &ObjectId,
+        revision_id: &ObjectId,
+    ) -> Result<EventEntry, MiddlewareError> {
+        let current = self.get_event(event_id).await?;
+        let history = self.get_history(event_id).await?;
+        let entry = event_at_revision(&current, &history, revision_id)?;
+        self.update_task(&entry).await
+    }
+
+    /// This function marks the current occurrence of the event done: a recurring event moves on
+    /// to its next occurrence, any other event, or one without occurrences left, is marked done.
+    /// Like update_task it fails with Conflict when the event changed since it was loaded.
+    async fn complete_event(&self, entry: &EventEntry) -> Result<EventEntry, MiddlewareError> {
+        self.update_task(&complete_occurrence(entry, &self.timezone()))
+            .await
+    }
+
+    /// This function adds a subtask at the end of the event's checklist and returns the event as
+    /// stored; the subtask functions fail with Conflict like update_task when the event changed
+    /// since it was loaded
+    async fn add_subtask(
+        &self,
+        entry: &EventEntry,
+        title: &str,
+    ) -> Result<EventEntry, MiddlewareError> {
+        let mut changed = entry.clone();
+        changed.add_subtask(title)?;
+        self.update_task(&changed).await
+    }
+
+    /// This function moves a subtask of the event to the given position of its checklist
+    async fn move_subtask(
+        &self,
+        entry: &EventEntry,
+        subtask_id: &ObjectId,
+        position: usize,
+    ) -> Result<EventEntry, MiddlewareError> {
+        let mut changed = entry.clone();
+        changed.move_subtask(subtask_id, position)?;
+        self.update_task(&changed).await
+    }
+
+    /// This function marks a subtask of the event done or not done; an event with auto_complete
+    /// set is completed along with its last open subtask (see EventEntry::toggle_subtask)
+    async fn toggle_subtask(
+        &self,
+        entry: &EventEntry,
+        subtask_id: &ObjectId,
+    ) -> Result<EventEntry, MiddlewareError> {
+        let mut changed = entry.clone();
+        changed.toggle_subtask(subtask_id, &self.timezone())?;
+        self.update_task(&changed).await
+    }
+
+    /// This function removes a subtask from the event's checklist
+    async fn remove_subtask(
+        &self,
+        entry: &EventEntry,
+        subtask_id: &ObjectId,
+    ) -> Result<EventEntry, MiddlewareError> {
+        let mut changed = entry.clone();
+        changed.remove_subtask(subtask_id)?;
+        self.update_task(&changed).await
+    }
+
+    /// This function makes the event wait for another one and returns the event as stored; the
+    /// blocker must be another stored event outside the trash, and blockers making events wait
+    /// for each other in a cycle are refused with Validation
+    async fn add_blocker(
+        &self,
+        entry: &EventEntry,
+        blocker_id: &ObjectId,
+    ) -> Result<EventEntry, MiddlewareError> {
+        if entry.blocked_by.contains(blocker_id) {
+            return Ok(entry.clone());
+        }
+        let mut changed = entry.clone();
+        // update_task checks the blockers against the stored events
+        changed.blocked_by.push(*blocker_id);
+        self.update_task(&changed).await
+    }
+
+    /// This function stops the event from waiting for the blocker
+    async fn remove_blocker(
+        &self,
+        entry: &EventEntry,
+        blocker_id: &ObjectId,
+    ) -> Result<EventEntry, MiddlewareError> {
+        let mut changed = entry.clone();
+        changed.blocked_by.retain(|x| x != blocker_id);
+        self.update_task(&changed).await
+    }
+
+    /// This function fetches the events which aren't done and don't wait for any open blocker
+    async fn get_ready_events(&self) -> Result<Vec<EventEntry>, MiddlewareError> {
+        let events = self.get_all_tasks().await?;
+        Ok(events
+            .iter()
+            .filter(|x| !x.is_done && !x.is_blocked(&events))
+            .cloned()
+            .collect())
+    }
+
+    /// This function fetches the events which aren't done and wait for a blocker which isn't
+    /// done either
+    async fn get_blocked_events(&self) -> Result<Vec<EventEntry>, MiddlewareError> {
+        let events = self.get_all_tasks().await?;
+        Ok(events
+            .iter()
+            .filter(|x| !x.is_done && x.is_blocked(&events))
+            .cloned()
+            .collect())
+    }
+
+    /// This function lists every tag of the events outside the trash with the number of events
+    /// carrying it, the most used first
+    async fn get_tags(&self) -> Result<Vec<TagCount>, MiddlewareError> {
+        Ok(count_tags(&self.get_all_tasks().await?))
+    }
+
+    /// This function lists the occurrences of the events from (inclusive) until (exclusive),
+    /// leaving out the trash and the events without a date; recurring events are expanded in
+    /// the time zone of the store. The occurrences come in order of time.
+    async fn expand_occurrences(
+        &self,
+        from: DateTime<Utc>,
+        until: DateTime<Utc>,
+    ) -> Result<Vec<Occurrence>, MiddlewareError> {
+        let tz = self.timezone();
+        let mut occurrences: Vec<Occurrence> = self
+            .query_events(&EventQuery::new().dated(true))
+            .await?
+            .iter()
+            .flat_map(|entry| occurrences_between(entry, from, until, &tz))
+            .collect();
+        occurrences.sort_by_key(|x| x.date_time);
+        Ok(occurrences)
+    }
+
+    /// This function applies the patch to every selected event; each change is versioned and
+    /// recorded in the history. It returns the events before and after the change; events the
+    /// patch doesn't change are left out. The stores of the middleware change all the events or,
+    /// on an error, none of them. This default goes through update_task one event at a time, so
+    /// there an error stops the events after it from being changed while those before it keep
+    /// the change.
+    async fn update_many(
+        &self,
+        selection: &EventSelection,
+        patch: &EventPatch,
+    ) -> Result<Vec<(EventEntry, EventEntry)>, MiddlewareError> {
+        update_each(self, selection, patch).await
+    }
+
+    /// This function moves every selected event to the trash and returns them as they were
+    /// deleted; like update_many, the default goes one event at a time and stops at the first
+    /// error
+    async fn delete_many(
+        &self,
+        selection: &EventSelection,
+    ) -> Result<Vec<EventEntry>, MiddlewareError> {
+        delete_each(self, selection).await
+    }
+
+    /// This function adds a project and returns it as stored; a project without a name or with a
+    /// color which isn't "#rrggbb" is refused with Validation, and an id which is already stored
+    /// fails with Conflict
+    async fn add_project(&self, project: &Project) -> Result<Project, MiddlewareError>;
+
+    /// This function updates the name, color, archived flag and position of a project; checked
+    /// like add_project
+    async fn update_project(&self, project: &Project) -> Result<Project, MiddlewareError>;
+
+    /// This function removes a project from the store, leaving its events as they are; see
+    /// delete_project for moving them to the Inbox first
+    async fn remove_project(&self, project_id: &ObjectId) -> Result<(), MiddlewareError>;
+
+    /// This function fetches every project, archived ones included, in the order of their position
+    async fn get_projects(&self) -> Result<Vec<Project>, MiddlewareError>;
+
+    /// This function deletes a project after moving its events to the Inbox, each one recorded in
+    /// its history; events in the trash keep the id of the project
+    async fn delete_project(&self, project_id: &ObjectId) -> Result<(), MiddlewareError> {
+        let selection = EventSelection::Query(EventQuery::new().in_project(*project_id));
+        self.update_many(&selection, &EventPatch::new().move_to_project(None))
+            .await?;
+        self.remove_project(project_id).await
+    }
+
+    /// This function moves a project to the given position among the projects, or to the end
+    /// when the position is past it, and returns the projects in their new order
+    async fn move_project(
+        &self,
+        project_id: &ObjectId,
+        position: usize,
+    ) -> Result<Vec<Project>, MiddlewareError> {
+        let projects = self.get_projects().await?;
+        let mut moved = reorder(projects.clone(), project_id, position)?;
+        for project in moved.iter_mut() {
+            // Only the projects whose position changed are written
+            if !projects.contains(project) {
+                *project = self.update_project(project).await?;
+            }
+        }
+        Ok(moved)
+    }
+
+    /// This function permanently removes the events which were deleted longer than retention ago
+    /// and returns how many were removed; a zero retention empties the trash
+    async fn purge_trash(&self, retention: Duration) -> Result<u64, MiddlewareError>;
+
+    /// This function brings the stored events up to the current schema version;
+    /// with dry_run set nothing is changed and the report lists what each pending step would change
+    async fn migrate(&self, dry_run: bool) -> Result<MigrationReport, MiddlewareError> {
+        Ok(MigrationReport {
+            dry_run,
+            ..Default::default()
+        })
+    }
+
+    /// This function subscribes to the changes made to the events by anyone, when the store can
+    /// push them; stores which can't return None and are polled instead (see KrabbyClient::subscribe)
+    async fn watch(&self) -> Result<Option<EventChangeStream>, MiddlewareError> {
+        Ok(None)
+    }
+
+    /// This function creates the indexes the queries need which are missing and reports which
+    /// were created and which were already present; stores without indexes report none
+    async fn ensure_indexes(&self) -> Result<IndexReport, MiddlewareError> {
+        Ok(IndexReport::default())
+    }
+}
@@ -0,0 +1,209 @@
+//! Subtasks break an event into an ordered checklist of steps, e.g. "Prepare release" into
+//! "Tag the commit" and "Write the changelog". The functions here change the subtasks of an
+//! EventEntry; they are stored along with the event by update_task, so changes to them are
+//! versioned and recorded in the history like any other field.
+
+use crate::recurrence::complete_occurrence;
+use crate::{EventEntry, MiddlewareError, Tz};
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+/// Subtask is one step of an event
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Subtask {
+    /// Identifies the subtask within its event
+    pub id: ObjectId,
+    /// What is to be done
+    pub title: String,
+    /// Denotates if the step is done or not
+    pub is_done: bool,
+}
+
+impl Subtask {
+    /// A step which isn't done yet
+    pub fn new(title: &str) -> Self {
+        Subtask {
+            id: ObjectId::new(),
+            title: title.to_string(),
+            is_done: false,
+        }
+    }
+}
+
+impl EventEntry {
+    /// Position of the subtask in the list; NotFound when the event has no such subtask
+    fn subtask_position(&self, id: &ObjectId) -> Result<usize, MiddlewareError> {
+        self.subtasks
+            .iter()
+            .position(|x| x.id == *id)
+            .ok_or(MiddlewareError::NotFound { id: *id })
+    }
+
+    /// Adds a step at the end of the list and returns its id; the title can't be blank and is
+    /// kept on one line
+    pub fn add_subtask(&mut self, title: &str) -> Result<ObjectId, MiddlewareError> {
+        let title = title.trim().replace('\n', " ");
+        if title.is_empty() {
+            return Err(MiddlewareError::Validation(String::from(
+                "A subtask needs a title",
+            )));
+        }
+        let subtask = Subtask::new(&title);
+        let id = subtask.id;
+        self.subtasks.push(subtask);
+        Ok(id)
+    }
+
+    /// Moves a step to the given position, or to the end when the position is past it
+    pub fn move_subtask(&mut self, id: &ObjectId, position: usize) -> Result<(), MiddlewareError> {
+        let subtask = self.subtasks.remove(self.subtask_position(id)?);
+        let position = position.min(self.subtasks.len());
+        self.subtasks.insert(position, subtask);
+        Ok(())
+    }
+
+    /// Marks a step done, or not done when it was. With auto_complete set, checking the last open
+    /// step completes the event, moving a recurring one on to its next occurrence (see
+    /// complete_occurrence), and unchecking a step of a done event reopens it.
+    pub fn toggle_subtask(&mut self, id: &ObjectId, tz: &Tz) -> Result<(), MiddlewareError> {
+        let position = self.subtask_position(id)?;
+        let subtask = &mut self.subtasks[position];
+        subtask.is_done = !subtask.is_done;
+        if self.auto_complete {
+            let (done, total) = self.subtask_progress();
+            if done == total && !self.is_done {
+                *self = complete_occurrence(self, tz);
+            } else if done < total && self.is_done {
+                self.is_done = false;
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes a step
+    pub fn remove_subtask(&mut self, id: &ObjectId) -> Result<(), MiddlewareError> {
+        self.subtasks.remove(self.subtask_position(id)?);
+        Ok(())
+    }
+
+    /// Number of steps done and number of steps
+    pub fn subtask_progress(&self) -> (usize, usize) {
+        let done = self.subtasks.iter().filter(|x| x.is_done).count();
+        (done, self.subtasks.len())
+    }
+}
+
+/// Text form of the subtasks kept in the history: one "- [x] title" or "- [ ] title" line per step
+pub(crate) fn subtasks_to_text(subtasks: &[Subtask]) -> String {
+    subtasks
+        .iter()
+        .map(|x| format!("- [{}] {}", if x.is_done { "x" } else { " " }, x.title))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Reads the subtasks back from their text form; steps keep the id of a current step with the
+/// same title, so reverting a change doesn't give every step a new id
+pub(crate) fn subtasks_from_text(text: &str, current: &[Subtask]) -> Option<Vec<Subtask>> {
+    let mut unused: Vec<&Subtask> = current.iter().collect();
+    text.lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let (is_done, title) = match line.get(..6)? {
+                "- [x] " => (true, &line[6..]),
+                "- [ ] " => (false, &line[6..]),
+                _ => return None,
+            };
+            let id = match unused.iter().position(|x| x.title == title) {
+                Some(position) => unused.remove(position).id,
+                None => ObjectId::new(),
+            };
+            Some(Subtask {
+                id,
+                title: title.to_string(),
+                is_done,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Frequency, Recurrence};
+    use chrono::{TimeZone, Utc};
+
+    fn release() -> EventEntry {
+        EventEntry::new(
+            ObjectId::new(),
+            String::from("Prepare release"),
+            String::from("Version 2"),
+            Utc.with_ymd_and_hms(2023, 6, 9, 17, 0, 0).unwrap(),
+            false,
+            String::from("Work"),
+        )
+    }
+
+    #[test]
+    fn test_subtasks() {
+        let mut entry = release();
+        let tag = entry.add_subtask("Tag the commit").unwrap();
+        let changelog = entry.add_subtask(" Write the changelog ").unwrap();
+        let announce = entry.add_subtask("Announce it").unwrap();
+        assert!(entry.add_subtask("  ").is_err());
+
+        entry.move_subtask(&changelog, 0).unwrap();
+        entry.move_subtask(&tag, 99).unwrap();
+        let titles: Vec<&str> = entry.subtasks.iter().map(|x| x.title.as_str()).collect();
+        assert_eq!(
+            titles,
+            vec!["Write the changelog", "Announce it", "Tag the commit"]
+        );
+
+        entry.toggle_subtask(&tag, &Tz::UTC).unwrap();
+        entry.remove_subtask(&announce).unwrap();
+        assert_eq!(entry.subtask_progress(), (1, 2));
+        assert!(matches!(
+            entry.remove_subtask(&announce),
+            Err(MiddlewareError::NotFound { .. })
+        ));
+
+        // The text kept in the history reads back into the same steps
+        let text = subtasks_to_text(&entry.subtasks);
+        assert_eq!(text, "- [ ] Write the changelog\n- [x] Tag the commit");
+        assert_eq!(
+            subtasks_from_text(&text, &entry.subtasks),
+            Some(entry.subtasks.clone())
+        );
+        assert_eq!(subtasks_from_text("", &entry.subtasks), Some(Vec::new()));
+        assert_eq!(subtasks_from_text("Tag it", &entry.subtasks), None);
+    }
+
+    #[test]
+    fn test_auto_complete() {
+        let mut entry = release();
+        let first = entry.add_subtask("First").unwrap();
+        let second = entry.add_subtask("Second").unwrap();
+        entry.toggle_subtask(&first, &Tz::UTC).unwrap();
+        entry.toggle_subtask(&second, &Tz::UTC).unwrap();
+        // Without auto_complete the event stays open
+        assert!(!entry.is_done);
+
+        entry.auto_complete = true;
+        entry.toggle_subtask(&second, &Tz::UTC).unwrap();
+        entry.toggle_subtask(&second, &Tz::UTC).unwrap();
+        assert!(entry.is_done);
+        entry.toggle_subtask(&first, &Tz::UTC).unwrap();
+        assert!(!entry.is_done);
+
+        // A recurring event moves on with its steps open again
+        entry.recurrence = Some(Recurrence::new(Frequency::Daily));
+        entry.toggle_subtask(&first, &Tz::UTC).unwrap();
+        assert!(!entry.is_done);
+        assert_eq!(
+            entry.date_time,
+            Some(Utc.with_ymd_and_hms(2023, 6, 10, 17, 0, 0).unwrap())
+        );
+        assert_eq!(entry.subtask_progress(), (0, 2));
+    }
+}
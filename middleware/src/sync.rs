@@ -0,0 +1,132 @@
+//! Live sync between running instances of Krabby Do.
+//! A subscription yields every event added, changed or removed by anyone using the same store:
+//! MongoDB pushes them through a change stream, other stores are polled and compared with the
+//! events seen the last time.
+
+use crate::{EventEntry, EventStore, MiddlewareError};
+use futures_util::stream::{self, Stream};
+use mongodb::bson::oid::ObjectId;
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// EventChange describes one change of the stored events
+#[derive(Debug, Clone, PartialEq)]
+pub enum EventChange {
+    /// An event was added, or came back from the trash
+    Inserted(EventEntry),
+    /// An event was changed; holds its new contents
+    Updated(EventEntry),
+    /// An event was moved to the trash or removed for good
+    Deleted(ObjectId),
+}
+
+/// Stream of the changes made to the stored events; it ends only when the store goes away
+pub type EventChangeStream =
+    Pin<Box<dyn Stream<Item = Result<EventChange, MiddlewareError>> + Send>>;
+
+/// Lists the changes which turn the old list of events into the new one:
+/// insertions and updates in the order of the new list, then removals in the order of the old one
+pub fn diff_snapshots(old: &[EventEntry], new: &[EventEntry]) -> Vec<EventChange> {
+    let mut changes = Vec::new();
+    for entry in new {
+        match old.iter().find(|x| x.unique_id == entry.unique_id) {
+            None => changes.push(EventChange::Inserted(entry.clone())),
+            Some(previous) if previous != entry => {
+                changes.push(EventChange::Updated(entry.clone()))
+            }
+            Some(_) => {}
+        }
+    }
+    for entry in old {
+        if !new.iter().any(|x| x.unique_id == entry.unique_id) {
+            changes.push(EventChange::Deleted(entry.unique_id));
+        }
+    }
+    changes
+}
+
+/// Subscribes to the changes of a store which can't push them, by loading its events every interval;
+/// only changes made after the subscription are reported
+pub async fn poll_changes(
+    store: Arc<dyn EventStore>,
+    interval: Duration,
+) -> Result<EventChangeStream, MiddlewareError> {
+    let snapshot = store.get_all_tasks().await?;
+    let state = (store, snapshot, VecDeque::new());
+    let changes = stream::unfold(
+        state,
+        move |(store, mut snapshot, mut pending)| async move {
+            loop {
+                if let Some(change) = pending.pop_front() {
+                    return Some((Ok(change), (store, snapshot, pending)));
+                }
+                tokio::time::sleep(interval).await;
+                match store.get_all_tasks().await {
+                    Ok(current) => {
+                        pending.extend(diff_snapshots(&snapshot, &current));
+                        snapshot = current;
+                    }
+                    Err(error) => return Some((Err(error), (store, snapshot, pending))),
+                }
+            }
+        },
+    );
+    Ok(Box::pin(changes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample_entry;
+    use crate::MemoryStore;
+    use futures_util::StreamExt;
+
+    #[test]
+    fn test_diff_snapshots() {
+        let kept = sample_entry("Kept");
+        let changed = sample_entry("Changed");
+        let removed = sample_entry("Removed");
+        let added = sample_entry("Added");
+        let mut renamed = changed.clone();
+        renamed.title = String::from("Renamed");
+
+        let old = vec![kept.clone(), changed, removed.clone()];
+        let new = vec![kept, renamed.clone(), added.clone()];
+        assert_eq!(
+            diff_snapshots(&old, &new),
+            vec![
+                EventChange::Updated(renamed),
+                EventChange::Inserted(added),
+                EventChange::Deleted(removed.unique_id),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_poll_changes() {
+        let store: Arc<dyn EventStore> = Arc::new(MemoryStore::new());
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let mut changes = rt
+            .block_on(poll_changes(store.clone(), Duration::from_millis(10)))
+            .unwrap();
+        let mut next = || {
+            rt.block_on(async {
+                tokio::time::timeout(Duration::from_secs(5), changes.next()).await
+            })
+            .unwrap()
+            .unwrap()
+            .unwrap()
+        };
+
+        let mut entry = sample_entry("Synced");
+        rt.block_on(store.add_event(&entry)).unwrap();
+        assert_eq!(next(), EventChange::Inserted(entry.clone()));
+        entry.is_done = true;
+        entry = rt.block_on(store.update_task(&entry)).unwrap();
+        assert_eq!(next(), EventChange::Updated(entry.clone()));
+        rt.block_on(store.delete_event(&entry)).unwrap();
+        assert_eq!(next(), EventChange::Deleted(entry.unique_id));
+    }
+}
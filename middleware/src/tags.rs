@@ -0,0 +1,127 @@
+//! Tags label events, e.g. "work" or "home". They are kept normalized: lowercase, without commas
+//! or whitespace, and each tag at most once per event, in the order they were added. Tags written
+//! as free text by older versions, like "Work, home", are split into ["work", "home"] when read.
+
+use crate::EventEntry;
+use serde::{Deserialize, Deserializer};
+
+/// TagCount is a tag along with the number of events carrying it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TagCount {
+    /// The normalized tag
+    pub tag: String,
+    /// Number of events outside the trash carrying the tag
+    pub count: usize,
+}
+
+/// Splits text into normalized tags; commas and whitespace separate tags, and a tag given twice,
+/// in any case, is kept once
+pub fn parse_tags(text: &str) -> Vec<String> {
+    let mut tags: Vec<String> = Vec::new();
+    for tag in text.split(|c: char| c == ',' || c.is_whitespace()) {
+        let tag = tag.to_lowercase();
+        if !tag.is_empty() && !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+    tags
+}
+
+/// Counts the tags of the events, the most used first and tags used as often by name
+pub(crate) fn count_tags(events: &[EventEntry]) -> Vec<TagCount> {
+    let mut counts: Vec<TagCount> = Vec::new();
+    for tag in events.iter().flat_map(|x| x.tags.iter()) {
+        match counts.iter_mut().find(|x| x.tag == *tag) {
+            Some(count) => count.count += 1,
+            None => counts.push(TagCount {
+                tag: tag.clone(),
+                count: 1,
+            }),
+        }
+    }
+    counts.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.tag.cmp(&b.tag)));
+    counts
+}
+
+/// Text form of the tags kept in the history and by SQLite: the tags separated by commas
+pub(crate) fn tags_to_text(tags: &[String]) -> String {
+    tags.join(", ")
+}
+
+/// Reads the tags of a saved event, either as a list or as the text written by older versions,
+/// where null stands for no tags
+pub(crate) fn deserialize_tags<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Tags {
+        List(Vec<String>),
+        Text(Option<String>),
+    }
+
+    Ok(match Tags::deserialize(deserializer)? {
+        Tags::List(tags) => parse_tags(&tags.join(",")),
+        Tags::Text(text) => parse_tags(text.as_deref().unwrap_or_default()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use mongodb::bson::{doc, from_document, oid::ObjectId};
+
+    #[test]
+    fn test_parse_tags() {
+        assert_eq!(
+            parse_tags(" Work, home\twork,,Errands "),
+            vec!["work", "home", "errands"]
+        );
+        assert!(parse_tags(" , ").is_empty());
+        assert_eq!(tags_to_text(&parse_tags("Work home")), "work, home");
+
+        #[derive(Deserialize)]
+        struct Saved {
+            #[serde(default, deserialize_with = "deserialize_tags")]
+            tags: Vec<String>,
+        }
+        let tags = |document| from_document::<Saved>(document).unwrap().tags;
+        assert_eq!(tags(doc! { "tags": "Work, home" }), vec!["work", "home"]);
+        assert_eq!(tags(doc! { "tags": ["Home", "home"] }), vec!["home"]);
+        assert!(tags(doc! { "tags": null }).is_empty());
+        assert!(tags(doc! {}).is_empty());
+    }
+
+    #[test]
+    fn test_count_tags() {
+        let entry = |tags: &str| {
+            EventEntry::new(
+                ObjectId::new(),
+                String::from("Title"),
+                String::from("Details"),
+                Utc::now(),
+                false,
+                String::from(tags),
+            )
+        };
+        let events = vec![entry("work home"), entry("errands"), entry("Work")];
+        assert_eq!(
+            count_tags(&events),
+            vec![
+                TagCount {
+                    tag: String::from("work"),
+                    count: 2
+                },
+                TagCount {
+                    tag: String::from("errands"),
+                    count: 1
+                },
+                TagCount {
+                    tag: String::from("home"),
+                    count: 1
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,111 @@
+//! Time zone of the user.
+//! Events are stored in UTC; the zone is used to turn the date and time the user picks into UTC,
+//! to show stored times in local time and to work out where a day starts and ends.
+
+use crate::MiddlewareError;
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, Offset, TimeZone, Utc};
+pub use chrono_tz::Tz;
+
+/// Parses an IANA time zone name such as "America/Los_Angeles"
+pub fn parse_timezone(name: &str) -> Result<Tz, MiddlewareError> {
+    name.parse()
+        .map_err(|_| MiddlewareError::Config(format!("Unknown time zone \"{}\"", name)))
+}
+
+/// Zone of the system: the `TZ` variable when it names an IANA zone, then the zone the OS is set to,
+/// and UTC when neither can be found
+pub fn local_timezone() -> Tz {
+    std::env::var("TZ")
+        .ok()
+        .and_then(|name| parse_timezone(name.trim_start_matches(':')).ok())
+        .or_else(|| {
+            iana_time_zone::get_timezone()
+                .ok()
+                .and_then(|name| parse_timezone(&name).ok())
+        })
+        .unwrap_or(Tz::UTC)
+}
+
+/// Turns a wall clock time of the zone into UTC.
+/// A time repeated when the clocks go back is taken the first time it occurs, and a time skipped
+/// when the clocks go forward is moved past the gap, the way a wall clock would read it.
+pub fn local_to_utc(local: NaiveDateTime, tz: &Tz) -> DateTime<Utc> {
+    if let Some(date_time) = tz.from_local_datetime(&local).earliest() {
+        return date_time.with_timezone(&Utc);
+    }
+    // Read with the offset in use before the gap, the time lands as far past the gap as it was
+    // into it, e.g. 2:37 AM becomes 3:37 AM when the clocks skip an hour at 2 AM
+    let offset_before = tz
+        .offset_from_utc_datetime(&(local - Duration::days(1)))
+        .fix();
+    Utc.from_utc_datetime(&(local - Duration::seconds(offset_before.local_minus_utc().into())))
+}
+
+/// Date of the day which is under way in the zone
+pub fn today(tz: &Tz) -> NaiveDate {
+    Utc::now().with_timezone(tz).date_naive()
+}
+
+/// Start (inclusive) and end (exclusive) of the day in the zone, in UTC;
+/// days are 23 or 25 hours long when the clocks change
+pub fn day_bounds(date: NaiveDate, tz: &Tz) -> (DateTime<Utc>, DateTime<Utc>) {
+    let start_of = |date: NaiveDate| local_to_utc(date.and_hms_opt(0, 0, 0).unwrap(), tz);
+    let next_day = date.succ_opt().unwrap_or(date);
+    (start_of(date), start_of(next_day))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_local_to_utc() {
+        let tz = parse_timezone("America/Los_Angeles").unwrap();
+        // 6:30 PM in Portland in summer is 1:30 AM UTC the day after
+        let local = NaiveDate::from_ymd_opt(2023, 6, 9)
+            .unwrap()
+            .and_hms_opt(18, 30, 0)
+            .unwrap();
+        assert_eq!(
+            local_to_utc(local, &tz),
+            Utc.with_ymd_and_hms(2023, 6, 10, 1, 30, 0).unwrap()
+        );
+
+        // 2:30 and 2:37 AM don't exist on the day the clocks go forward, so they are moved an hour
+        // on to 3:30 and 3:37 AM PDT
+        for minute in [30, 37] {
+            let skipped = NaiveDate::from_ymd_opt(2023, 3, 12)
+                .unwrap()
+                .and_hms_opt(2, minute, 0)
+                .unwrap();
+            assert_eq!(
+                local_to_utc(skipped, &tz),
+                Utc.with_ymd_and_hms(2023, 3, 12, 10, minute, 0).unwrap()
+            );
+        }
+
+        // Zones east of UTC skip their hour in the same way
+        let berlin = parse_timezone("Europe/Berlin").unwrap();
+        let skipped = NaiveDate::from_ymd_opt(2023, 3, 26)
+            .unwrap()
+            .and_hms_opt(2, 37, 0)
+            .unwrap();
+        assert_eq!(
+            local_to_utc(skipped, &berlin),
+            Utc.with_ymd_and_hms(2023, 3, 26, 1, 37, 0).unwrap()
+        );
+        assert!(parse_timezone("Mars/Olympus_Mons").is_err());
+    }
+
+    #[test]
+    fn test_day_bounds() {
+        let tz = parse_timezone("America/Los_Angeles").unwrap();
+        let (start, end) = day_bounds(NaiveDate::from_ymd_opt(2023, 6, 9).unwrap(), &tz);
+        assert_eq!(start, Utc.with_ymd_and_hms(2023, 6, 9, 7, 0, 0).unwrap());
+        assert_eq!(end, Utc.with_ymd_and_hms(2023, 6, 10, 7, 0, 0).unwrap());
+
+        // The day the clocks go back lasts 25 hours
+        let (start, end) = day_bounds(NaiveDate::from_ymd_opt(2023, 11, 5).unwrap(), &tz);
+        assert_eq!(end - start, Duration::hours(25));
+    }
+}
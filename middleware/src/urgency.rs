@@ -0,0 +1,216 @@
+//! Priority of the events and the urgency score they are ordered by.
+//! Like Taskwarrior's, the urgency adds up a term per property of the event (its priority, how
+//! close it is due, whether it is overdue, its tags and its age), each multiplied by a coefficient
+//! which can be changed in the [urgency] table of the config.
+
+use crate::{EventEntry, MiddlewareError};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// How important an event is; events have no priority unless one is given
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    #[default]
+    None,
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+
+impl Priority {
+    /// Every priority, from the lowest to the highest
+    pub const ALL: [Priority; 5] = [
+        Priority::None,
+        Priority::Low,
+        Priority::Medium,
+        Priority::High,
+        Priority::Critical,
+    ];
+
+    /// Name of the priority as stored
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Priority::None => "none",
+            Priority::Low => "low",
+            Priority::Medium => "medium",
+            Priority::High => "high",
+            Priority::Critical => "critical",
+        }
+    }
+}
+
+impl std::fmt::Display for Priority {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for Priority {
+    type Err = MiddlewareError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Priority::ALL
+            .into_iter()
+            .find(|x| x.as_str().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| MiddlewareError::Validation(format!("Unknown priority \"{}\"", s)))
+    }
+}
+
+/// Coefficients of the urgency score; a coefficient of zero leaves its property out
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct UrgencyConfig {
+    /// Added for events of low priority
+    pub priority_low: f64,
+    /// Added for events of medium priority
+    pub priority_medium: f64,
+    /// Added for events of high priority
+    pub priority_high: f64,
+    /// Added for events of critical priority
+    pub priority_critical: f64,
+    /// Weight of how close the event is due: a fifth of it two weeks ahead, all of it a week overdue
+    pub due: f64,
+    /// Added on top of `due` once the event is overdue
+    pub overdue: f64,
+    /// Weight of having tags: 80% of it for one tag, 90% for two and all of it for three or more
+    pub tags: f64,
+    /// Added for each tag the event carries, by tag
+    pub tag_coefficients: BTreeMap<String, f64>,
+    /// Weight of the age of the event, which grows until it is `age_max_days` old
+    pub age: f64,
+    /// Age in days at which an event gets all of the `age` weight
+    pub age_max_days: u32,
+}
+
+impl Default for UrgencyConfig {
+    fn default() -> Self {
+        UrgencyConfig {
+            priority_low: 1.8,
+            priority_medium: 3.9,
+            priority_high: 6.0,
+            priority_critical: 9.0,
+            due: 12.0,
+            overdue: 2.0,
+            tags: 1.0,
+            tag_coefficients: BTreeMap::new(),
+            age: 2.0,
+            age_max_days: 365,
+        }
+    }
+}
+
+impl UrgencyConfig {
+    /// Urgency of the event at the given time; the higher it is, the sooner the event should be done.
+    /// The age of an event is taken from the time its id was created.
+    pub fn score(&self, entry: &EventEntry, now: DateTime<Utc>) -> f64 {
+        let priority = match entry.priority {
+            Priority::None => 0.0,
+            Priority::Low => self.priority_low,
+            Priority::Medium => self.priority_medium,
+            Priority::High => self.priority_high,
+            Priority::Critical => self.priority_critical,
+        };
+
+        let (due, overdue) = match entry.date_time {
+            Some(date_time) => {
+                let days = (date_time - now).num_seconds() as f64 / 86_400.0;
+                // From 0.2 two weeks ahead up to 1.0 a week overdue, linear in between
+                let due = ((14.0 - days) * 0.8 / 21.0 + 0.2).clamp(0.2, 1.0);
+                let overdue = if days < 0.0 { self.overdue } else { 0.0 };
+                (due * self.due, overdue)
+            }
+            None => (0.0, 0.0),
+        };
+
+        let tags_factor = match entry.tags.len() {
+            0 => 0.0,
+            1 => 0.8,
+            2 => 0.9,
+            _ => 1.0,
+        };
+        let tag_coefficients: f64 = entry
+            .tags
+            .iter()
+            .filter_map(|tag| self.tag_coefficients.get(tag))
+            .sum();
+
+        let created = entry.unique_id.timestamp().timestamp_millis();
+        let age_days = (now.timestamp_millis() - created).max(0) as f64 / 86_400_000.0;
+        let age = match self.age_max_days {
+            0 => self.age,
+            max_days => (age_days / f64::from(max_days)).min(1.0) * self.age,
+        };
+
+        priority + due + overdue + tags_factor * self.tags + tag_coefficients + age
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_tags;
+    use chrono::{Duration, TimeZone};
+    use mongodb::bson::oid::ObjectId;
+
+    #[test]
+    fn test_priority() {
+        assert_eq!("High".parse::<Priority>().unwrap(), Priority::High);
+        assert!("urgent".parse::<Priority>().is_err());
+        assert!(Priority::Critical > Priority::Low);
+        assert_eq!(Priority::default(), Priority::None);
+    }
+
+    #[test]
+    fn test_score() {
+        let now = Utc.with_ymd_and_hms(2023, 6, 9, 12, 0, 0).unwrap();
+        // Created just now, so the age adds nothing
+        let entry = |date_time: Option<DateTime<Utc>>, priority: Priority, tags: &str| EventEntry {
+            unique_id: ObjectId::from_parts(now.timestamp() as u32, [0; 5], [0; 3]),
+            date_time,
+            priority,
+            tags: parse_tags(tags),
+            ..EventEntry::new(
+                ObjectId::new(),
+                String::from("Title"),
+                String::from("Details"),
+                now,
+                false,
+                String::new(),
+            )
+        };
+        let config = UrgencyConfig::default();
+        let score = |x: &EventEntry| (config.score(x, now) * 100.0).round() / 100.0;
+
+        // A critical task due tomorrow outranks a low one due today
+        let critical = entry(Some(now + Duration::days(1)), Priority::Critical, "");
+        let low = entry(Some(now), Priority::Low, "");
+        assert_eq!(score(&critical), 17.34);
+        assert_eq!(score(&low), 10.6);
+        // Overdue by a week or more gets all of the due weight and the overdue bonus
+        let overdue = entry(Some(now - Duration::days(10)), Priority::None, "");
+        assert_eq!(score(&overdue), 12.0 + 2.0);
+        assert_eq!(
+            score(&entry(Some(now + Duration::days(30)), Priority::None, "")),
+            2.4
+        );
+
+        let undated = entry(None, Priority::None, "Work, urgent");
+        assert_eq!(score(&undated), 0.9);
+        let config = UrgencyConfig {
+            tag_coefficients: BTreeMap::from([(String::from("urgent"), 5.0)]),
+            age: 0.0,
+            ..UrgencyConfig::default()
+        };
+        assert_eq!((config.score(&undated, now) * 100.0).round() / 100.0, 5.9);
+
+        // The age counts from the time the id was made
+        let old = EventEntry {
+            unique_id: ObjectId::from_parts((now.timestamp() - 73 * 86_400) as u32, [0; 5], [0; 3]),
+            ..entry(None, Priority::None, "")
+        };
+        assert_eq!(score(&old), 0.4);
+    }
+}
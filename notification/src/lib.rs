@@ -1,13 +1,11 @@
 //! This crate will generate the notifications for the event fetched from database which are due today
 //!It uses notify-rust crate to do so.
-use middleware::EventEntry;
+use middleware::{EventEntry, KrabbyClient, MiddlewareError};
 use notify_rust::Notification;
 
 ///This function will call middleware crate to fetch events for due for today
-fn fetch_events_for_today() -> Result<Vec<EventEntry>, Box<dyn std::error::Error>> {
-    let rt = tokio::runtime::Runtime::new().unwrap();
-    let notification_task_list =
-        rt.block_on(async { EventEntry::get_today_events().await.unwrap() });
+fn fetch_events_for_today(client: &KrabbyClient) -> Result<Vec<EventEntry>, MiddlewareError> {
+    let notification_task_list = client.block_on(client.store().get_today_events())?;
 
     #[cfg(feature = "print_debug_log")]
     println!("{:?}", notification_task_list);
@@ -16,16 +14,32 @@ fn fetch_events_for_today() -> Result<Vec<EventEntry>, Box<dyn std::error::Error
 }
 
 ///This function will generate notification for all events one by one
-pub fn send_notifications() {
-    let notification_task_list = fetch_events_for_today().unwrap();
+pub fn send_notifications(client: &KrabbyClient) {
+    let notification_task_list = match fetch_events_for_today(client) {
+        Ok(notification_task_list) => notification_task_list,
+        Err(MiddlewareError::Connection(error)) => {
+            // Nothing to notify about while the database can't be reached; try again next time
+            eprintln!(
+                "Skipping notifications, the database is unreachable: {}",
+                error
+            );
+            return;
+        }
+        Err(error) => {
+            eprintln!("Could not fetch today's events: {}", error);
+            return;
+        }
+    };
     for notification_task in notification_task_list {
         // Perform actions on each notification_task
         if !notification_task.is_done {
-            Notification::new()
+            if let Err(error) = Notification::new()
                 .summary(&notification_task.title)
-                .body(&notification_task.details)
+                .body(notification_task.details.as_deref().unwrap_or_default())
                 .show()
-                .unwrap();
+            {
+                eprintln!("Could not show notification: {}", error);
+            }
         }
     }
 }
@@ -1,17 +1,27 @@
+use crate::undo::{Command, UndoStack};
 use bson::oid::ObjectId;
 use chrono::offset::*;
 use chrono::DateTime;
-use chrono::Datelike;
 use chrono::NaiveDate;
 use chrono::Timelike;
+use chrono::Weekday;
 use egui::{
-    menu, widgets, Align, CentralPanel, Checkbox, Direction, DragValue, Label, Layout, ScrollArea,
-    SidePanel, TopBottomPanel, Ui, Window,
+    menu, widgets, Align, Button, CentralPanel, Checkbox, Color32, Direction, DragValue, Key,
+    Label, Layout, Modifiers, ProgressBar, RichText, ScrollArea, SidePanel, TextEdit,
+    TopBottomPanel, Ui, Window,
+};
+use futures_util::StreamExt;
+use middleware::{
+    complete_occurrence, day_bounds, local_to_utc, parse_tags, today, EventChange, EventEntry,
+    EventPatch, EventSelection, Frequency, KrabbyClient, MemoryStore, MiddlewareError, Priority,
+    Project, Recurrence, Revision, SortKey, TagCount, Tz,
 };
-use middleware::EventEntry;
 use notification::send_notifications;
 use std::fs::File;
 use std::io::prelude::*;
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 // https://stackoverflow.com/questions/48071513/how-to-use-one-module-from-another-module-in-a-rust-cargo-project
 // GUI elements' dimension values segregated in a different file for ease of modification
@@ -19,6 +29,12 @@ use std::io::prelude::*;
 mod style;
 use style::style_constants;
 
+/// Time the search box has to stay unchanged before the search is sent to the store
+const SEARCH_DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// How often stores which can't push changes are asked for changes made by other instances
+const SYNC_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
 // egui template sourced from:
 // https://github.com/emilk/eframe_template
 
@@ -29,6 +45,172 @@ enum AmPm {
     Pm,
 }
 
+/// Enum to choose how a recurring event ends in the New / Edit Event dialog
+#[derive(Clone, Copy, PartialEq)]
+enum RepeatEnd {
+    Never,
+    AfterCount,
+    OnDate,
+}
+
+/// Frequencies offered by the Repeat field of the New / Edit Event dialog
+const FREQUENCIES: [Frequency; 4] = [
+    Frequency::Daily,
+    Frequency::Weekly,
+    Frequency::Monthly,
+    Frequency::Yearly,
+];
+
+/// Days of the week offered for daily and weekly events, in the order shown
+const WEEKDAYS: [Weekday; 7] = [
+    Weekday::Mon,
+    Weekday::Tue,
+    Weekday::Wed,
+    Weekday::Thu,
+    Weekday::Fri,
+    Weekday::Sat,
+    Weekday::Sun,
+];
+
+/// Enum to choose what the central panel shows about the selected event
+#[derive(PartialEq)]
+enum CentralPanelTab {
+    Details,
+    History,
+}
+
+/// Enum to name the event lists shown in the side panels
+#[derive(Clone, Copy, PartialEq)]
+enum EventList {
+    /// Events not done yet which have a date
+    Upcoming,
+    /// Events not done yet without a date
+    NoDate,
+    /// Events marked done
+    Done,
+}
+
+impl EventList {
+    /// List the event is shown in
+    fn of(entry: &EventEntry) -> Self {
+        if entry.is_done {
+            EventList::Done
+        } else if entry.date_time.is_none() {
+            EventList::NoDate
+        } else {
+            EventList::Upcoming
+        }
+    }
+}
+
+/// Names of the fields compared in the Merge dialog, in the order of MergeConflict::keep_mine
+const MERGE_FIELDS: [&str; 10] = [
+    "Title",
+    "Details",
+    "Date",
+    "Done",
+    "Tags",
+    "Repeat",
+    "Subtasks",
+    "Blocked by",
+    "Priority",
+    "Project",
+];
+
+/// A change refused because the event was changed elsewhere since it was loaded;
+/// the Merge dialog lets the user pick which version of each field to keep
+struct MergeConflict {
+    /// The event as this instance had it before the change
+    before: EventEntry,
+    /// The change made in this instance
+    mine: EventEntry,
+    /// The event as it is stored now
+    theirs: EventEntry,
+    /// Whether each field of MERGE_FIELDS is taken from mine rather than theirs
+    keep_mine: [bool; 10],
+}
+
+impl MergeConflict {
+    /// Starts out keeping the fields changed in this instance and taking the others from the store
+    fn new(before: EventEntry, mine: EventEntry, theirs: EventEntry) -> Self {
+        let changed = |x: &EventEntry| Self::field_values(x, &Tz::UTC, &[]);
+        let (before_values, mine_values) = (changed(&before), changed(&mine));
+        let keep_mine = std::array::from_fn(|i| before_values[i] != mine_values[i]);
+        MergeConflict {
+            before,
+            mine,
+            theirs,
+            keep_mine,
+        }
+    }
+
+    /// Values of the MERGE_FIELDS of an event as shown in the Merge dialog
+    fn field_values(entry: &EventEntry, timezone: &Tz, projects: &[Project]) -> [String; 10] {
+        [
+            entry.title.clone(),
+            entry.details.clone().unwrap_or_default(),
+            match entry.date_time {
+                Some(date_time) => date_time
+                    .with_timezone(timezone)
+                    .format("%B %e, %Y %l:%M %p")
+                    .to_string(),
+                None => String::from("No date"),
+            },
+            String::from(if entry.is_done { "Done" } else { "Not done" }),
+            entry.tags.join(", "),
+            match &entry.recurrence {
+                Some(recurrence) => describe_recurrence(recurrence, timezone),
+                None => String::from("Never"),
+            },
+            describe_subtasks(entry),
+            match entry.blocked_by.len() {
+                0 => String::from("None"),
+                1 => String::from("1 event"),
+                count => format!("{} events", count),
+            },
+            String::from(priority_name(entry.priority)),
+            project_name(entry.project_id, projects),
+        ]
+    }
+
+    /// The stored event with the fields chosen from mine; it carries the stored version
+    fn merged(&self) -> EventEntry {
+        let mut merged = self.theirs.clone();
+        if self.keep_mine[0] {
+            merged.title = self.mine.title.clone();
+        }
+        if self.keep_mine[1] {
+            merged.details = self.mine.details.clone();
+        }
+        if self.keep_mine[2] {
+            merged.date_time = self.mine.date_time;
+        }
+        if self.keep_mine[3] {
+            merged.is_done = self.mine.is_done;
+        }
+        if self.keep_mine[4] {
+            merged.tags = self.mine.tags.clone();
+        }
+        if self.keep_mine[5] {
+            merged.recurrence = self.mine.recurrence.clone();
+        }
+        if self.keep_mine[6] {
+            merged.subtasks = self.mine.subtasks.clone();
+            merged.auto_complete = self.mine.auto_complete;
+        }
+        if self.keep_mine[7] {
+            merged.blocked_by = self.mine.blocked_by.clone();
+        }
+        if self.keep_mine[8] {
+            merged.priority = self.mine.priority;
+        }
+        if self.keep_mine[9] {
+            merged.project_id = self.mine.project_id;
+        }
+        merged
+    }
+}
+
 /// Struct to store UI components of Krabby Do
 pub struct KrabbyDoUi {
     /// To control the display of New / Edit Event dialog
@@ -58,6 +240,28 @@ pub struct KrabbyDoUi {
     /// To specify if an event is done or not in New / Edit Event dialog
     new_event_is_done: bool,
 
+    /// To specify if an event has a date in New / Edit Event dialog; events without one are listed under No Date
+    new_event_has_date: bool,
+
+    /// To store how often the event repeats in New / Edit Event dialog; None when it doesn't
+    new_event_repeat: Option<Frequency>,
+
+    /// To store the interval, days of the week and skipped dates of the rule in New / Edit Event
+    /// dialog; its frequency, count and end date are taken from the other Repeat fields
+    new_event_recurrence: Recurrence,
+
+    /// To store how a recurring event ends in New / Edit Event dialog
+    new_event_repeat_end: RepeatEnd,
+
+    /// To store the number of times a recurring event happens in New / Edit Event dialog
+    new_event_repeat_count: u32,
+
+    /// To store the last day a recurring event can happen on in New / Edit Event dialog
+    new_event_repeat_until: Option<NaiveDate>,
+
+    /// To store how important the event is in New / Edit Event dialog
+    new_event_priority: Priority,
+
     /// To store the value of date and time in a unified format
     date_time: DateTime<Utc>,
 
@@ -82,58 +286,242 @@ pub struct KrabbyDoUi {
     /// To search for a specific event based on various criteria
     search_query: String,
 
-    /// To add tags to the events
-    new_event_tags: String,
+    /// Ids of the events found by the last search, best match first;
+    /// None while no search has been run for the current search query
+    search_results: Option<Vec<ObjectId>>,
+
+    /// When the search query was last edited; the search runs once it has been left alone for a while
+    search_edited_at: Option<Instant>,
+
+    /// To add tags to the events; shown as chips in New / Edit Event dialog
+    new_event_tags: Vec<String>,
+
+    /// To store the tag being typed in New / Edit Event dialog, added as a chip on Enter or a comma
+    new_event_tag_input: String,
+
+    /// To suggest the tags already in use, most used first, while a tag is typed
+    known_tags: Vec<TagCount>,
 
     /// To disable notifications while testing Krabby Do UI because it is not required and it fails tests otherwise
     is_testing: bool,
+
+    /// Long-lived middleware client used to load and persist the event entries
+    client: KrabbyClient,
+
+    /// To tell the user about the last operation which failed; empty when there is nothing to report
+    status_message: String,
+
+    /// To control the display of the Trash dialog
+    is_show_trash_dialog: bool,
+
+    /// Event entries in the trash, loaded when the Trash dialog is opened
+    trash_entries: Vec<EventEntry>,
+
+    /// To choose between the details and the history of the selected event in the central panel
+    central_panel_tab: CentralPanelTab,
+
+    /// Revisions of the selected event shown in the History tab, oldest first
+    history_entries: Vec<Revision>,
+
+    /// Changes which can be undone and redone
+    undo_stack: UndoStack,
+
+    /// Time zone of the user; dates are picked and shown in it and stored in UTC
+    timezone: Tz,
+
+    /// Changes made to the events by any instance, read from the subscription in the background;
+    /// None until the subscription is started by the first frame
+    sync_receiver: Option<Receiver<Result<EventChange, MiddlewareError>>>,
+
+    /// Change refused because the event was changed elsewhere; the Merge dialog is shown while set
+    merge_conflict: Option<MergeConflict>,
+
+    /// Ids of the events picked with Ctrl / Shift-click for a bulk action, in the order they were picked
+    selected_ids: Vec<ObjectId>,
+
+    /// Event a Shift-click selects from; the last event clicked without Shift
+    selection_anchor: Option<ObjectId>,
+
+    /// To store the tag added or removed by the bulk action bar
+    bulk_tag: String,
+
+    /// To store the number of days the bulk action bar moves events by; negative moves them earlier
+    bulk_shift_days: i64,
+
+    /// To store the title of the subtask added from the checklist in the central panel
+    new_subtask_title: String,
+
+    /// To choose the order of the event lists: SortKey::DateTime, or SortKey::Urgency for the
+    /// most urgent event first
+    list_order: SortKey,
+
+    /// Projects in the order of the project navigator, archived ones included
+    projects: Vec<Project>,
+
+    /// Project the lists are filtered on: None shows every event, Some(None) the events in the
+    /// Inbox and Some(Some(id)) the events of that project
+    selected_project: Option<Option<ObjectId>>,
+
+    /// To show the archived projects in the navigator, and their events when no project is selected
+    show_archived_projects: bool,
+
+    /// To store the name of the project added from the project navigator
+    new_project_name: String,
+
+    /// To choose the project of the event in New / Edit Event dialog; None puts it in the Inbox
+    new_event_project: Option<ObjectId>,
+
+    /// Project whose color is being picked in the navigator; the color is saved once the pointer
+    /// is released instead of on every frame of a drag
+    unsaved_project: Option<ObjectId>,
 }
 
 impl Default for KrabbyDoUi {
-    /// Assign default values to struct properties
+    /// Assign default values to struct properties; events are stored in the backend named by the config
     fn default() -> Self {
-        let ui = Self {
+        match KrabbyClient::connect_default() {
+            Ok(client) => Self::with_client(client),
+            Err(error) => {
+                // Keep the UI usable on an in-memory store and tell the user why nothing is saved
+                let client = KrabbyClient::with_store(Arc::new(MemoryStore::new()))
+                    .expect("Failed to start the middleware runtime");
+                let mut ui = Self::with_client(client);
+                ui.handle_middleware_error(error);
+                ui
+            }
+        }
+    }
+}
+
+impl KrabbyDoUi {
+    /// New function to set up the UI
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Set up the UI on top of the given middleware client
+    pub fn with_client(client: KrabbyClient) -> Self {
+        let mut ui = Self {
             is_show_new_edit_dialog: false,
             is_show_central_panel_context_elements: false,
             new_event_title: "".to_owned(),
             new_event_details: "".to_owned(),
             new_event_is_done: false,
+            new_event_has_date: true,
+            new_event_repeat: None,
+            new_event_recurrence: Recurrence::new(Frequency::Weekly),
+            new_event_repeat_end: RepeatEnd::Never,
+            new_event_repeat_count: 10,
+            new_event_repeat_until: None,
+            new_event_priority: Priority::None,
             new_event_date: None,
             new_event_hour: 6,
             new_event_minute: 30,
             new_event_am_pm: AmPm::Pm,
             search_query: String::new(),
-            new_event_tags: String::new(),
+            search_results: None,
+            search_edited_at: None,
+            new_event_tags: Vec::new(),
+            new_event_tag_input: String::new(),
+            known_tags: Vec::new(),
             date_time: Utc.with_ymd_and_hms(2023, 5, 20, 22, 2, 0).unwrap(),
-            event_entries: tokio::runtime::Runtime::new()
-                .unwrap()
-                .block_on(async { EventEntry::get_all_tasks().await })
-                .unwrap(),
+            event_entries: Vec::new(),
             details_panel_title: String::from("Krabby Do"),
             details_panel_details: String::from(""),
             details_panel_time: String::from(""),
             active_entry: EventEntry {
                 unique_id: ObjectId::new(),
                 title: String::from(""),
-                details: String::from(""),
-                date_time: Utc.with_ymd_and_hms(2000, 1, 1, 1, 1, 1).unwrap(),
+                details: None,
+                date_time: Some(Utc.with_ymd_and_hms(2000, 1, 1, 1, 1, 1).unwrap()),
                 is_done: false,
-                tags: String::new(),
+                tags: Vec::new(),
+                deleted_at: None,
+                version: 0,
+                recurrence: None,
+                subtasks: Vec::new(),
+                auto_complete: false,
+                blocked_by: Vec::new(),
+                priority: Priority::None,
+                project_id: None,
             },
             new_edit_title: String::from("New Event"),
             is_testing: false,
+            timezone: client.timezone(),
+            sync_receiver: None,
+            merge_conflict: None,
+            selected_ids: Vec::new(),
+            selection_anchor: None,
+            bulk_tag: String::new(),
+            bulk_shift_days: 1,
+            new_subtask_title: String::new(),
+            list_order: SortKey::DateTime,
+            projects: Vec::new(),
+            selected_project: None,
+            show_archived_projects: false,
+            new_project_name: String::new(),
+            new_event_project: None,
+            unsaved_project: None,
+            client,
+            status_message: String::new(),
+            is_show_trash_dialog: false,
+            trash_entries: Vec::new(),
+            central_panel_tab: CentralPanelTab::Details,
+            history_entries: Vec::new(),
+            undo_stack: UndoStack::default(),
         };
+        ui.load_events();
         if ui.is_testing {
-            send_notifications();
+            send_notifications(&ui.client);
         }
         ui
     }
-}
 
-impl KrabbyDoUi {
-    /// New function to set up the UI
-    pub fn new() -> Self {
-        Default::default()
+    /// Load all event entries from the store
+    pub fn load_events(&mut self) {
+        match self.client.block_on(self.client.store().get_all_tasks()) {
+            Ok(event_entries) => self.event_entries = event_entries,
+            Err(error) => self.handle_middleware_error(error),
+        }
+        self.load_projects();
+        self.refresh_search();
+    }
+
+    /// Load the projects for the project navigator
+    fn load_projects(&mut self) {
+        match self.client.block_on(self.client.store().get_projects()) {
+            Ok(projects) => self.projects = projects,
+            Err(error) => self.handle_middleware_error(error),
+        }
+    }
+
+    /// Handle an error returned by the middleware; the status message tells the user what happened
+    /// and the event list is brought back in line with the store where that is possible
+    pub fn handle_middleware_error(&mut self, error: MiddlewareError) {
+        self.status_message = match &error {
+            MiddlewareError::Connection(_) => {
+                String::from("Could not reach the database, changes are not saved")
+            }
+            MiddlewareError::NotFound { id } => {
+                // The event is gone from the store, so it is dropped from the lists too
+                self.event_entries.retain(|x| x.unique_id != *id);
+                self.trash_entries.retain(|x| x.unique_id != *id);
+                String::from("The event no longer exists and was removed from the list")
+            }
+            MiddlewareError::Decode { field, id } => {
+                format!("Event {} has an invalid {} and can't be shown", id, field)
+            }
+            MiddlewareError::Conflict { .. } => {
+                // Show what is actually stored instead of the local copy
+                self.load_events();
+                String::from("The event was changed elsewhere, the list has been reloaded")
+            }
+            MiddlewareError::Validation(message) => format!("Invalid value: {}", message),
+            _ => error.to_string(),
+        };
+
+        #[cfg(feature = "print_debug_log")]
+        println!("{:?}", error);
     }
 
     /// Handle New Event menu option clicked;
@@ -145,25 +533,17 @@ impl KrabbyDoUi {
         self.new_event_title = String::from("");
         self.new_event_details = String::from("");
 
-        let now = Local::now();
-        self.new_event_date = NaiveDate::from_ymd_opt(now.year(), now.month(), now.day());
-
-        self.new_event_am_pm = AmPm::Am;
-        let mut hour = Local::now().time().hour();
-        if hour > 11 {
-            self.new_event_am_pm = AmPm::Pm;
-        }
-        if hour > 12 {
-            hour -= 12;
-        } else if hour == 0 {
-            hour = 12;
-            self.new_event_am_pm = AmPm::Am;
-        }
-        self.new_event_hour = hour;
-        let minute = Local::now().time().minute();
-        self.new_event_minute = minute;
+        self.set_dialog_date_time(Utc::now());
+        self.new_event_has_date = true;
+        self.set_dialog_recurrence(None);
 
         self.new_event_is_done = false;
+        self.new_event_priority = Priority::None;
+        // A new event goes in the project shown in the lists
+        self.new_event_project = self.selected_project.flatten();
+        self.new_event_tags.clear();
+        self.new_event_tag_input.clear();
+        self.load_known_tags();
     }
 
     /// Handle OK button clicked of the New/Edit Event dialog;
@@ -172,40 +552,102 @@ impl KrabbyDoUi {
     /// 3. Add the created struct to upcoming entries or marked-done entries as per user's choice
     pub fn handle_new_edit_ok_button_clicked(&mut self) {
         self.is_show_new_edit_dialog = false;
+        // A tag still being typed is kept too
+        self.handle_tag_entered();
+        // A new event starts out fresh with its own id; an edited one keeps the id, version,
+        // subtasks and blockers of the event being edited
+        let kept = if self.new_edit_title == "New Event" {
+            EventEntry::new(
+                ObjectId::new(),
+                String::new(),
+                String::new(),
+                Utc::now(),
+                false,
+                String::new(),
+            )
+        } else {
+            self.active_entry.clone()
+        };
         let new_entry = EventEntry {
-            unique_id: self.active_entry.unique_id,
             title: self.new_event_title.clone(),
-            details: self.new_event_details.clone(),
-            date_time: self.get_selected_date_time(),
+            details: non_empty(&self.new_event_details),
+            date_time: self
+                .new_event_has_date
+                .then(|| self.get_selected_date_time()),
             is_done: self.new_event_is_done,
             tags: self.new_event_tags.clone(),
+            priority: self.new_event_priority,
+            project_id: self.new_event_project,
+            deleted_at: None,
+            recurrence: self
+                .new_event_has_date
+                .then(|| self.get_selected_recurrence())
+                .flatten(),
+            ..kept
         };
 
         #[cfg(feature = "print_debug_log")]
         println!("{:?}", new_entry);
 
         if self.new_edit_title == "New Event" {
-            let _result = tokio::runtime::Runtime::new()
-                .unwrap()
-                .block_on(async { new_entry.add_event().await });
-            self.event_entries.push(new_entry);
+            match self
+                .client
+                .block_on(self.client.store().add_event(&new_entry))
+            {
+                // Keep the entry as stored so later edits and deletes target the right event
+                Ok(stored_entry) => {
+                    self.undo_stack.push(Command::Create(stored_entry.clone()));
+                    self.event_entries.push(stored_entry);
+                }
+                Err(error) => self.handle_middleware_error(error),
+            }
         } else if self.new_edit_title == "Edit Event" {
             #[cfg(feature = "print_debug_log")]
             println!("\nEntry edit requested!\n");
 
-            let _result = tokio::runtime::Runtime::new()
-                .unwrap()
-                .block_on(async { new_entry.update_task().await });
+            let before = self.active_entry.clone();
+            let stored_entry = match self
+                .client
+                .block_on(self.client.store().update_task(&new_entry))
+            {
+                Ok(stored_entry) => stored_entry,
+                Err(MiddlewareError::Conflict { .. }) => {
+                    self.open_merge_dialog(before, new_entry);
+                    return;
+                }
+                Err(error) => {
+                    self.handle_middleware_error(error);
+                    return;
+                }
+            };
+            if before.is_done != new_entry.is_done
+                && before
+                    == (EventEntry {
+                        is_done: before.is_done,
+                        ..new_entry.clone()
+                    })
+            {
+                self.undo_stack.push(Command::MarkDone {
+                    before,
+                    after: stored_entry.clone(),
+                });
+            } else {
+                self.undo_stack.push(Command::Edit {
+                    before,
+                    after: stored_entry.clone(),
+                });
+            }
 
             if let Some(index) = self
                 .event_entries
                 .iter()
                 .position(|x| x == &(self.active_entry))
             {
-                self.event_entries[index] = new_entry.clone();
-                self.handle_event_list_item_clicked(&new_entry);
+                self.event_entries[index] = stored_entry.clone();
+                self.handle_event_list_item_clicked(&stored_entry);
             }
         }
+        self.refresh_search();
     }
 
     /// Handle Cancel button clicked of the New / Edit Event dialog; close the dialog
@@ -219,6 +661,14 @@ impl KrabbyDoUi {
             .sort_by(|a, b| a.date_time.cmp(&b.date_time));
     }
 
+    /// Sort events by their urgency, most urgent first, scored with the coefficients of the config
+    pub fn sort_events_by_urgency(&mut self) {
+        let urgency = self.client.store().urgency();
+        let now = Utc::now();
+        self.event_entries
+            .sort_by(|a, b| urgency.score(b, now).total_cmp(&urgency.score(a, now)));
+    }
+
     /// Exports events to a JSON file.
     pub fn export_events_to_json(&self, filename: &str) -> std::io::Result<()> {
         // Serialize our events vector to a JSON string.
@@ -241,21 +691,294 @@ impl KrabbyDoUi {
 
         // Event data shown in the central panel
         self.details_panel_title = entry.title.clone();
-        self.details_panel_details = entry.details.clone();
+        self.details_panel_details = entry.details.clone().unwrap_or_default();
 
-        // Event date time is displayed in the central panel
+        // Event date time is displayed in the central panel, in the time zone of the user
         // https://docs.rs/chrono/0.4.24/chrono/format/strftime/index.html
-        self.details_panel_time = format!(
-            "{}",
-            entry
-                .date_time
-                .format("Date: %A, %B %e, %Y \tTime: %l:%M %p")
-        );
+        self.details_panel_time = match entry.date_time {
+            Some(date_time) => format!(
+                "{}",
+                date_time
+                    .with_timezone(&self.timezone)
+                    .format("Date: %A, %B %e, %Y \tTime: %l:%M %p %Z")
+            ),
+            None => String::from("No date"),
+        };
+
+        // The History tab follows the selected event
+        if self.central_panel_tab == CentralPanelTab::History {
+            self.load_history();
+        }
 
         #[cfg(feature = "print_debug_log")]
         println!("{:?}", entry);
     }
 
+    /// Handle event list item clicked with the modifiers held at the time;
+    /// 1. Ctrl-click adds the event to the bulk selection, or takes it out
+    /// 2. Shift-click selects every event of the list from the last clicked one up to this one
+    /// 3. A plain click clears the selection and shows the event in the central panel
+    pub fn handle_event_list_item_selected(&mut self, entry: &EventEntry, modifiers: Modifiers) {
+        if modifiers.shift {
+            let list = self.list_of(EventList::of(entry));
+            let position = |id: &ObjectId| list.iter().position(|x| x.unique_id == *id);
+            let end = position(&entry.unique_id).unwrap_or_default();
+            let start = self
+                .selection_anchor
+                .as_ref()
+                .and_then(position)
+                .unwrap_or(end);
+            let range = start.min(end)..=start.max(end);
+            if !modifiers.command {
+                self.selected_ids.clear();
+            }
+            for event in &list[range] {
+                if !self.selected_ids.contains(&event.unique_id) {
+                    self.selected_ids.push(event.unique_id);
+                }
+            }
+            return;
+        }
+
+        self.selection_anchor = Some(entry.unique_id);
+        if modifiers.command {
+            if let Some(index) = self.selected_ids.iter().position(|x| *x == entry.unique_id) {
+                self.selected_ids.remove(index);
+            } else {
+                self.selected_ids.push(entry.unique_id);
+            }
+        } else {
+            self.selected_ids.clear();
+            self.handle_event_list_item_clicked(entry);
+        }
+    }
+
+    /// Apply a patch to the selected events through the middleware; the change is undone as one
+    pub fn handle_bulk_patch(&mut self, patch: EventPatch) {
+        let selection = EventSelection::Ids(self.selected_ids.clone());
+        let changes = match self
+            .client
+            .block_on(self.client.store().update_many(&selection, &patch))
+        {
+            Ok(changes) => changes,
+            Err(error) => {
+                // The store leaves every event as it was, but the lists are reloaded in case
+                // the error comes from a change made elsewhere
+                self.handle_middleware_error(error);
+                self.load_events();
+                return;
+            }
+        };
+        if changes.is_empty() {
+            return;
+        }
+
+        let mut commands = Vec::with_capacity(changes.len());
+        for (before, after) in changes {
+            self.apply_event_change(EventChange::Updated(after.clone()));
+            let is_only_done_changed = before
+                == EventEntry {
+                    is_done: before.is_done,
+                    version: before.version,
+                    ..after.clone()
+                };
+            commands.push(if is_only_done_changed {
+                Command::MarkDone { before, after }
+            } else {
+                Command::Edit { before, after }
+            });
+        }
+        self.undo_stack.push(Command::Bulk(commands));
+        self.refresh_search();
+    }
+
+    /// Handle Mark Done / Mark Not Done button clicked in the bulk action bar
+    pub fn handle_bulk_done_button_clicked(&mut self, is_done: bool) {
+        self.handle_bulk_patch(EventPatch::new().done(is_done));
+    }
+
+    /// Handle Add Tag button clicked in the bulk action bar
+    pub fn handle_bulk_add_tag_button_clicked(&mut self) {
+        if !self.bulk_tag.trim().is_empty() {
+            self.handle_bulk_patch(EventPatch::new().add_tag(self.bulk_tag.trim()));
+        }
+    }
+
+    /// Handle Remove Tag button clicked in the bulk action bar
+    pub fn handle_bulk_remove_tag_button_clicked(&mut self) {
+        if !self.bulk_tag.trim().is_empty() {
+            self.handle_bulk_patch(EventPatch::new().remove_tag(self.bulk_tag.trim()));
+        }
+    }
+
+    /// Handle Shift Dates button clicked in the bulk action bar
+    pub fn handle_bulk_shift_button_clicked(&mut self) {
+        if self.bulk_shift_days != 0 {
+            self.handle_bulk_patch(EventPatch::new().shift_days(self.bulk_shift_days));
+        }
+    }
+
+    /// Handle Delete button clicked in the bulk action bar; the selected events go to the trash
+    /// together and the selection is cleared
+    pub fn handle_bulk_delete_button_clicked(&mut self) {
+        let selection = EventSelection::Ids(self.selected_ids.clone());
+        let deleted = match self
+            .client
+            .block_on(self.client.store().delete_many(&selection))
+        {
+            Ok(deleted) => deleted,
+            Err(error) => {
+                self.handle_middleware_error(error);
+                self.load_events();
+                return;
+            }
+        };
+        for entry in &deleted {
+            self.apply_event_change(EventChange::Deleted(entry.unique_id));
+        }
+        if !deleted.is_empty() {
+            self.undo_stack.push(Command::Bulk(
+                deleted.into_iter().map(Command::Delete).collect(),
+            ));
+        }
+        self.selected_ids.clear();
+        self.refresh_search();
+    }
+
+    /// Events of one of the lists, in the order shown
+    fn list_of(&self, list: EventList) -> Vec<EventEntry> {
+        self.get_events()
+            .into_iter()
+            .filter(|x| EventList::of(x) == list && self.is_in_selected_project(x))
+            .collect()
+    }
+
+    /// Whether the event is shown for the project selected in the navigator; an event whose
+    /// project no longer exists is in the Inbox, and events of archived projects are only shown
+    /// with the archived projects or when their project is selected
+    fn is_in_selected_project(&self, entry: &EventEntry) -> bool {
+        let project = entry
+            .project_id
+            .and_then(|id| self.projects.iter().find(|x| x.id == id));
+        match self.selected_project {
+            None => self.show_archived_projects || !project.is_some_and(|x| x.archived),
+            Some(selected) => project.map(|x| x.id) == selected,
+        }
+    }
+
+    /// Project selected in the navigator; None while all events or the Inbox are shown
+    fn selected_project_entry(&self) -> Option<&Project> {
+        let id = self.selected_project.flatten()?;
+        self.projects.iter().find(|x| x.id == id)
+    }
+
+    /// Handle All, Inbox or a project clicked in the project navigator; the lists show its events
+    /// and an event of another project is no longer shown in the central panel
+    pub fn handle_project_selected(&mut self, selected_project: Option<Option<ObjectId>>) {
+        self.selected_project = selected_project;
+        self.selected_ids.clear();
+        if !self.is_in_selected_project(&self.active_entry) {
+            self.is_show_central_panel_context_elements = false;
+            self.details_panel_title = String::from("Krabby Do");
+        }
+    }
+
+    /// Handle Add button clicked in the project navigator; the project is added at the end and
+    /// selected
+    pub fn handle_add_project_button_clicked(&mut self) {
+        let project = Project {
+            position: self.projects.len() as u32,
+            ..Project::new(&self.new_project_name)
+        };
+        match self
+            .client
+            .block_on(self.client.store().add_project(&project))
+        {
+            Ok(stored_project) => {
+                self.new_project_name.clear();
+                self.projects.push(stored_project.clone());
+                self.handle_project_selected(Some(Some(stored_project.id)));
+            }
+            Err(error) => self.handle_middleware_error(error),
+        }
+    }
+
+    /// Handle a color picked for a project in the project navigator; the navigator shows it right
+    /// away, and it is saved by handle_project_color_released
+    pub fn handle_project_color_picked(&mut self, project: &Project) {
+        if let Some(x) = self.projects.iter_mut().find(|x| x.id == project.id) {
+            x.color = project.color.clone();
+        }
+        self.unsaved_project = Some(project.id);
+    }
+
+    /// Handle the pointer released after picking a project color; the color is saved once
+    pub fn handle_project_color_released(&mut self) {
+        let Some(id) = self.unsaved_project.take() else {
+            return;
+        };
+        if let Some(project) = self.projects.iter().find(|x| x.id == id).cloned() {
+            self.handle_project_changed(&project);
+        }
+    }
+
+    /// Handle the name, color or archived flag of a project changed in the project navigator
+    pub fn handle_project_changed(&mut self, project: &Project) {
+        match self
+            .client
+            .block_on(self.client.store().update_project(project))
+        {
+            Ok(stored_project) => {
+                if let Some(x) = self.projects.iter_mut().find(|x| x.id == project.id) {
+                    *x = stored_project;
+                }
+            }
+            Err(error) => {
+                self.handle_middleware_error(error);
+                self.load_projects();
+            }
+        }
+    }
+
+    /// Handle Up / Down button clicked in the project navigator; the project moves to the given
+    /// position among the projects
+    pub fn handle_move_project_button_clicked(&mut self, project_id: ObjectId, position: usize) {
+        match self
+            .client
+            .block_on(self.client.store().move_project(&project_id, position))
+        {
+            Ok(projects) => self.projects = projects,
+            Err(error) => {
+                self.handle_middleware_error(error);
+                self.load_projects();
+            }
+        }
+    }
+
+    /// Handle Delete button clicked in the project navigator; the events of the project move to
+    /// the Inbox, which is shown instead
+    pub fn handle_delete_project_button_clicked(&mut self, project_id: ObjectId) {
+        if let Err(error) = self
+            .client
+            .block_on(self.client.store().delete_project(&project_id))
+        {
+            self.handle_middleware_error(error);
+        }
+        // The events moved to the Inbox are reloaded as stored, with their new versions
+        self.load_events();
+        if self.active_entry.project_id == Some(project_id) {
+            self.active_entry.project_id = None;
+            if let Some(entry) = self
+                .event_entries
+                .iter()
+                .find(|x| x.unique_id == self.active_entry.unique_id)
+            {
+                self.active_entry = entry.clone();
+            }
+        }
+        self.handle_project_selected(Some(None));
+    }
+
     /// Handle Edit Event button clicked
     pub fn handle_edit_event_button_clicked(&mut self) {
         #[cfg(feature = "print_debug_log")]
@@ -266,27 +989,63 @@ impl KrabbyDoUi {
 
         // Fetching string values to be displayed in Edit Dialog
         self.new_event_title = self.active_entry.title.clone();
-        self.new_event_details = self.active_entry.details.clone();
-
-        // Fetching date time of currently active event for editing
-        let date_time = self.active_entry.date_time;
+        self.new_event_details = self.active_entry.details.clone().unwrap_or_default();
+
+        // Fetching date and time of currently active event for editing;
+        // an event without a date starts from now if the user gives it one
+        self.new_event_has_date = self.active_entry.date_time.is_some();
+        self.set_dialog_date_time(self.active_entry.date_time.unwrap_or_else(Utc::now));
+        let recurrence = self.active_entry.recurrence.clone();
+        self.set_dialog_recurrence(recurrence.as_ref());
+        self.new_event_is_done = self.active_entry.is_done;
+        self.new_event_tags = self.active_entry.tags.clone();
+        self.new_event_tag_input.clear();
+        self.load_known_tags();
+        self.new_event_priority = self.active_entry.priority;
+        self.new_event_project = self.active_entry.project_id;
+    }
 
-        // Fetching date values to be displayed in Edit Dialog
-        self.new_event_date =
-            NaiveDate::from_ymd_opt(date_time.year(), date_time.month(), date_time.day());
+    /// Load the tags in use along with how many events carry them, for the suggestions
+    fn load_known_tags(&mut self) {
+        match self.client.block_on(self.client.store().get_tags()) {
+            Ok(known_tags) => self.known_tags = known_tags,
+            Err(error) => self.handle_middleware_error(error),
+        }
+    }
 
-        // Fetching time values to be displayed in Edit Dialog
-        if date_time.hour() > 11 {
-            self.new_event_am_pm = AmPm::Pm;
-        } else {
-            self.new_event_am_pm = AmPm::Am;
+    /// Handle Enter or a comma typed in the tag field of New / Edit Event dialog; the typed text
+    /// becomes chips, normalized the way the store keeps tags
+    pub fn handle_tag_entered(&mut self) {
+        for tag in parse_tags(&self.new_event_tag_input) {
+            if !self.new_event_tags.contains(&tag) {
+                self.new_event_tags.push(tag);
+            }
         }
-        if date_time.hour() > 12 {
-            self.new_event_hour = date_time.hour() - 12;
+        self.new_event_tag_input.clear();
+    }
+
+    /// Handle a suggested tag clicked in New / Edit Event dialog
+    pub fn handle_tag_suggestion_clicked(&mut self, tag: &str) {
+        self.new_event_tag_input = tag.to_string();
+        self.handle_tag_entered();
+    }
+
+    /// Handle the remove button clicked on a tag chip in New / Edit Event dialog
+    pub fn handle_tag_chip_remove_clicked(&mut self, tag: &str) {
+        self.new_event_tags.retain(|x| x != tag);
+    }
+
+    /// Tags in use which start with the typed text and aren't on the event yet, most used first
+    fn tag_suggestions(&self) -> Vec<&TagCount> {
+        let typed = self.new_event_tag_input.trim().to_lowercase();
+        if typed.is_empty() {
+            return Vec::new();
         }
-        self.new_event_minute = date_time.minute();
-        self.new_event_is_done = self.active_entry.is_done;
-        self.new_event_tags = self.active_entry.tags.clone();
+        self.known_tags
+            .iter()
+            .filter(|x| x.tag.starts_with(&typed) && !self.new_event_tags.contains(&x.tag))
+            .take(5)
+            .collect()
     }
 
     /// Handle Edit button clicked on event list entry
@@ -295,57 +1054,580 @@ impl KrabbyDoUi {
         self.handle_edit_event_button_clicked();
     }
 
-    /// Handle Delete button clicked on event list entry
+    /// Handle Delete button clicked on event list entry; the event is moved to the trash
     pub fn handle_event_list_item_delete_button_clicked(&mut self, entry: &EventEntry) {
+        if let Err(error) = self
+            .client
+            .block_on(self.client.store().delete_event(entry))
+        {
+            self.handle_middleware_error(error);
+            return;
+        }
+        self.undo_stack.push(Command::Delete(entry.clone()));
         let local_entry = &entry.clone();
         if let Some(index) = self.event_entries.iter().position(|x| x == local_entry) {
             self.event_entries.remove(index);
         }
+        // The details of a deleted event are no longer shown in the central panel
+        if self.active_entry.unique_id == entry.unique_id {
+            self.is_show_central_panel_context_elements = false;
+            self.details_panel_title = String::from("Krabby Do");
+        }
     }
 
-    /// Get the date selected by the date picker widget in NaiveDate format wrapped in Option
-    pub fn get_selected_date(&mut self) -> Option<NaiveDate> {
-        #[cfg(feature = "print_debug_log")]
-        println!("Date selected: {}", self.new_event_date.unwrap_or_default());
-
-        self.new_event_date
+    /// Handle Done / Not Done button clicked on event list entry; the event moves between the
+    /// Upcoming Events and Marked Done lists, except a recurring event marked done, which stays
+    /// and moves on to its next occurrence
+    pub fn handle_event_list_item_done_button_clicked(&mut self, entry: &EventEntry) {
+        let after = if entry.is_done {
+            EventEntry {
+                is_done: false,
+                ..entry.clone()
+            }
+        } else {
+            complete_occurrence(entry, &self.timezone)
+        };
+        if let Some(after) = self.store_change(entry, after) {
+            self.undo_stack.push(Command::MarkDone {
+                before: entry.clone(),
+                after,
+            });
+        }
     }
 
-    /// Get date and time selected by the user in the dialog in  `DateTime<Utc>` format
-    pub fn get_selected_date_time(&mut self) -> DateTime<Utc> {
-        // Considering AM / PM
-        let mut hour = self.new_event_hour;
-        if hour < 12 && self.new_event_am_pm == AmPm::Pm {
-            hour += 12;
-        } else if hour == 12 && self.new_event_am_pm == AmPm::Am {
-            hour -= 12;
+    /// Handle Skip button clicked next to an occurrence of the selected recurring event; skipping
+    /// the current occurrence moves the event on to the next one, like marking it done
+    pub fn handle_skip_occurrence_button_clicked(&mut self, date_time: DateTime<Utc>) {
+        let before = self.active_entry.clone();
+        let Some(recurrence) = before.recurrence.clone() else {
+            return;
+        };
+        let after = if before.date_time == Some(date_time) {
+            complete_occurrence(&before, &self.timezone)
+        } else {
+            EventEntry {
+                recurrence: Some(recurrence.except(date_time)),
+                ..before.clone()
+            }
+        };
+        if let Some(after) = self.store_change(&before, after) {
+            self.undo_stack.push(Command::Edit { before, after });
         }
-        self.date_time = chrono::offset::Utc
-            .with_ymd_and_hms(
-                self.new_event_date.unwrap().year(),
-                self.new_event_date.unwrap().month(),
-                self.new_event_date.unwrap().day(),
-                hour,
-                self.new_event_minute,
-                0,
-            )
-            .unwrap();
-        self.date_time
     }
 
-    /// Function to create a UI list item for event list
-    pub fn create_event_list_item(&mut self, ui: &mut Ui, entry: EventEntry) {
-        ui.style_mut().spacing.item_spacing.y = style_constants::EVENT_LIST_BUTTON_SPACING;
-        ui.with_layout(Layout::top_down(Align::TOP), |ui| {
-            ui.set_min_width(style_constants::EVENT_LIST_ITEM_MIN_WIDTH);
-            ui.set_max_width(style_constants::EVENT_LIST_ITEM_MAX_WIDTH);
-            ui.with_layout(Layout::left_to_right(Align::TOP), |ui| {
+    /// Handle Add button clicked, or Enter pressed, under the checklist of the selected event
+    pub fn handle_add_subtask_button_clicked(&mut self) {
+        let title = std::mem::take(&mut self.new_subtask_title);
+        self.change_active_entry(|entry| entry.add_subtask(&title).map(|_| ()));
+    }
+
+    /// Handle the checkbox of a subtask clicked; with auto-complete on, checking the last open
+    /// subtask marks the event done
+    pub fn handle_subtask_checkbox_clicked(&mut self, id: ObjectId) {
+        let timezone = self.timezone;
+        self.change_active_entry(|entry| entry.toggle_subtask(&id, &timezone));
+    }
+
+    /// Handle the Up / Down buttons of a subtask clicked; moves it to the given position
+    pub fn handle_subtask_move_button_clicked(&mut self, id: ObjectId, position: usize) {
+        self.change_active_entry(|entry| entry.move_subtask(&id, position));
+    }
+
+    /// Handle the Remove button of a subtask clicked
+    pub fn handle_subtask_remove_button_clicked(&mut self, id: ObjectId) {
+        self.change_active_entry(|entry| entry.remove_subtask(&id));
+    }
+
+    /// Handle the auto-complete checkbox under the checklist clicked
+    pub fn handle_auto_complete_checkbox_clicked(&mut self, auto_complete: bool) {
+        self.change_active_entry(|entry| {
+            entry.auto_complete = auto_complete;
+            Ok(())
+        });
+    }
+
+    /// Handle a blocker picked for the selected event; blockers making events wait for each other
+    /// are refused
+    pub fn handle_add_blocker(&mut self, blocker_id: ObjectId) {
+        let before = self.active_entry.clone();
+        let mut after = before.clone();
+        after.blocked_by.push(blocker_id);
+        let result = self
+            .client
+            .block_on(self.client.store().add_blocker(&before, &blocker_id));
+        if let Some(after) = self.show_stored_change(&before, after, result) {
+            self.undo_stack.push(Command::Edit { before, after });
+        }
+    }
+
+    /// Handle the Remove button of a blocker of the selected event clicked
+    pub fn handle_remove_blocker_button_clicked(&mut self, blocker_id: ObjectId) {
+        self.change_active_entry(|entry| {
+            entry.blocked_by.retain(|x| *x != blocker_id);
+            Ok(())
+        });
+    }
+
+    /// Apply a change made in the central panel to the selected event and store it, so it can be undone
+    fn change_active_entry(
+        &mut self,
+        change: impl FnOnce(&mut EventEntry) -> Result<(), MiddlewareError>,
+    ) {
+        let before = self.active_entry.clone();
+        let mut after = before.clone();
+        if let Err(error) = change(&mut after) {
+            self.handle_middleware_error(error);
+            return;
+        }
+        if let Some(after) = self.store_change(&before, after) {
+            self.undo_stack.push(Command::Edit { before, after });
+        }
+    }
+
+    /// Store the change of an event made from the event lists or the central panel and show it;
+    /// returns the event as stored, or None when the change failed or needs merging first
+    fn store_change(&mut self, before: &EventEntry, after: EventEntry) -> Option<EventEntry> {
+        let result = self
+            .client
+            .block_on(self.client.store().update_task(&after));
+        self.show_stored_change(before, after, result)
+    }
+
+    /// Show the result of storing a change of an event; a change refused because the event was
+    /// changed elsewhere opens the Merge dialog. Returns the event as stored, or None
+    fn show_stored_change(
+        &mut self,
+        before: &EventEntry,
+        after: EventEntry,
+        result: Result<EventEntry, MiddlewareError>,
+    ) -> Option<EventEntry> {
+        let after = match result {
+            Ok(stored_entry) => stored_entry,
+            Err(MiddlewareError::Conflict { .. }) => {
+                self.open_merge_dialog(before.clone(), after);
+                return None;
+            }
+            Err(error) => {
+                self.handle_middleware_error(error);
+                return None;
+            }
+        };
+        if let Some(index) = self.event_entries.iter().position(|x| x == before) {
+            self.event_entries[index] = after.clone();
+        }
+        if self.active_entry.unique_id == before.unique_id {
+            self.handle_event_list_item_clicked(&after);
+        }
+        Some(after)
+    }
+
+    /// Handle Undo clicked or Ctrl+Z pressed; reverts the last change in the store
+    pub fn handle_undo(&mut self) {
+        let result = self.undo_stack.undo(&self.client);
+        self.handle_undo_redo_result(result);
+    }
+
+    /// Handle Redo clicked or Ctrl+Shift+Z pressed; applies the last undone change again
+    pub fn handle_redo(&mut self) {
+        let result = self.undo_stack.redo(&self.client);
+        self.handle_undo_redo_result(result);
+    }
+
+    /// Reload the events after an undo or redo, since it may have touched any of them
+    fn handle_undo_redo_result(&mut self, result: Result<bool, MiddlewareError>) {
+        match result {
+            Ok(false) => return,
+            Ok(true) => {}
+            Err(error) => self.handle_middleware_error(error),
+        }
+        self.load_events();
+
+        // Show the selected event as it is now, or hide it when it no longer exists
+        if let Some(entry) = self
+            .event_entries
+            .iter()
+            .find(|x| x.unique_id == self.active_entry.unique_id)
+            .cloned()
+        {
+            self.handle_event_list_item_clicked(&entry);
+        } else {
+            self.is_show_central_panel_context_elements = false;
+            self.details_panel_title = String::from("Krabby Do");
+        }
+    }
+
+    /// Subscribe to the changes of the events and read them in the background; every change is
+    /// handed over to the UI thread and a repaint is requested so it shows up right away
+    pub fn start_sync(&mut self, ctx: &egui::Context, poll_interval: Duration) {
+        let (sender, receiver) = channel();
+        self.sync_receiver = Some(receiver);
+        let mut changes = match self.client.block_on(self.client.subscribe(poll_interval)) {
+            Ok(changes) => changes,
+            Err(error) => {
+                self.handle_middleware_error(error);
+                return;
+            }
+        };
+        // Changes made between the first load and the subscription are only seen by reloading
+        self.load_events();
+
+        let ctx = ctx.clone();
+        self.client.spawn(async move {
+            while let Some(change) = changes.next().await {
+                // The UI is gone once nobody receives the changes
+                if sender.send(change).is_err() {
+                    break;
+                }
+                ctx.request_repaint();
+            }
+        });
+    }
+
+    /// Apply the changes received from the subscription since the last frame
+    pub fn handle_sync_changes(&mut self) {
+        let Some(receiver) = &self.sync_receiver else {
+            return;
+        };
+        let changes: Vec<_> = receiver.try_iter().collect();
+        if changes.is_empty() {
+            return;
+        }
+        for change in changes {
+            match change {
+                Ok(change) => self.apply_event_change(change),
+                Err(error) => self.handle_middleware_error(error),
+            }
+        }
+        self.refresh_search();
+    }
+
+    /// Bring the event lists and the central panel in line with a change made by any instance;
+    /// changes made by this instance come back too and leave the lists as they are
+    pub fn apply_event_change(&mut self, change: EventChange) {
+        match change {
+            EventChange::Inserted(entry) | EventChange::Updated(entry) => {
+                match self
+                    .event_entries
+                    .iter()
+                    .position(|x| x.unique_id == entry.unique_id)
+                {
+                    Some(index) => self.event_entries[index] = entry.clone(),
+                    None => self.event_entries.push(entry.clone()),
+                }
+                if self.is_show_central_panel_context_elements
+                    && self.active_entry.unique_id == entry.unique_id
+                {
+                    self.handle_event_list_item_clicked(&entry);
+                }
+            }
+            EventChange::Deleted(id) => {
+                self.event_entries.retain(|x| x.unique_id != id);
+                self.selected_ids.retain(|x| *x != id);
+                if self.active_entry.unique_id == id {
+                    self.is_show_central_panel_context_elements = false;
+                    self.details_panel_title = String::from("Krabby Do");
+                }
+            }
+        }
+    }
+
+    /// Show the Merge dialog for a change refused because the event was changed elsewhere;
+    /// the lists show the stored event meanwhile
+    fn open_merge_dialog(&mut self, before: EventEntry, mine: EventEntry) {
+        match self
+            .client
+            .block_on(self.client.store().get_event(&mine.unique_id))
+        {
+            Ok(theirs) if theirs.deleted_at.is_none() => {
+                self.apply_event_change(EventChange::Updated(theirs.clone()));
+                self.merge_conflict = Some(MergeConflict::new(before, mine, theirs));
+            }
+            Ok(theirs) => self.handle_middleware_error(MiddlewareError::NotFound {
+                id: theirs.unique_id,
+            }),
+            Err(error) => self.handle_middleware_error(error),
+        }
+    }
+
+    /// Handle Save Merged button clicked in the Merge dialog; the chosen fields are saved over
+    /// the stored event, and the dialog comes back if it was changed elsewhere yet again
+    pub fn handle_merge_save_button_clicked(&mut self) {
+        let Some(conflict) = self.merge_conflict.take() else {
+            return;
+        };
+        let merged = conflict.merged();
+        match self
+            .client
+            .block_on(self.client.store().update_task(&merged))
+        {
+            Ok(stored_entry) => {
+                self.undo_stack.push(Command::Edit {
+                    before: conflict.theirs,
+                    after: stored_entry.clone(),
+                });
+                self.apply_event_change(EventChange::Updated(stored_entry));
+                self.refresh_search();
+            }
+            Err(MiddlewareError::Conflict { .. }) => {
+                self.open_merge_dialog(conflict.before, conflict.mine)
+            }
+            Err(error) => self.handle_middleware_error(error),
+        }
+    }
+
+    /// Handle Keep Theirs button clicked in the Merge dialog; the change made here is dropped
+    pub fn handle_merge_keep_theirs_button_clicked(&mut self) {
+        self.merge_conflict = None;
+    }
+
+    /// Load the revision history of the selected event for the History tab
+    pub fn load_history(&mut self) {
+        match self.client.block_on(
+            self.client
+                .store()
+                .get_history(&self.active_entry.unique_id),
+        ) {
+            Ok(history_entries) => self.history_entries = history_entries,
+            Err(error) => self.handle_middleware_error(error),
+        }
+    }
+
+    /// Handle Revert button clicked in the History tab; the event gets back its contents
+    /// right after the chosen revision
+    pub fn handle_history_revert_button_clicked(&mut self, revision: &Revision) {
+        match self.client.block_on(
+            self.client
+                .store()
+                .revert_event(&revision.event_id, &revision.revision_id),
+        ) {
+            Ok(reverted_entry) => {
+                if let Some(index) = self
+                    .event_entries
+                    .iter()
+                    .position(|x| x.unique_id == reverted_entry.unique_id)
+                {
+                    self.event_entries[index] = reverted_entry.clone();
+                }
+                self.handle_event_list_item_clicked(&reverted_entry);
+                self.refresh_search();
+            }
+            Err(error) => self.handle_middleware_error(error),
+        }
+    }
+
+    /// Handle Trash menu option clicked; load the trash and show the Trash dialog
+    pub fn handle_menu_trash_clicked(&mut self) {
+        match self.client.block_on(self.client.store().get_trash()) {
+            Ok(trash_entries) => {
+                self.trash_entries = trash_entries;
+                self.is_show_trash_dialog = true;
+            }
+            Err(error) => self.handle_middleware_error(error),
+        }
+    }
+
+    /// Handle Restore button clicked on trash entry; the event goes back to the event lists
+    pub fn handle_trash_restore_button_clicked(&mut self, entry: &EventEntry) {
+        match self
+            .client
+            .block_on(self.client.store().restore_event(entry))
+        {
+            Ok(restored_entry) => {
+                self.trash_entries
+                    .retain(|x| x.unique_id != entry.unique_id);
+                self.event_entries.push(restored_entry);
+                self.refresh_search();
+            }
+            Err(error) => self.handle_middleware_error(error),
+        }
+    }
+
+    /// Handle Delete Forever button clicked on trash entry
+    pub fn handle_trash_delete_forever_button_clicked(&mut self, entry: &EventEntry) {
+        match self.client.block_on(self.client.store().purge_event(entry)) {
+            Ok(()) => self
+                .trash_entries
+                .retain(|x| x.unique_id != entry.unique_id),
+            Err(error) => self.handle_middleware_error(error),
+        }
+    }
+
+    /// Handle Empty Trash button clicked; every event in the trash is removed for good
+    pub fn handle_empty_trash_button_clicked(&mut self) {
+        match self
+            .client
+            .block_on(self.client.store().purge_trash(chrono::Duration::zero()))
+        {
+            Ok(_) => self.trash_entries.clear(),
+            Err(error) => self.handle_middleware_error(error),
+        }
+    }
+
+    /// Load a stored date and time into the date, hour, minute and AM / PM fields of the dialog,
+    /// in the time zone of the user
+    fn set_dialog_date_time(&mut self, date_time: DateTime<Utc>) {
+        let local = date_time.with_timezone(&self.timezone);
+        self.new_event_date = Some(local.date_naive());
+
+        let (is_pm, hour) = local.hour12();
+        self.new_event_am_pm = if is_pm { AmPm::Pm } else { AmPm::Am };
+        self.new_event_hour = hour;
+        self.new_event_minute = local.minute();
+    }
+
+    /// Load the rule of a recurring event into the Repeat fields of the dialog; None clears them
+    fn set_dialog_recurrence(&mut self, recurrence: Option<&Recurrence>) {
+        self.new_event_repeat = recurrence.map(|x| x.frequency);
+        self.new_event_recurrence = recurrence
+            .cloned()
+            .unwrap_or_else(|| Recurrence::new(Frequency::Weekly));
+        self.new_event_repeat_end = match recurrence {
+            Some(Recurrence { count: Some(_), .. }) => RepeatEnd::AfterCount,
+            Some(Recurrence { until: Some(_), .. }) => RepeatEnd::OnDate,
+            _ => RepeatEnd::Never,
+        };
+        self.new_event_repeat_count = recurrence.and_then(|x| x.count).unwrap_or(10);
+        self.new_event_repeat_until = recurrence
+            .and_then(|x| x.until)
+            .map(|until| until.with_timezone(&self.timezone).date_naive());
+    }
+
+    /// Get the rule picked in the Repeat fields of the dialog; None when the event doesn't repeat
+    pub fn get_selected_recurrence(&self) -> Option<Recurrence> {
+        let frequency = self.new_event_repeat?;
+        let mut recurrence = self.new_event_recurrence.clone();
+        recurrence.frequency = frequency;
+        recurrence.interval = recurrence.interval.max(1);
+        // Plain days of the week are offered for daily and weekly events; monthly and yearly ones
+        // only keep the numbered days they were loaded with, like the last Friday
+        let is_monthly_or_yearly = matches!(frequency, Frequency::Monthly | Frequency::Yearly);
+        recurrence
+            .by_day
+            .retain(|x| x.nth.is_some() == is_monthly_or_yearly);
+        recurrence.count = None;
+        recurrence.until = None;
+        match self.new_event_repeat_end {
+            RepeatEnd::Never => {}
+            RepeatEnd::AfterCount => recurrence.count = Some(self.new_event_repeat_count.max(1)),
+            // The event can still happen on the last day, whatever its time
+            RepeatEnd::OnDate => {
+                recurrence.until = self
+                    .new_event_repeat_until
+                    .map(|date| day_bounds(date, &self.timezone).1 - chrono::Duration::seconds(1))
+            }
+        }
+        Some(recurrence)
+    }
+
+    /// Handle the search query edited; the search waits until the user stops typing
+    pub fn handle_search_query_changed(&mut self) {
+        self.search_results = None;
+        self.search_edited_at = Some(Instant::now());
+    }
+
+    /// Run the search once the search query has been left alone for SEARCH_DEBOUNCE;
+    /// until then a repaint is asked for when it will be due
+    pub fn poll_search(&mut self, ctx: &egui::Context) {
+        let Some(edited_at) = self.search_edited_at else {
+            return;
+        };
+        let waited = edited_at.elapsed();
+        if waited >= SEARCH_DEBOUNCE {
+            self.search_edited_at = None;
+            self.run_search();
+        } else {
+            ctx.request_repaint_after(SEARCH_DEBOUNCE - waited);
+        }
+    }
+
+    /// Search the events in the store for the search query
+    pub fn run_search(&mut self) {
+        if self.search_query.trim().is_empty() {
+            self.search_results = None;
+            return;
+        }
+        match self
+            .client
+            .block_on(self.client.store().search_events(&self.search_query))
+        {
+            Ok(hits) => {
+                self.search_results = Some(hits.into_iter().map(|x| x.entry.unique_id).collect())
+            }
+            Err(error) => self.handle_middleware_error(error),
+        }
+    }
+
+    /// Run the last search again after events changed, so the results take the change into account
+    fn refresh_search(&mut self) {
+        if self.search_results.is_some() {
+            self.run_search();
+        }
+    }
+
+    /// Get the date selected by the date picker widget in NaiveDate format wrapped in Option
+    pub fn get_selected_date(&mut self) -> Option<NaiveDate> {
+        #[cfg(feature = "print_debug_log")]
+        println!("Date selected: {}", self.new_event_date.unwrap_or_default());
+
+        self.new_event_date
+    }
+
+    /// Get date and time selected by the user in the dialog in  `DateTime<Utc>` format;
+    /// the user picks them in their own time zone
+    pub fn get_selected_date_time(&mut self) -> DateTime<Utc> {
+        // Considering AM / PM
+        let mut hour = self.new_event_hour;
+        if hour < 12 && self.new_event_am_pm == AmPm::Pm {
+            hour += 12;
+        } else if hour == 12 && self.new_event_am_pm == AmPm::Am {
+            hour -= 12;
+        }
+        let local = self
+            .new_event_date
+            .unwrap()
+            .and_hms_opt(hour, self.new_event_minute, 0)
+            .unwrap();
+        self.date_time = local_to_utc(local, &self.timezone);
+        self.date_time
+    }
+
+    /// Function to create a UI list item for event list
+    pub fn create_event_list_item(&mut self, ui: &mut Ui, entry: EventEntry) {
+        // Events waiting for an open blocker are greyed out
+        let blockers: Vec<String> = if entry.is_done {
+            Vec::new()
+        } else {
+            entry
+                .open_blockers(&self.event_entries)
+                .iter()
+                .map(|x| x.title.clone())
+                .collect()
+        };
+        ui.style_mut().spacing.item_spacing.y = style_constants::EVENT_LIST_BUTTON_SPACING;
+        ui.with_layout(Layout::top_down(Align::TOP), |ui| {
+            ui.set_min_width(style_constants::EVENT_LIST_ITEM_MIN_WIDTH);
+            ui.set_max_width(style_constants::EVENT_LIST_ITEM_MAX_WIDTH);
+            ui.with_layout(Layout::left_to_right(Align::TOP), |ui| {
                 ui.set_max_height(style_constants::EVENT_LIST_BUTTON_MAX_HEIGHT);
                 ui.with_layout(
                     Layout::centered_and_justified(Direction::LeftToRight),
                     |ui| {
-                        if ui.button(entry.title.clone()).clicked() {
-                            KrabbyDoUi::handle_event_list_item_clicked(self, &entry);
+                        let mut title = RichText::new(entry.title.clone());
+                        if !blockers.is_empty() {
+                            title = title.color(ui.visuals().weak_text_color());
+                        }
+                        let mut title_button = Button::new(title);
+                        if self.selected_ids.contains(&entry.unique_id) {
+                            title_button = title_button.fill(ui.visuals().selection.bg_fill);
+                        }
+                        let mut response = ui.add(title_button);
+                        if !blockers.is_empty() {
+                            response = response
+                                .on_hover_text(format!("Blocked by {}", blockers.join(", ")));
+                        }
+                        if response.clicked() {
+                            let modifiers = ui.input(|i| i.modifiers);
+                            KrabbyDoUi::handle_event_list_item_selected(self, &entry, modifiers);
                         }
                     },
                 );
@@ -358,6 +1640,16 @@ impl KrabbyDoUi {
                         }
                     },
                 );
+                ui.with_layout(
+                    Layout::centered_and_justified(Direction::LeftToRight),
+                    |ui| {
+                        ui.set_min_width(style_constants::EVENT_LIST_INTERNAL_BUTTON_MIN_WIDTH);
+                        let label = if entry.is_done { "Not Done" } else { "Done" };
+                        if ui.button(label).clicked() {
+                            KrabbyDoUi::handle_event_list_item_done_button_clicked(self, &entry);
+                        }
+                    },
+                );
                 ui.with_layout(
                     Layout::centered_and_justified(Direction::LeftToRight),
                     |ui| {
@@ -372,17 +1664,11 @@ impl KrabbyDoUi {
     }
 
     /// Generic function to populate a list of events using the event list supplied as an argument
-    pub fn list_events(&mut self, ui: &mut Ui, widget_id: u32, is_show_events_marked_done: bool) {
+    fn list_events(&mut self, ui: &mut Ui, widget_id: u32, list: EventList) {
         ui.push_id(widget_id, |ui| {
             ScrollArea::vertical().show(ui, |ui| {
-                for entry in self.get_events() {
-                    if is_show_events_marked_done {
-                        if entry.is_done {
-                            self.create_event_list_item(ui, entry);
-                        }
-                    } else if !entry.is_done {
-                        self.create_event_list_item(ui, entry);
-                    }
+                for entry in self.list_of(list) {
+                    self.create_event_list_item(ui, entry);
                 }
             });
         });
@@ -396,6 +1682,9 @@ impl KrabbyDoUi {
                     if ui.button("New Event").clicked() {
                         KrabbyDoUi::handle_menu_new_clicked(self);
                     }
+                    if ui.button("Trash").clicked() {
+                        KrabbyDoUi::handle_menu_trash_clicked(self);
+                    }
                     if ui.button("Export").clicked() {
                         let filename = "exported_events.json"; // Filename can be dynamically determined.
                         match self.export_events_to_json(filename) {
@@ -407,11 +1696,46 @@ impl KrabbyDoUi {
                         frame.close();
                     }
                 });
+                ui.menu_button("Edit", |ui| {
+                    let undo_label = match self.undo_stack.next_undo() {
+                        Some(command) => format!("Undo {}", command.describe()),
+                        None => String::from("Undo"),
+                    };
+                    let redo_label = match self.undo_stack.next_redo() {
+                        Some(command) => format!("Redo {}", command.describe()),
+                        None => String::from("Redo"),
+                    };
+                    if ui
+                        .add_enabled(
+                            self.undo_stack.next_undo().is_some(),
+                            Button::new(undo_label),
+                        )
+                        .clicked()
+                    {
+                        KrabbyDoUi::handle_undo(self);
+                        ui.close_menu();
+                    }
+                    if ui
+                        .add_enabled(
+                            self.undo_stack.next_redo().is_some(),
+                            Button::new(redo_label),
+                        )
+                        .clicked()
+                    {
+                        KrabbyDoUi::handle_redo(self);
+                        ui.close_menu();
+                    }
+                });
 
-                ui.add(
-                    widgets::TextEdit::singleline(&mut self.search_query)
-                        .hint_text("Search events"),
-                );
+                if ui
+                    .add(
+                        widgets::TextEdit::singleline(&mut self.search_query)
+                            .hint_text("Search events"),
+                    )
+                    .changed()
+                {
+                    KrabbyDoUi::handle_search_query_changed(self);
+                }
             });
         });
     }
@@ -435,14 +1759,166 @@ impl KrabbyDoUi {
                 });
             });
             ui.separator();
+            ui.horizontal(|ui| {
+                ui.label("Sort by:");
+                ui.selectable_value(&mut self.list_order, SortKey::DateTime, "Date");
+                ui.selectable_value(&mut self.list_order, SortKey::Urgency, "Urgency");
+            });
+            self.setup_project_navigator(ui);
+            ui.separator();
             ui.heading("Upcoming Events");
             ui.with_layout(Layout::left_to_right(Align::TOP), |_ui| {});
-            self.list_events(ui, 123456, false);
+            self.list_events(ui, 123456, EventList::Upcoming);
+            ui.separator();
+            ui.heading("No Date");
+            ui.with_layout(Layout::left_to_right(Align::TOP), |_ui| {});
+            self.list_events(ui, 123458, EventList::NoDate);
             ui.separator();
             ui.with_layout(Layout::bottom_up(Align::LEFT), |ui| {
                 ui.horizontal(|ui| {
                     ui.label("Rohan, Kajal, Prachi");
                 });
+                if !self.status_message.is_empty() {
+                    ui.horizontal(|ui| {
+                        ui.add(Label::new(self.status_message.clone()).wrap(true));
+                        if ui.button("Dismiss").clicked() {
+                            self.status_message.clear();
+                        }
+                    });
+                }
+            });
+        });
+    }
+
+    /// Set up the project navigator in the left panel: All, the Inbox and the projects to filter
+    /// the lists on, and the settings of the selected project
+    fn setup_project_navigator(&mut self, ui: &mut Ui) {
+        if self.unsaved_project.is_some() && !ui.input(|i| i.pointer.any_down()) {
+            self.handle_project_color_released();
+        }
+        ui.heading("Projects");
+        ui.horizontal_wrapped(|ui| {
+            if ui
+                .selectable_label(self.selected_project.is_none(), "All")
+                .clicked()
+            {
+                self.handle_project_selected(None);
+            }
+            if ui
+                .selectable_label(self.selected_project == Some(None), "Inbox")
+                .clicked()
+            {
+                self.handle_project_selected(Some(None));
+            }
+            for project in self.projects.clone() {
+                if project.archived && !self.show_archived_projects {
+                    continue;
+                }
+                let mut name = RichText::new(&project.name);
+                if let Some([r, g, b]) = project.rgb() {
+                    name = name.color(Color32::from_rgb(r, g, b));
+                }
+                if project.archived {
+                    name = name.italics();
+                }
+                if ui
+                    .selectable_label(self.selected_project == Some(Some(project.id)), name)
+                    .clicked()
+                {
+                    self.handle_project_selected(Some(Some(project.id)));
+                }
+            }
+        });
+        ui.checkbox(&mut self.show_archived_projects, "Show archived");
+        ui.horizontal(|ui| {
+            let response = ui.add(
+                TextEdit::singleline(&mut self.new_project_name)
+                    .hint_text("New project")
+                    .desired_width(style_constants::PROJECT_NAME_FIELD_WIDTH),
+            );
+            let is_enter_pressed = response.lost_focus() && ui.input(|i| i.key_pressed(Key::Enter));
+            if (ui.button("Add").clicked() || is_enter_pressed)
+                && !self.new_project_name.trim().is_empty()
+            {
+                self.handle_add_project_button_clicked();
+            }
+        });
+        let Some(mut project) = self.selected_project_entry().cloned() else {
+            return;
+        };
+        let index = self
+            .projects
+            .iter()
+            .position(|x| x.id == project.id)
+            .unwrap_or_default();
+        ui.horizontal(|ui| {
+            let mut rgb = project.rgb().unwrap_or_default();
+            if ui.color_edit_button_srgb(&mut rgb).changed() {
+                project.set_rgb(rgb);
+                self.handle_project_color_picked(&project);
+            }
+            if ui.add_enabled(index > 0, Button::new("Up")).clicked() {
+                self.handle_move_project_button_clicked(project.id, index - 1);
+            }
+            if ui
+                .add_enabled(index + 1 < self.projects.len(), Button::new("Down"))
+                .clicked()
+            {
+                self.handle_move_project_button_clicked(project.id, index + 1);
+            }
+            let archive_text = if project.archived {
+                "Unarchive"
+            } else {
+                "Archive"
+            };
+            if ui.button(archive_text).clicked() {
+                project.archived = !project.archived;
+                self.handle_project_changed(&project);
+            }
+            if ui.button("Delete").clicked() {
+                self.handle_delete_project_button_clicked(project.id);
+            }
+        });
+    }
+
+    /// Set up the bulk action bar at the bottom of the window, shown while events are selected
+    pub fn setup_bulk_action_bar(&mut self, ctx: &egui::Context) {
+        if self.selected_ids.is_empty() {
+            return;
+        }
+        TopBottomPanel::bottom("bulk_action_bar").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label(format!("{} selected", self.selected_ids.len()));
+                if ui.button("Mark Done").clicked() {
+                    KrabbyDoUi::handle_bulk_done_button_clicked(self, true);
+                }
+                if ui.button("Mark Not Done").clicked() {
+                    KrabbyDoUi::handle_bulk_done_button_clicked(self, false);
+                }
+                ui.separator();
+                ui.add(
+                    widgets::TextEdit::singleline(&mut self.bulk_tag)
+                        .hint_text("Tag")
+                        .desired_width(style_constants::BULK_TAG_FIELD_WIDTH),
+                );
+                if ui.button("Add Tag").clicked() {
+                    KrabbyDoUi::handle_bulk_add_tag_button_clicked(self);
+                }
+                if ui.button("Remove Tag").clicked() {
+                    KrabbyDoUi::handle_bulk_remove_tag_button_clicked(self);
+                }
+                ui.separator();
+                ui.add(DragValue::new(&mut self.bulk_shift_days).suffix(" days"));
+                if ui.button("Shift Dates").clicked() {
+                    KrabbyDoUi::handle_bulk_shift_button_clicked(self);
+                }
+                ui.separator();
+                if ui.button("Delete").clicked() {
+                    KrabbyDoUi::handle_bulk_delete_button_clicked(self);
+                }
+                if ui.button("Clear Selection").clicked() {
+                    self.selected_ids.clear();
+                }
             });
         });
     }
@@ -454,7 +1930,7 @@ impl KrabbyDoUi {
             ui.with_layout(Layout::left_to_right(Align::TOP), |_ui| {});
             ui.heading("Marked Done");
             ui.with_layout(Layout::left_to_right(Align::TOP), |_ui| {});
-            self.list_events(ui, 123457, true);
+            self.list_events(ui, 123457, EventList::Done);
             ui.separator();
         });
     }
@@ -475,6 +1951,30 @@ impl KrabbyDoUi {
                 });
             });
             if self.is_show_central_panel_context_elements {
+                ui.horizontal(|ui| {
+                    ui.selectable_value(
+                        &mut self.central_panel_tab,
+                        CentralPanelTab::Details,
+                        "Details",
+                    );
+                    if ui
+                        .selectable_value(
+                            &mut self.central_panel_tab,
+                            CentralPanelTab::History,
+                            "History",
+                        )
+                        .clicked()
+                    {
+                        KrabbyDoUi::load_history(self);
+                    }
+                });
+                ui.separator();
+            }
+            if self.is_show_central_panel_context_elements
+                && self.central_panel_tab == CentralPanelTab::History
+            {
+                self.setup_history_tab(ui);
+            } else if self.is_show_central_panel_context_elements {
                 ui.with_layout(Layout::left_to_right(Align::TOP), |ui| {
                     ui.set_min_width(style_constants::DETAILS_PANEL_MINIMUM_WIDTH);
                     ui.add(Label::new(self.details_panel_details.clone()).wrap(true));
@@ -483,23 +1983,348 @@ impl KrabbyDoUi {
                 ui.with_layout(Layout::left_to_right(Align::TOP), |ui| {
                     ui.add(Label::new(self.details_panel_time.clone()).wrap(true));
                 });
-                ui.separator();
+                ui.separator();
+                if let Some(recurrence) = self.active_entry.recurrence.clone() {
+                    self.setup_occurrences(ui, &recurrence);
+                    ui.separator();
+                }
+                ui.with_layout(Layout::left_to_right(Align::TOP), |ui| {
+                    ui.label("Tags:");
+                    ui.label(self.active_entry.tags.join(", "));
+                });
+                ui.separator();
+                ui.with_layout(Layout::left_to_right(Align::TOP), |ui| {
+                    ui.label("Project:");
+                    ui.label(project_name(self.active_entry.project_id, &self.projects));
+                });
+                ui.separator();
+                ui.with_layout(Layout::left_to_right(Align::TOP), |ui| {
+                    let urgency = self
+                        .client
+                        .store()
+                        .urgency()
+                        .score(&self.active_entry, Utc::now());
+                    ui.label(format!(
+                        "Priority: {}    Urgency: {:.1}",
+                        priority_name(self.active_entry.priority),
+                        urgency
+                    ));
+                });
+                ui.separator();
+                self.setup_checklist(ui);
+                ui.separator();
+                self.setup_blockers(ui);
+                ui.separator();
+                ui.with_layout(Layout::left_to_right(Align::TOP), |ui| {
+                    ui.set_max_height(style_constants::EDIT_EVENT_BUTTON_MAX_HEIGHT);
+                    ui.with_layout(
+                        Layout::centered_and_justified(Direction::LeftToRight),
+                        |ui| {
+                            if ui.button("Edit Event").clicked() {
+                                KrabbyDoUi::handle_edit_event_button_clicked(self);
+                            }
+                        },
+                    );
+                });
+            }
+        });
+    }
+
+    /// Set up the checklist of the selected event in the central panel: its progress, a checkbox,
+    /// Up / Down and Remove buttons per subtask, and a field to add one
+    fn setup_checklist(&mut self, ui: &mut Ui) {
+        let (done, total) = self.active_entry.subtask_progress();
+        if total > 0 {
+            ui.add(
+                ProgressBar::new(done as f32 / total as f32)
+                    .text(format!("{} / {} subtasks done", done, total)),
+            );
+        }
+        let (mut toggled, mut moved, mut removed) = (None, None, None);
+        for (index, subtask) in self.active_entry.subtasks.iter().enumerate() {
+            ui.horizontal(|ui| {
+                let mut is_done = subtask.is_done;
+                if ui.checkbox(&mut is_done, &subtask.title).clicked() {
+                    toggled = Some(subtask.id);
+                }
+                if ui
+                    .add_enabled(index > 0, Button::new("Up").small())
+                    .clicked()
+                {
+                    moved = Some((subtask.id, index - 1));
+                }
+                if ui
+                    .add_enabled(index + 1 < total, Button::new("Down").small())
+                    .clicked()
+                {
+                    moved = Some((subtask.id, index + 1));
+                }
+                if ui.small_button("Remove").clicked() {
+                    removed = Some(subtask.id);
+                }
+            });
+        }
+        if let Some(id) = toggled {
+            self.handle_subtask_checkbox_clicked(id);
+        }
+        if let Some((id, position)) = moved {
+            self.handle_subtask_move_button_clicked(id, position);
+        }
+        if let Some(id) = removed {
+            self.handle_subtask_remove_button_clicked(id);
+        }
+        ui.horizontal(|ui| {
+            let response =
+                ui.add(TextEdit::singleline(&mut self.new_subtask_title).hint_text("New subtask"));
+            let is_entered = response.lost_focus() && ui.input(|i| i.key_pressed(Key::Enter));
+            if ui.button("Add").clicked() || is_entered {
+                self.handle_add_subtask_button_clicked();
+            }
+        });
+        let mut auto_complete = self.active_entry.auto_complete;
+        if ui
+            .checkbox(
+                &mut auto_complete,
+                "Mark the event done when all subtasks are done",
+            )
+            .clicked()
+        {
+            self.handle_auto_complete_checkbox_clicked(auto_complete);
+        }
+    }
+
+    /// Set up the blockers of the selected event in the central panel: a link to each of them,
+    /// which shows it in the central panel, and a menu to add another
+    fn setup_blockers(&mut self, ui: &mut Ui) {
+        let (mut selected, mut removed, mut added) = (None, None, None);
+        ui.horizontal_wrapped(|ui| {
+            ui.label("Blocked by:");
+            if self.active_entry.blocked_by.is_empty() {
+                ui.label("Nothing");
+            }
+            for id in &self.active_entry.blocked_by {
+                let Some(blocker) = self.event_entries.iter().find(|x| x.unique_id == *id) else {
+                    continue;
+                };
+                let mut title = RichText::new(blocker.title.clone());
+                if blocker.is_done {
+                    title = title.strikethrough();
+                }
+                if ui.link(title).clicked() {
+                    selected = Some(blocker.clone());
+                }
+                if ui.small_button("Remove").clicked() {
+                    removed = Some(*id);
+                }
+            }
+        });
+        egui::ComboBox::from_id_source("add_blocker")
+            .selected_text("Add blocker")
+            .show_ui(ui, |ui| {
+                for entry in &self.event_entries {
+                    if entry.is_done
+                        || entry.unique_id == self.active_entry.unique_id
+                        || self.active_entry.blocked_by.contains(&entry.unique_id)
+                    {
+                        continue;
+                    }
+                    if ui.selectable_label(false, &entry.title).clicked() {
+                        added = Some(entry.unique_id);
+                    }
+                }
+            });
+        if let Some(entry) = selected {
+            self.handle_event_list_item_clicked(&entry);
+        }
+        if let Some(id) = removed {
+            self.handle_remove_blocker_button_clicked(id);
+        }
+        if let Some(id) = added {
+            self.handle_add_blocker(id);
+        }
+    }
+
+    /// Set up the Tags field of the New / Edit Event dialog: a chip per tag with a button removing
+    /// it, the field a tag is typed in, and the tags in use starting with what was typed
+    fn setup_tag_editor(&mut self, ui: &mut Ui) {
+        let (mut removed, mut suggested, mut entered) = (None, None, false);
+        ui.vertical(|ui| {
+            ui.horizontal_wrapped(|ui| {
+                for tag in &self.new_event_tags {
+                    ui.group(|ui| {
+                        ui.label(tag);
+                        if ui.small_button("x").clicked() {
+                            removed = Some(tag.clone());
+                        }
+                    });
+                }
+                let response = ui.add(
+                    widgets::TextEdit::singleline(&mut self.new_event_tag_input)
+                        .hint_text("Add a tag")
+                        .desired_width(120.0),
+                );
+                entered = self.new_event_tag_input.contains(',')
+                    || (response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)));
+            });
+            ui.horizontal_wrapped(|ui| {
+                for suggestion in self.tag_suggestions() {
+                    let text = format!("{} ({})", suggestion.tag, suggestion.count);
+                    if ui.small_button(text).clicked() {
+                        suggested = Some(suggestion.tag.clone());
+                    }
+                }
+            });
+        });
+        if let Some(tag) = removed {
+            self.handle_tag_chip_remove_clicked(&tag);
+        }
+        if let Some(tag) = suggested {
+            self.handle_tag_suggestion_clicked(&tag);
+        } else if entered {
+            self.handle_tag_entered();
+        }
+    }
+
+    /// Set up the Repeat fields of the New / Edit Event dialog: how often the event repeats, on
+    /// which days of the week, until when, and the dates it skips
+    fn setup_recurrence_editor(&mut self, ui: &mut Ui) {
+        ui.vertical(|ui| {
+            egui::ComboBox::from_id_source("repeat_frequency")
+                .selected_text(match self.new_event_repeat {
+                    Some(frequency) => frequency_name(frequency),
+                    None => "Never",
+                })
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.new_event_repeat, None, "Never");
+                    for frequency in FREQUENCIES {
+                        ui.selectable_value(
+                            &mut self.new_event_repeat,
+                            Some(frequency),
+                            frequency_name(frequency),
+                        );
+                    }
+                });
+            let Some(frequency) = self.new_event_repeat else {
+                return;
+            };
+            ui.horizontal(|ui| {
+                ui.label("Every");
+                ui.add(
+                    DragValue::new(&mut self.new_event_recurrence.interval)
+                        .clamp_range(1..=99)
+                        .suffix(format!(" {}(s)", frequency_unit(frequency))),
+                );
+            });
+            if matches!(frequency, Frequency::Daily | Frequency::Weekly) {
+                ui.horizontal(|ui| {
+                    for weekday in WEEKDAYS {
+                        let by_day = &mut self.new_event_recurrence.by_day;
+                        let mut is_on = by_day.iter().any(|x| x.weekday == weekday);
+                        if ui.toggle_value(&mut is_on, weekday.to_string()).changed() {
+                            by_day.retain(|x| x.weekday != weekday);
+                            if is_on {
+                                by_day.push(middleware::ByDay::every(weekday));
+                                by_day.sort_by_key(|x| x.weekday.num_days_from_monday());
+                            }
+                        }
+                    }
+                });
+            }
+            ui.horizontal(|ui| {
+                ui.radio_value(&mut self.new_event_repeat_end, RepeatEnd::Never, "Forever");
+                ui.radio_value(&mut self.new_event_repeat_end, RepeatEnd::AfterCount, "For");
+                ui.add(
+                    DragValue::new(&mut self.new_event_repeat_count)
+                        .clamp_range(1..=999)
+                        .suffix(" times"),
+                );
+                ui.radio_value(&mut self.new_event_repeat_end, RepeatEnd::OnDate, "Until");
+                let date = self
+                    .new_event_repeat_until
+                    .get_or_insert_with(|| today(&self.timezone));
+                ui.add(egui_extras::DatePickerButton::new(date).id_source("repeat_until"));
+            });
+            let mut removed = None;
+            for (index, exception) in self.new_event_recurrence.exceptions.iter().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.label(format!(
+                        "Skips {}",
+                        exception
+                            .with_timezone(&self.timezone)
+                            .format("%B %e, %Y %l:%M %p")
+                    ));
+                    if ui.small_button("Remove").clicked() {
+                        removed = Some(index);
+                    }
+                });
+            }
+            if let Some(index) = removed {
+                self.new_event_recurrence.exceptions.remove(index);
+            }
+        });
+    }
+
+    /// Set up the rule and the next occurrences of the selected recurring event in the central
+    /// panel, with a button to skip each of them
+    fn setup_occurrences(&mut self, ui: &mut Ui, recurrence: &Recurrence) {
+        ui.label(format!(
+            "Repeats: {}",
+            describe_recurrence(recurrence, &self.timezone)
+        ));
+        let Some(start) = self.active_entry.date_time else {
+            return;
+        };
+        let mut skipped = None;
+        for date_time in recurrence.occurrences(start, &self.timezone).take(4) {
+            ui.horizontal(|ui| {
+                ui.label(
+                    date_time
+                        .with_timezone(&self.timezone)
+                        .format("%A, %B %e, %Y %l:%M %p")
+                        .to_string(),
+                );
+                if ui.small_button("Skip").clicked() {
+                    skipped = Some(date_time);
+                }
+            });
+        }
+        if let Some(date_time) = skipped {
+            self.handle_skip_occurrence_button_clicked(date_time);
+        }
+    }
+
+    /// Set up the History tab of the central panel listing the revisions of the selected event
+    pub fn setup_history_tab(&mut self, ui: &mut Ui) {
+        if self.history_entries.is_empty() {
+            ui.label("No history recorded for this event");
+        }
+        ScrollArea::vertical().show(ui, |ui| {
+            // Newest revision first
+            for revision in self.history_entries.clone().iter().rev() {
                 ui.with_layout(Layout::left_to_right(Align::TOP), |ui| {
-                    ui.label("Tags:");
-                    ui.label(self.active_entry.tags.clone());
+                    ui.label(format!(
+                        "{} \t{:?} by {}",
+                        revision
+                            .timestamp
+                            .with_timezone(&self.timezone)
+                            .format("%B %e, %Y %l:%M %p"),
+                        revision.action,
+                        revision.actor
+                    ));
+                    if ui.button("Revert").clicked() {
+                        KrabbyDoUi::handle_history_revert_button_clicked(self, revision);
+                    }
                 });
-                ui.separator();
-                ui.with_layout(Layout::left_to_right(Align::TOP), |ui| {
-                    ui.set_max_height(style_constants::EDIT_EVENT_BUTTON_MAX_HEIGHT);
-                    ui.with_layout(
-                        Layout::centered_and_justified(Direction::LeftToRight),
-                        |ui| {
-                            if ui.button("Edit Event").clicked() {
-                                KrabbyDoUi::handle_edit_event_button_clicked(self);
-                            }
-                        },
+                for change in &revision.changes {
+                    ui.add(
+                        Label::new(format!(
+                            "    {}: {} \u{2192} {}",
+                            change.field, change.old_value, change.new_value
+                        ))
+                        .wrap(true),
                     );
-                });
+                }
+                ui.separator();
             }
         });
     }
@@ -537,17 +2362,21 @@ impl KrabbyDoUi {
                     ui.set_min_width(style_constants::NEW_EDIT_DIALOG_MIN_LABEL_WIDTH);
                     ui.label("Date");
                 });
+                ui.add(Checkbox::new(&mut self.new_event_has_date, ""));
                 let date = self
                     .new_event_date
-                    .get_or_insert_with(|| chrono::offset::Utc::now().date_naive());
-                ui.add(egui_extras::DatePickerButton::new(date));
+                    .get_or_insert_with(|| today(&self.timezone));
+                ui.add_enabled(
+                    self.new_event_has_date,
+                    egui_extras::DatePickerButton::new(date),
+                );
             });
             ui.with_layout(Layout::left_to_right(Align::TOP), |ui| {
                 ui.with_layout(Layout::left_to_right(Align::TOP), |ui| {
                     ui.set_min_width(style_constants::NEW_EDIT_DIALOG_MIN_LABEL_WIDTH);
                     ui.label("Time");
                 });
-                ui.with_layout(Layout::left_to_right(Align::TOP), |ui| {
+                ui.add_enabled_ui(self.new_event_has_date, |ui| {
                     ui.with_layout(Layout::left_to_right(Align::TOP), |ui| {
                         ui.set_min_width(style_constants::NEW_EDIT_DIALOG_MIN_LABEL_WIDTH);
                         ui.label("Hour");
@@ -564,6 +2393,15 @@ impl KrabbyDoUi {
                     });
                 });
             });
+            ui.with_layout(Layout::left_to_right(Align::TOP), |ui| {
+                ui.with_layout(Layout::left_to_right(Align::TOP), |ui| {
+                    ui.set_min_width(style_constants::NEW_EDIT_DIALOG_MIN_LABEL_WIDTH);
+                    ui.label("Repeat");
+                });
+                ui.add_enabled_ui(self.new_event_has_date, |ui| {
+                    self.setup_recurrence_editor(ui);
+                });
+            });
             ui.with_layout(Layout::left_to_right(Align::TOP), |ui| {
                 ui.with_layout(Layout::left_to_right(Align::TOP), |ui| {
                     ui.set_min_width(style_constants::NEW_EDIT_DIALOG_MIN_LABEL_WIDTH);
@@ -571,17 +2409,50 @@ impl KrabbyDoUi {
                 });
                 ui.add(Checkbox::new(&mut self.new_event_is_done, ""));
             });
+            ui.with_layout(Layout::left_to_right(Align::TOP), |ui| {
+                ui.with_layout(Layout::left_to_right(Align::TOP), |ui| {
+                    ui.set_min_width(style_constants::NEW_EDIT_DIALOG_MIN_LABEL_WIDTH);
+                    ui.label("Priority");
+                });
+                egui::ComboBox::from_id_source("priority")
+                    .selected_text(priority_name(self.new_event_priority))
+                    .show_ui(ui, |ui| {
+                        for priority in Priority::ALL {
+                            ui.selectable_value(
+                                &mut self.new_event_priority,
+                                priority,
+                                priority_name(priority),
+                            );
+                        }
+                    });
+            });
             ui.with_layout(Layout::left_to_right(Align::TOP), |ui| {
                 ui.with_layout(Layout::left_to_right(Align::TOP), |ui| {
                     ui.set_min_width(style_constants::NEW_EDIT_DIALOG_MIN_LABEL_WIDTH);
                     ui.label("Tags");
                 });
-                ui.horizontal(|ui| {
-                    ui.add(
-                        widgets::TextEdit::multiline(&mut self.new_event_tags)
-                            .hint_text("Enter tags"),
-                    );
+                self.setup_tag_editor(ui);
+            });
+            ui.with_layout(Layout::left_to_right(Align::TOP), |ui| {
+                ui.with_layout(Layout::left_to_right(Align::TOP), |ui| {
+                    ui.set_min_width(style_constants::NEW_EDIT_DIALOG_MIN_LABEL_WIDTH);
+                    ui.label("Project");
                 });
+                egui::ComboBox::from_id_source("project")
+                    .selected_text(project_name(self.new_event_project, &self.projects))
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut self.new_event_project, None, "Inbox");
+                        for project in &self.projects {
+                            // An archived project is only offered to the events already in it
+                            if !project.archived || self.new_event_project == Some(project.id) {
+                                ui.selectable_value(
+                                    &mut self.new_event_project,
+                                    Some(project.id),
+                                    &project.name,
+                                );
+                            }
+                        }
+                    });
             });
             ui.with_layout(Layout::left_to_right(Align::TOP), |ui| {
                 ui.set_max_width(style_constants::NEW_EDIT_DIALOG_MAX_WIDTH);
@@ -596,29 +2467,260 @@ impl KrabbyDoUi {
         });
     }
 
+    /// Set up Trash dialog listing the deleted events, which can be restored or deleted for good
+    pub fn setup_trash_dialog(&mut self, ctx: &egui::Context) {
+        Window::new("Trash").show(ctx, |ui| {
+            ui.style_mut().spacing.item_spacing.y =
+                style_constants::NEW_EDIT_DIALOG_VERTICAL_SPACING;
+            if self.trash_entries.is_empty() {
+                ui.label("The trash is empty");
+            }
+            ScrollArea::vertical().show(ui, |ui| {
+                for entry in self.trash_entries.clone() {
+                    ui.with_layout(Layout::left_to_right(Align::TOP), |ui| {
+                        ui.set_min_width(style_constants::EVENT_LIST_ITEM_MIN_WIDTH);
+                        ui.label(entry.title.clone());
+                        if let Some(deleted_at) = entry.deleted_at {
+                            ui.label(format!(
+                                "Deleted {}",
+                                deleted_at
+                                    .with_timezone(&self.timezone)
+                                    .format("%B %e, %Y %l:%M %p")
+                            ));
+                        }
+                        if ui.button("Restore").clicked() {
+                            KrabbyDoUi::handle_trash_restore_button_clicked(self, &entry);
+                        }
+                        if ui.button("Delete Forever").clicked() {
+                            KrabbyDoUi::handle_trash_delete_forever_button_clicked(self, &entry);
+                        }
+                    });
+                }
+            });
+            ui.separator();
+            ui.with_layout(Layout::left_to_right(Align::TOP), |ui| {
+                ui.set_max_width(style_constants::NEW_EDIT_DIALOG_MAX_WIDTH);
+                ui.with_layout(Layout::right_to_left(Align::TOP), |ui| {
+                    if ui.button("Close").clicked() {
+                        self.is_show_trash_dialog = false;
+                    } else if ui.button("Empty Trash").clicked() {
+                        KrabbyDoUi::handle_empty_trash_button_clicked(self);
+                    }
+                });
+            });
+        });
+    }
+
+    /// Set up Merge dialog comparing the change made here with the event as stored now,
+    /// one row per field with a choice of the version to keep
+    pub fn setup_merge_dialog(&mut self, ctx: &egui::Context) {
+        let Some(conflict) = &mut self.merge_conflict else {
+            return;
+        };
+        let mine = MergeConflict::field_values(&conflict.mine, &self.timezone, &self.projects);
+        let theirs = MergeConflict::field_values(&conflict.theirs, &self.timezone, &self.projects);
+        let mut is_save_clicked = false;
+        let mut is_keep_theirs_clicked = false;
+        Window::new("Merge Changes").show(ctx, |ui| {
+            ui.style_mut().spacing.item_spacing.y =
+                style_constants::NEW_EDIT_DIALOG_VERTICAL_SPACING;
+            ui.label(format!(
+                "\"{}\" was changed elsewhere while you were editing it. Choose what to keep:",
+                conflict.theirs.title
+            ));
+            egui::Grid::new("merge_grid").striped(true).show(ui, |ui| {
+                ui.label("");
+                ui.strong("Mine");
+                ui.strong("Theirs");
+                ui.end_row();
+                for (index, field) in MERGE_FIELDS.iter().enumerate() {
+                    ui.label(*field);
+                    ui.radio_value(&mut conflict.keep_mine[index], true, &mine[index]);
+                    ui.radio_value(&mut conflict.keep_mine[index], false, &theirs[index]);
+                    ui.end_row();
+                }
+            });
+            ui.separator();
+            ui.with_layout(Layout::left_to_right(Align::TOP), |ui| {
+                ui.set_max_width(style_constants::NEW_EDIT_DIALOG_MAX_WIDTH);
+                ui.with_layout(Layout::right_to_left(Align::TOP), |ui| {
+                    is_keep_theirs_clicked = ui.button("Keep Theirs").clicked();
+                    is_save_clicked = ui.button("Save Merged").clicked();
+                });
+            });
+        });
+        if is_save_clicked {
+            self.handle_merge_save_button_clicked();
+        } else if is_keep_theirs_clicked {
+            self.handle_merge_keep_theirs_button_clicked();
+        }
+    }
+
     pub fn get_events(&self) -> Vec<EventEntry> {
+        if self.search_query.trim().is_empty() {
+            return self.event_entries.clone();
+        }
+        // Events found by the store, best match first
+        if let Some(search_results) = &self.search_results {
+            return search_results
+                .iter()
+                .filter_map(|id| self.event_entries.iter().find(|x| x.unique_id == *id))
+                .cloned()
+                .collect();
+        }
+
+        // Until the search has run, the loaded events are filtered on the search query as typed
         let search_query = self.search_query.to_lowercase(); // Convert search query to lowercase for case-insensitive search
 
         self.event_entries
             .iter()
             .filter(|event| {
                 event.title.to_lowercase().contains(&search_query)
-                    || event.details.to_lowercase().contains(&search_query)
-                    || event.tags.to_lowercase().contains(&search_query)
-                // .any(|tag| tag.to_lowercase().contains(&search_query))
+                    || event
+                        .details
+                        .as_deref()
+                        .unwrap_or_default()
+                        .to_lowercase()
+                        .contains(&search_query)
+                    || event.tags.iter().any(|tag| tag.contains(&search_query))
             })
             .cloned()
             .collect()
     }
 }
 
+/// Name of the project shown for an event; events without a project are in the Inbox, and the id
+/// is shown for a project which isn't loaded
+fn project_name(project_id: Option<ObjectId>, projects: &[Project]) -> String {
+    match project_id {
+        None => String::from("Inbox"),
+        Some(id) => projects
+            .iter()
+            .find(|x| x.id == id)
+            .map_or_else(|| id.to_hex(), |x| x.name.clone()),
+    }
+}
+
+/// Name of the priority shown in the Priority field
+fn priority_name(priority: Priority) -> &'static str {
+    match priority {
+        Priority::None => "None",
+        Priority::Low => "Low",
+        Priority::Medium => "Medium",
+        Priority::High => "High",
+        Priority::Critical => "Critical",
+    }
+}
+
+/// Name of the frequency shown in the Repeat field
+fn frequency_name(frequency: Frequency) -> &'static str {
+    match frequency {
+        Frequency::Daily => "Daily",
+        Frequency::Weekly => "Weekly",
+        Frequency::Monthly => "Monthly",
+        Frequency::Yearly => "Yearly",
+    }
+}
+
+/// Period the interval of a rule with the frequency is counted in
+fn frequency_unit(frequency: Frequency) -> &'static str {
+    match frequency {
+        Frequency::Daily => "day",
+        Frequency::Weekly => "week",
+        Frequency::Monthly => "month",
+        Frequency::Yearly => "year",
+    }
+}
+
+/// Describes a recurrence rule in words, e.g. "Every 2 weeks on Mon, Fri, 10 times"
+fn describe_recurrence(recurrence: &Recurrence, timezone: &Tz) -> String {
+    let unit = frequency_unit(recurrence.frequency);
+    let mut text = match recurrence.interval {
+        1 => format!("Every {}", unit),
+        interval => format!("Every {} {}s", interval, unit),
+    };
+    let days: Vec<String> = recurrence
+        .by_day
+        .iter()
+        .map(|x| match x.nth {
+            None => x.weekday.to_string(),
+            Some(-1) => format!("the last {}", x.weekday),
+            Some(nth) if nth < 0 => format!("the {} from last {}", -nth, x.weekday),
+            Some(nth) => format!("the {} {}", ordinal(nth), x.weekday),
+        })
+        .collect();
+    if !days.is_empty() {
+        text.push_str(&format!(" on {}", days.join(", ")));
+    }
+    if let Some(count) = recurrence.count {
+        text.push_str(&format!(", {} times", count));
+    }
+    if let Some(until) = recurrence.until {
+        let until = until.with_timezone(timezone).format("%B %e, %Y");
+        text.push_str(&format!(", until {}", until));
+    }
+    text
+}
+
+/// Subtasks of an event as shown in the Merge dialog, e.g. "[x] Tag the commit, [ ] Announce it"
+fn describe_subtasks(entry: &EventEntry) -> String {
+    if entry.subtasks.is_empty() {
+        return String::from("None");
+    }
+    let subtasks: Vec<String> = entry
+        .subtasks
+        .iter()
+        .map(|x| format!("[{}] {}", if x.is_done { "x" } else { " " }, x.title))
+        .collect();
+    let auto_complete = if entry.auto_complete {
+        ", auto-complete"
+    } else {
+        ""
+    };
+    format!("{}{}", subtasks.join(", "), auto_complete)
+}
+
+/// English ordinal of a number, e.g. "1st", "2nd", "11th"
+fn ordinal(n: i32) -> String {
+    let suffix = match (n % 10, n % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+    format!("{}{}", n, suffix)
+}
+
+/// Text of a dialog field, or None when it was left empty
+fn non_empty(text: &str) -> Option<String> {
+    (!text.trim().is_empty()).then(|| text.to_string())
+}
+
 impl eframe::App for KrabbyDoUi {
     /// Update the state of UI (Redraw UI)
     fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
         let Self { .. } = self;
 
+        // Ctrl+Shift+Z is checked first, since Ctrl+Z would not match it anyway but reads clearer
+        if ctx.input_mut(|i| i.consume_key(Modifiers::COMMAND | Modifiers::SHIFT, Key::Z)) {
+            self.handle_redo();
+        } else if ctx.input_mut(|i| i.consume_key(Modifiers::COMMAND, Key::Z)) {
+            self.handle_undo();
+        }
+
+        // Changes made by other instances
+        if self.sync_receiver.is_none() && !self.is_testing {
+            self.start_sync(ctx, SYNC_POLL_INTERVAL);
+        }
+        self.handle_sync_changes();
+
         // Menu Bar
         self.setup_menu_bar(ctx, frame);
+        self.poll_search(ctx);
+
+        // Bulk action bar, laid out before the side panels so they end above it
+        self.setup_bulk_action_bar(ctx);
 
         // Left Panel
         self.setup_left_panel(ctx);
@@ -634,8 +2736,20 @@ impl eframe::App for KrabbyDoUi {
             self.setup_new_event_dialog(ctx);
         }
 
+        if self.is_show_trash_dialog {
+            // Trash dialog
+            self.setup_trash_dialog(ctx);
+        }
+
+        // Merge dialog, shown while a change conflicts with one made elsewhere
+        self.setup_merge_dialog(ctx);
+
         // Sort events before displaying
-        self.sort_events_by_date();
+        if self.list_order == SortKey::Urgency {
+            self.sort_events_by_urgency();
+        } else {
+            self.sort_events_by_date();
+        }
     }
 }
 
@@ -656,6 +2770,7 @@ mod tests {
     fn test_get_selected_date_time() {
         let mut test_ui = KrabbyDoUi::default();
         test_ui.is_testing = true;
+        test_ui.timezone = Tz::UTC;
         test_ui.new_event_date = NaiveDate::from_ymd_opt(2023, 6, 9);
         test_ui.new_event_hour = 15;
         test_ui.new_event_minute = 9;
@@ -665,6 +2780,32 @@ mod tests {
         assert_eq!(test_ui.get_selected_date_time(), test_date_time);
     }
 
+    #[test]
+    fn test_local_date_time() {
+        let client = KrabbyClient::with_store(Arc::new(MemoryStore::new())).unwrap();
+        let mut test_ui = KrabbyDoUi::with_client(client);
+        test_ui.timezone = Tz::America__Los_Angeles;
+        test_ui.new_event_date = NaiveDate::from_ymd_opt(2023, 6, 9);
+        test_ui.new_event_hour = 6;
+        test_ui.new_event_minute = 30;
+        test_ui.new_event_am_pm = AmPm::Pm;
+        // 6:30 PM in Portland is 1:30 AM UTC the day after
+        let stored = Utc.with_ymd_and_hms(2023, 6, 10, 1, 30, 0).unwrap();
+        assert_eq!(test_ui.get_selected_date_time(), stored);
+
+        // Editing the event shows the time it was entered at again
+        test_ui.active_entry.date_time = Some(stored);
+        test_ui.handle_edit_event_button_clicked();
+        assert_eq!(test_ui.new_event_date, NaiveDate::from_ymd_opt(2023, 6, 9));
+        assert_eq!(test_ui.new_event_hour, 6);
+        assert_eq!(test_ui.new_event_minute, 30);
+        assert!(test_ui.new_event_am_pm == AmPm::Pm);
+
+        let entry = test_ui.active_entry.clone();
+        test_ui.handle_event_list_item_clicked(&entry);
+        assert!(test_ui.details_panel_time.ends_with("6:30 PM PDT"));
+    }
+
     #[test]
     fn test_get_events() {
         let mut test_ui = KrabbyDoUi::default();
@@ -698,6 +2839,79 @@ mod tests {
         assert_eq!(results[0].title, "Title1");
     }
 
+    #[test]
+    fn test_search_events() {
+        let client = KrabbyClient::with_store(Arc::new(MemoryStore::new())).unwrap();
+        let mut test_ui = KrabbyDoUi::with_client(client);
+        for (title, details) in [
+            ("Groceries", "Buy coffee for the planning meeting"),
+            ("Weekly meetings", "Room 3"),
+            ("Dentist", "Bring the forms"),
+        ] {
+            test_ui.handle_menu_new_clicked();
+            test_ui.new_event_title = String::from(title);
+            test_ui.new_event_details = String::from(details);
+            test_ui.handle_new_edit_ok_button_clicked();
+        }
+
+        test_ui.search_query = String::from("meeting");
+        test_ui.handle_search_query_changed();
+        test_ui.run_search();
+        // Hits in the title come first
+        let titles: Vec<String> = test_ui.get_events().into_iter().map(|x| x.title).collect();
+        assert_eq!(titles, vec!["Weekly meetings", "Groceries"]);
+
+        // A new matching event shows up in the results
+        test_ui.handle_menu_new_clicked();
+        test_ui.new_event_title = String::from("Meeting notes");
+        test_ui.handle_new_edit_ok_button_clicked();
+        assert_eq!(test_ui.get_events().len(), 3);
+
+        test_ui.search_query.clear();
+        test_ui.handle_search_query_changed();
+        assert_eq!(test_ui.get_events().len(), 4);
+    }
+
+    #[test]
+    fn test_sync_changes() {
+        let client = KrabbyClient::with_store(Arc::new(MemoryStore::new())).unwrap();
+        let mut test_ui = KrabbyDoUi::with_client(client.clone());
+        test_ui.start_sync(&egui::Context::default(), Duration::from_millis(10));
+
+        // Another instance adds an event, then renames it
+        let mut entry = EventEntry::new(
+            ObjectId::new(),
+            String::from("Added elsewhere"),
+            String::from("Details"),
+            Utc::now(),
+            false,
+            String::from("Work"),
+        );
+        client.block_on(client.store().add_event(&entry)).unwrap();
+        let wait_for = |test_ui: &mut KrabbyDoUi, title: &str| {
+            for _ in 0..500 {
+                test_ui.handle_sync_changes();
+                if test_ui.event_entries.iter().any(|x| x.title == title) {
+                    return;
+                }
+                std::thread::sleep(Duration::from_millis(20));
+            }
+            panic!("{} never arrived", title);
+        };
+        wait_for(&mut test_ui, "Added elsewhere");
+        test_ui.handle_event_list_item_clicked(&entry);
+
+        entry.title = String::from("Renamed elsewhere");
+        entry = client.block_on(client.store().update_task(&entry)).unwrap();
+        wait_for(&mut test_ui, "Renamed elsewhere");
+        assert_eq!(test_ui.event_entries.len(), 1);
+        assert_eq!(test_ui.details_panel_title, "Renamed elsewhere");
+
+        test_ui.apply_event_change(EventChange::Deleted(entry.unique_id));
+        assert!(test_ui.event_entries.is_empty());
+        assert!(!test_ui.is_show_central_panel_context_elements);
+    }
+
     #[test]
     fn test_sort_events_by_date() {
         let mut test_ui = KrabbyDoUi::default();
@@ -730,6 +2944,590 @@ mod tests {
         assert_eq!(test_ui.event_entries[1].title, "");
     }
 
+    #[test]
+    fn test_handle_middleware_error() {
+        let client = KrabbyClient::with_store(Arc::new(MemoryStore::new())).unwrap();
+        let mut test_ui = KrabbyDoUi::with_client(client);
+        let event_entry = EventEntry::new(
+            ObjectId::new(),
+            "Title1".to_string(),
+            "Details1".to_string(),
+            Utc::now(),
+            false,
+            "Tag1".to_string(),
+        );
+        test_ui.event_entries.push(event_entry.clone());
+
+        // The event was never stored, so updating it reports NotFound and drops it from the list
+        test_ui.active_entry = event_entry;
+        test_ui.new_edit_title = String::from("Edit Event");
+        test_ui.new_event_title = "Title2".to_string();
+        test_ui.new_event_date = NaiveDate::from_ymd_opt(2023, 6, 9);
+        test_ui.handle_new_edit_ok_button_clicked();
+
+        assert!(test_ui.event_entries.is_empty());
+        assert!(!test_ui.status_message.is_empty());
+    }
+
+    #[test]
+    fn test_delete_and_restore_event() {
+        let client = KrabbyClient::with_store(Arc::new(MemoryStore::new())).unwrap();
+        let mut test_ui = KrabbyDoUi::with_client(client);
+        test_ui.new_edit_title = String::from("New Event");
+        test_ui.new_event_title = "Title1".to_string();
+        test_ui.new_event_date = NaiveDate::from_ymd_opt(2023, 6, 9);
+        test_ui.handle_new_edit_ok_button_clicked();
+        let entry = test_ui.event_entries[0].clone();
+
+        // Deleting moves the event to the trash of the store
+        test_ui.handle_event_list_item_delete_button_clicked(&entry);
+        assert!(test_ui.event_entries.is_empty());
+        test_ui.handle_menu_trash_clicked();
+        assert_eq!(test_ui.trash_entries.len(), 1);
+
+        test_ui.handle_trash_restore_button_clicked(&entry);
+        assert!(test_ui.trash_entries.is_empty());
+        assert_eq!(test_ui.event_entries, vec![entry]);
+    }
+
+    #[test]
+    fn test_undated_event() {
+        let client = KrabbyClient::with_store(Arc::new(MemoryStore::new())).unwrap();
+        let mut test_ui = KrabbyDoUi::with_client(client);
+        test_ui.handle_menu_new_clicked();
+        test_ui.new_event_title = "Someday".to_string();
+        test_ui.new_event_has_date = false;
+        test_ui.handle_new_edit_ok_button_clicked();
+        test_ui.handle_menu_new_clicked();
+        test_ui.new_event_title = "Dated".to_string();
+        test_ui.new_event_details = "Details".to_string();
+        test_ui.handle_new_edit_ok_button_clicked();
+
+        // Empty fields are stored as left out and undated events get their own list
+        let undated = test_ui.list_of(EventList::NoDate);
+        assert_eq!(undated.len(), 1);
+        assert_eq!(undated[0].title, "Someday");
+        assert_eq!(undated[0].date_time, None);
+        assert_eq!(undated[0].details, None);
+        assert!(undated[0].tags.is_empty());
+        assert_eq!(test_ui.list_of(EventList::Upcoming)[0].title, "Dated");
+
+        test_ui.handle_event_list_item_clicked(&undated[0]);
+        assert_eq!(test_ui.details_panel_time, "No date");
+        test_ui.handle_edit_event_button_clicked();
+        assert!(!test_ui.new_event_has_date);
+    }
+
+    #[test]
+    fn test_recurring_event() {
+        let client = KrabbyClient::with_store(Arc::new(MemoryStore::new())).unwrap();
+        let mut test_ui = KrabbyDoUi::with_client(client);
+        test_ui.timezone = Tz::UTC;
+        test_ui.handle_menu_new_clicked();
+        test_ui.new_event_title = "Standup".to_string();
+        test_ui.new_event_date = NaiveDate::from_ymd_opt(2023, 6, 9);
+        test_ui.new_event_hour = 9;
+        test_ui.new_event_minute = 0;
+        test_ui.new_event_am_pm = AmPm::Am;
+        test_ui.new_event_repeat = Some(Frequency::Weekly);
+        test_ui.new_event_recurrence = Recurrence::new(Frequency::Weekly)
+            .on(Weekday::Mon)
+            .on(Weekday::Fri);
+        test_ui.new_event_repeat_end = RepeatEnd::AfterCount;
+        test_ui.new_event_repeat_count = 4;
+        test_ui.handle_new_edit_ok_button_clicked();
+        let entry = test_ui.event_entries[0].clone();
+        assert_eq!(
+            entry.recurrence.as_ref().map(ToString::to_string),
+            Some(String::from("RRULE:FREQ=WEEKLY;BYDAY=MO,FR;COUNT=4"))
+        );
+        assert_eq!(
+            describe_recurrence(entry.recurrence.as_ref().unwrap(), &Tz::UTC),
+            "Every week on Mon, Fri, 4 times"
+        );
+
+        // Marking the standup done moves it on to Monday
+        test_ui.handle_event_list_item_clicked(&entry);
+        test_ui.handle_event_list_item_done_button_clicked(&entry);
+        let monday = Utc.with_ymd_and_hms(2023, 6, 12, 9, 0, 0).unwrap();
+        let next = test_ui.event_entries[0].clone();
+        assert!(!next.is_done);
+        assert_eq!(next.date_time, Some(monday));
+        assert_eq!(test_ui.active_entry, next);
+        assert_eq!(
+            test_ui.undo_stack.next_undo().unwrap().describe(),
+            "mark \"Standup\" done"
+        );
+
+        // Skipping Friday keeps Monday as the next occurrence
+        let friday = monday + chrono::Duration::days(4);
+        test_ui.handle_skip_occurrence_button_clicked(friday);
+        let skipped = test_ui.event_entries[0].clone();
+        assert_eq!(skipped.date_time, Some(monday));
+        assert_eq!(
+            skipped.recurrence.as_ref().unwrap().exceptions,
+            vec![friday]
+        );
+
+        // The dialog shows the rule as it was entered
+        test_ui.handle_edit_event_button_clicked();
+        assert_eq!(test_ui.new_event_repeat, Some(Frequency::Weekly));
+        assert!(test_ui.new_event_repeat_end == RepeatEnd::AfterCount);
+        assert_eq!(test_ui.new_event_repeat_count, 3);
+        assert_eq!(test_ui.get_selected_recurrence(), skipped.recurrence);
+
+        test_ui.handle_undo();
+        test_ui.handle_undo();
+        assert_eq!(test_ui.event_entries[0].date_time, entry.date_time);
+    }
+
+    #[test]
+    fn test_subtasks() {
+        let client = KrabbyClient::with_store(Arc::new(MemoryStore::new())).unwrap();
+        let mut test_ui = KrabbyDoUi::with_client(client);
+        test_ui.handle_menu_new_clicked();
+        test_ui.new_event_title = "Prepare release".to_string();
+        test_ui.handle_new_edit_ok_button_clicked();
+        let entry = test_ui.event_entries[0].clone();
+        test_ui.handle_event_list_item_clicked(&entry);
+
+        for title in ["Tag the commit", "Write the changelog", " "] {
+            test_ui.new_subtask_title = title.to_string();
+            test_ui.handle_add_subtask_button_clicked();
+        }
+        // The blank subtask was refused
+        assert!(!test_ui.status_message.is_empty());
+        assert!(test_ui.new_subtask_title.is_empty());
+        let ids: Vec<ObjectId> = test_ui.active_entry.subtasks.iter().map(|x| x.id).collect();
+        test_ui.handle_subtask_move_button_clicked(ids[1], 0);
+        test_ui.handle_auto_complete_checkbox_clicked(true);
+        test_ui.handle_subtask_checkbox_clicked(ids[0]);
+        assert_eq!(test_ui.active_entry.subtask_progress(), (1, 2));
+        assert_eq!(test_ui.active_entry.subtasks[1].id, ids[0]);
+        assert!(!test_ui.active_entry.is_done);
+
+        // Checking the last subtask moves the event to Marked Done
+        test_ui.handle_subtask_checkbox_clicked(ids[1]);
+        assert!(test_ui.event_entries[0].is_done);
+        assert_eq!(test_ui.active_entry, test_ui.event_entries[0]);
+
+        // Editing the event keeps its subtasks
+        test_ui.handle_edit_event_button_clicked();
+        test_ui.new_event_title = "Prepare the release".to_string();
+        test_ui.handle_new_edit_ok_button_clicked();
+        assert_eq!(test_ui.event_entries[0].subtask_progress(), (2, 2));
+
+        test_ui.handle_undo();
+        test_ui.handle_undo();
+        test_ui.handle_subtask_remove_button_clicked(ids[0]);
+        assert_eq!(test_ui.event_entries[0].subtask_progress(), (0, 1));
+        assert!(!test_ui.event_entries[0].is_done);
+    }
+
+    #[test]
+    fn test_blockers() {
+        let client = KrabbyClient::with_store(Arc::new(MemoryStore::new())).unwrap();
+        let mut test_ui = KrabbyDoUi::with_client(client);
+        for title in ["Tag the commit", "Announce it"] {
+            test_ui.handle_menu_new_clicked();
+            test_ui.new_event_title = title.to_string();
+            test_ui.handle_new_edit_ok_button_clicked();
+        }
+        let (tag, announce) = (
+            test_ui.event_entries[0].clone(),
+            test_ui.event_entries[1].clone(),
+        );
+
+        test_ui.handle_event_list_item_clicked(&announce);
+        test_ui.handle_add_blocker(tag.unique_id);
+        let announce = test_ui.event_entries[1].clone();
+        assert_eq!(announce.blocked_by, vec![tag.unique_id]);
+        assert!(announce.is_blocked(&test_ui.event_entries));
+
+        // The tag can't wait for the announcement in turn
+        test_ui.handle_event_list_item_clicked(&tag);
+        test_ui.handle_add_blocker(announce.unique_id);
+        assert!(test_ui.status_message.contains("cycle"));
+        assert!(test_ui.event_entries[0].blocked_by.is_empty());
+
+        // Editing the announcement keeps its blocker, and marking the tag done unblocks it
+        test_ui.handle_event_list_item_clicked(&announce);
+        test_ui.handle_edit_event_button_clicked();
+        test_ui.new_event_title = "Announce the release".to_string();
+        test_ui.handle_new_edit_ok_button_clicked();
+        assert_eq!(test_ui.event_entries[1].blocked_by, vec![tag.unique_id]);
+        test_ui.handle_event_list_item_done_button_clicked(&tag);
+        assert!(!test_ui.event_entries[1].is_blocked(&test_ui.event_entries));
+
+        test_ui.handle_remove_blocker_button_clicked(tag.unique_id);
+        assert!(test_ui.event_entries[1].blocked_by.is_empty());
+    }
+
+    #[test]
+    fn test_new_event_version() {
+        let client = KrabbyClient::with_store(Arc::new(MemoryStore::new())).unwrap();
+        let mut test_ui = KrabbyDoUi::with_client(client);
+        test_ui.handle_menu_new_clicked();
+        test_ui.new_event_title = "Report".to_string();
+        test_ui.handle_new_edit_ok_button_clicked();
+        for title in ["Draft the report", "Send the report"] {
+            let report = test_ui.event_entries[0].clone();
+            test_ui.handle_event_list_item_edit_button_clicked(&report);
+            test_ui.new_event_title = title.to_string();
+            test_ui.handle_new_edit_ok_button_clicked();
+        }
+        let report = test_ui.event_entries[0].clone();
+        assert!(report.version > 0);
+
+        // A new event doesn't take the version of the event selected when it is created
+        test_ui.handle_event_list_item_clicked(&report);
+        test_ui.handle_menu_new_clicked();
+        test_ui.new_event_title = "Standup".to_string();
+        test_ui.handle_new_edit_ok_button_clicked();
+        let standup = test_ui
+            .event_entries
+            .iter()
+            .find(|x| x.title == "Standup")
+            .unwrap()
+            .clone();
+        assert_eq!(standup.version, 0);
+    }
+
+    #[test]
+    fn test_tag_editor() {
+        let client = KrabbyClient::with_store(Arc::new(MemoryStore::new())).unwrap();
+        let mut test_ui = KrabbyDoUi::with_client(client);
+        test_ui.handle_menu_new_clicked();
+        test_ui.new_event_title = "Report".to_string();
+        test_ui.new_event_tag_input = "Work, urgent work".to_string();
+        test_ui.handle_tag_entered();
+        assert_eq!(test_ui.new_event_tags, vec!["work", "urgent"]);
+        test_ui.handle_new_edit_ok_button_clicked();
+
+        // Tags in use are suggested as they are typed, and a tag still being typed is kept
+        test_ui.handle_menu_new_clicked();
+        assert!(test_ui.new_event_tags.is_empty());
+        test_ui.new_event_title = "Standup".to_string();
+        test_ui.new_event_tag_input = "W".to_string();
+        let suggestions: Vec<String> = test_ui
+            .tag_suggestions()
+            .into_iter()
+            .map(|x| x.tag.clone())
+            .collect();
+        assert_eq!(suggestions, vec!["work"]);
+        test_ui.handle_tag_suggestion_clicked("work");
+        test_ui.new_event_tag_input = "Home".to_string();
+        test_ui.handle_new_edit_ok_button_clicked();
+        let standup = test_ui
+            .event_entries
+            .iter()
+            .find(|x| x.title == "Standup")
+            .unwrap()
+            .clone();
+        assert_eq!(standup.tags, vec!["work", "home"]);
+
+        let report = test_ui
+            .event_entries
+            .iter()
+            .find(|x| x.title == "Report")
+            .unwrap()
+            .clone();
+        test_ui.handle_event_list_item_edit_button_clicked(&report);
+        test_ui.handle_tag_chip_remove_clicked("urgent");
+        test_ui.handle_new_edit_ok_button_clicked();
+        assert_eq!(
+            test_ui
+                .client
+                .block_on(test_ui.client.store().get_tags())
+                .unwrap(),
+            vec![
+                TagCount {
+                    tag: "work".to_string(),
+                    count: 2
+                },
+                TagCount {
+                    tag: "home".to_string(),
+                    count: 1
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_projects() {
+        let client = KrabbyClient::with_store(Arc::new(MemoryStore::new())).unwrap();
+        let mut test_ui = KrabbyDoUi::with_client(client);
+        let add_event = |test_ui: &mut KrabbyDoUi, title: &str| {
+            test_ui.handle_menu_new_clicked();
+            test_ui.new_event_title = title.to_string();
+            test_ui.handle_new_edit_ok_button_clicked();
+        };
+        let titles = |test_ui: &KrabbyDoUi| -> Vec<String> {
+            let mut titles: Vec<String> = test_ui
+                .list_of(EventList::Upcoming)
+                .into_iter()
+                .map(|x| x.title)
+                .collect();
+            titles.sort();
+            titles
+        };
+
+        // A new project is selected, and new events go in the selected project
+        add_event(&mut test_ui, "Call the bank");
+        test_ui.new_project_name = "Home".to_string();
+        test_ui.handle_add_project_button_clicked();
+        add_event(&mut test_ui, "Water the plants");
+        test_ui.new_project_name = "Work".to_string();
+        test_ui.handle_add_project_button_clicked();
+        let work = test_ui.projects[1].id;
+        add_event(&mut test_ui, "Write the report");
+        assert_eq!(titles(&test_ui), vec!["Write the report"]);
+        test_ui.handle_project_selected(Some(None));
+        assert_eq!(titles(&test_ui), vec!["Call the bank"]);
+        test_ui.handle_project_selected(None);
+        assert_eq!(titles(&test_ui).len(), 3);
+
+        // A picked color is shown right away and saved once the pointer is released
+        let mut project = test_ui.projects[1].clone();
+        for rgb in [[200, 0, 0], [255, 0, 0]] {
+            project.set_rgb(rgb);
+            test_ui.handle_project_color_picked(&project);
+        }
+        let stored_color = |test_ui: &KrabbyDoUi| {
+            let projects = test_ui
+                .client
+                .block_on(test_ui.client.store().get_projects());
+            projects.unwrap()[1].color.clone()
+        };
+        assert_eq!(test_ui.projects[1].color, "#ff0000");
+        assert_ne!(stored_color(&test_ui), "#ff0000");
+        test_ui.handle_project_color_released();
+        assert_eq!(stored_color(&test_ui), "#ff0000");
+        assert_eq!(test_ui.unsaved_project, None);
+
+        // Projects are reordered, and an archived project's events are hidden from All
+        test_ui.handle_move_project_button_clicked(work, 0);
+        let names: Vec<String> = test_ui.projects.iter().map(|x| x.name.clone()).collect();
+        assert_eq!(names, vec!["Work", "Home"]);
+        let mut project = test_ui.projects[0].clone();
+        project.archived = true;
+        test_ui.handle_project_changed(&project);
+        assert_eq!(titles(&test_ui), vec!["Call the bank", "Water the plants"]);
+
+        // Deleting the project moves its events to the Inbox
+        test_ui.handle_delete_project_button_clicked(work);
+        assert_eq!(test_ui.projects.len(), 1);
+        assert_eq!(test_ui.selected_project, Some(None));
+        assert_eq!(titles(&test_ui), vec!["Call the bank", "Write the report"]);
+    }
+
+    #[test]
+    fn test_sort_events_by_urgency() {
+        let client = KrabbyClient::with_store(Arc::new(MemoryStore::new())).unwrap();
+        let mut test_ui = KrabbyDoUi::with_client(client);
+        test_ui.timezone = Tz::UTC;
+        let today = Utc::now().date_naive();
+        for (title, days, priority) in [
+            ("Water the plants", 0, Priority::Low),
+            ("Fix the outage", 1, Priority::Critical),
+        ] {
+            test_ui.handle_menu_new_clicked();
+            test_ui.new_event_title = title.to_string();
+            test_ui.new_event_date = Some(today + chrono::Duration::days(days));
+            test_ui.new_event_priority = priority;
+            test_ui.handle_new_edit_ok_button_clicked();
+        }
+        let titles = |test_ui: &KrabbyDoUi| -> Vec<String> {
+            test_ui
+                .list_of(EventList::Upcoming)
+                .into_iter()
+                .map(|x| x.title)
+                .collect()
+        };
+
+        test_ui.sort_events_by_date();
+        assert_eq!(titles(&test_ui), vec!["Water the plants", "Fix the outage"]);
+        test_ui.sort_events_by_urgency();
+        assert_eq!(titles(&test_ui), vec!["Fix the outage", "Water the plants"]);
+
+        // The dialog shows the priority of the edited event
+        let entry = test_ui.event_entries[0].clone();
+        test_ui.handle_event_list_item_edit_button_clicked(&entry);
+        assert_eq!(test_ui.new_event_priority, Priority::Critical);
+    }
+
+    #[test]
+    fn test_history_revert() {
+        let client = KrabbyClient::with_store(Arc::new(MemoryStore::new())).unwrap();
+        let mut test_ui = KrabbyDoUi::with_client(client);
+        test_ui.new_edit_title = String::from("New Event");
+        test_ui.new_event_title = "Title1".to_string();
+        test_ui.new_event_date = NaiveDate::from_ymd_opt(2023, 6, 9);
+        test_ui.handle_new_edit_ok_button_clicked();
+        let entry = test_ui.event_entries[0].clone();
+
+        test_ui.handle_event_list_item_clicked(&entry);
+        test_ui.handle_edit_event_button_clicked();
+        test_ui.new_event_title = "Title2".to_string();
+        test_ui.handle_new_edit_ok_button_clicked();
+        assert_eq!(test_ui.event_entries[0].title, "Title2");
+
+        test_ui.central_panel_tab = CentralPanelTab::History;
+        test_ui.load_history();
+        assert_eq!(test_ui.history_entries.len(), 2);
+        let first_revision = test_ui.history_entries[0].clone();
+        test_ui.handle_history_revert_button_clicked(&first_revision);
+        assert_eq!(test_ui.event_entries[0].title, "Title1");
+        assert_eq!(test_ui.history_entries.len(), 3);
+    }
+
+    #[test]
+    fn test_merge_conflict() {
+        let client = KrabbyClient::with_store(Arc::new(MemoryStore::new())).unwrap();
+        let mut test_ui = KrabbyDoUi::with_client(client.clone());
+        test_ui.new_edit_title = String::from("New Event");
+        test_ui.new_event_title = "Title1".to_string();
+        test_ui.new_event_date = NaiveDate::from_ymd_opt(2023, 6, 9);
+        test_ui.handle_new_edit_ok_button_clicked();
+        let entry = test_ui.event_entries[0].clone();
+
+        // Another instance changes the details while the event is edited here
+        let theirs = EventEntry {
+            details: Some("Their details".to_string()),
+            ..entry.clone()
+        };
+        client
+            .block_on(client.store().update_task(&theirs))
+            .unwrap();
+        test_ui.handle_event_list_item_clicked(&entry);
+        test_ui.handle_edit_event_button_clicked();
+        test_ui.new_event_title = "My title".to_string();
+        test_ui.handle_new_edit_ok_button_clicked();
+
+        // Nothing was overwritten; the dialog offers my title and keeps their details
+        let conflict = test_ui.merge_conflict.as_ref().unwrap();
+        assert_eq!(
+            conflict.keep_mine,
+            [true, false, false, false, false, false, false, false, false, false]
+        );
+        assert_eq!(
+            test_ui.event_entries[0].details.as_deref(),
+            Some("Their details")
+        );
+        assert_eq!(test_ui.event_entries[0].title, "Title1");
+
+        test_ui.handle_merge_save_button_clicked();
+        assert!(test_ui.merge_conflict.is_none());
+        let stored = client
+            .block_on(client.store().get_event(&entry.unique_id))
+            .unwrap();
+        assert_eq!(stored.title, "My title");
+        assert_eq!(stored.details.as_deref(), Some("Their details"));
+        assert_eq!(test_ui.event_entries, vec![stored]);
+
+        // Keeping theirs drops the change made here
+        let theirs = EventEntry {
+            is_done: true,
+            ..test_ui.event_entries[0].clone()
+        };
+        client
+            .block_on(client.store().update_task(&theirs))
+            .unwrap();
+        let mine = test_ui.event_entries[0].clone();
+        test_ui.handle_event_list_item_done_button_clicked(&mine);
+        assert!(test_ui.merge_conflict.is_some());
+        test_ui.handle_merge_keep_theirs_button_clicked();
+        assert!(test_ui.merge_conflict.is_none());
+        assert!(test_ui.event_entries[0].is_done);
+    }
+
+    #[test]
+    fn test_bulk_actions() {
+        let client = KrabbyClient::with_store(Arc::new(MemoryStore::new())).unwrap();
+        let mut test_ui = KrabbyDoUi::with_client(client);
+        test_ui.timezone = Tz::UTC;
+        for (title, day) in [("Title1", 9), ("Title2", 10), ("Title3", 11)] {
+            test_ui.new_edit_title = String::from("New Event");
+            test_ui.new_event_title = title.to_string();
+            test_ui.new_event_date = NaiveDate::from_ymd_opt(2023, 6, day);
+            test_ui.handle_new_edit_ok_button_clicked();
+        }
+        test_ui.sort_events_by_date();
+        let entries = test_ui.event_entries.clone();
+
+        // A click, then a Shift-click selects the range; Ctrl-click takes one out again
+        test_ui.handle_event_list_item_selected(&entries[0], Modifiers::NONE);
+        assert!(test_ui.selected_ids.is_empty());
+        test_ui.handle_event_list_item_selected(&entries[2], Modifiers::SHIFT);
+        assert_eq!(test_ui.selected_ids.len(), 3);
+        test_ui.handle_event_list_item_selected(&entries[1], Modifiers::COMMAND);
+        assert_eq!(
+            test_ui.selected_ids,
+            vec![entries[0].unique_id, entries[2].unique_id]
+        );
+
+        test_ui.bulk_tag = "Sprint".to_string();
+        test_ui.handle_bulk_add_tag_button_clicked();
+        test_ui.bulk_shift_days = 2;
+        test_ui.handle_bulk_shift_button_clicked();
+        test_ui.handle_bulk_done_button_clicked(true);
+        let tagged: Vec<&EventEntry> = test_ui
+            .event_entries
+            .iter()
+            .filter(|x| x.tags == vec!["sprint"])
+            .collect();
+        assert_eq!(tagged.len(), 2);
+        assert!(tagged.iter().all(|x| x.is_done));
+        assert_eq!(
+            tagged[0].date_time,
+            entries[0].date_time.map(|x| x + chrono::Duration::days(2))
+        );
+
+        // Each bulk action is undone as a whole
+        test_ui.handle_undo();
+        assert_eq!(
+            test_ui.event_entries.iter().filter(|x| x.is_done).count(),
+            0
+        );
+        test_ui.handle_bulk_delete_button_clicked();
+        assert_eq!(test_ui.event_entries.len(), 1);
+        assert!(test_ui.selected_ids.is_empty());
+        test_ui.handle_undo();
+        assert_eq!(test_ui.event_entries.len(), 3);
+    }
+
+    #[test]
+    fn test_undo_delete() {
+        let client = KrabbyClient::with_store(Arc::new(MemoryStore::new())).unwrap();
+        let mut test_ui = KrabbyDoUi::with_client(client);
+        test_ui.new_edit_title = String::from("New Event");
+        test_ui.new_event_title = "Title1".to_string();
+        test_ui.new_event_date = NaiveDate::from_ymd_opt(2023, 6, 9);
+        test_ui.handle_new_edit_ok_button_clicked();
+        let entry = test_ui.event_entries[0].clone();
+
+        test_ui.handle_event_list_item_done_button_clicked(&entry);
+        assert!(test_ui.event_entries[0].is_done);
+        let done_entry = test_ui.event_entries[0].clone();
+        test_ui.handle_event_list_item_delete_button_clicked(&done_entry);
+        assert!(test_ui.event_entries.is_empty());
+
+        // Undo brings the event back from the trash, then reopens it
+        test_ui.handle_undo();
+        assert_eq!(test_ui.event_entries, vec![done_entry]);
+        test_ui.handle_undo();
+        // Marking it not done again is an update of its own, made on the latest version
+        assert_eq!(
+            test_ui.event_entries,
+            vec![EventEntry {
+                version: 2,
+                ..entry.clone()
+            }]
+        );
+        test_ui.handle_redo();
+        assert!(test_ui.event_entries[0].is_done);
+    }
+
     #[test]
     fn test_export_events_to_json() -> std::io::Result<()> {
         let mut test_ui = KrabbyDoUi::default();
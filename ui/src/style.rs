@@ -15,4 +15,6 @@ pub mod style_constants {
     pub const EVENT_LIST_ITEM_MIN_WIDTH: f32 = 50.0;
     pub const EVENT_LIST_ITEM_MAX_WIDTH: f32 = 200.0;
     pub const EVENT_LIST_INTERNAL_BUTTON_MIN_WIDTH: f32 = 40.0;
+    pub const BULK_TAG_FIELD_WIDTH: f32 = 100.0;
+    pub const PROJECT_NAME_FIELD_WIDTH: f32 = 120.0;
 }
@@ -0,0 +1,372 @@
+//! Undo / redo of the changes made to events from the UI.
+//! Every change is kept as a Command which knows how to apply and revert itself on the store,
+//! so undoing a change also undoes it in the database and not only in the event lists.
+
+use bson::oid::ObjectId;
+use futures_util::future::BoxFuture;
+use middleware::{EventEntry, EventStore, KrabbyClient, MiddlewareError};
+use std::collections::HashMap;
+
+/// Commands are the changes made to events which can be undone
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// A new event was added
+    Create(EventEntry),
+    /// An event was edited; holds its contents before and after the edit
+    Edit {
+        before: EventEntry,
+        after: EventEntry,
+    },
+    /// An event was marked done or not done; holds its contents before and after the change.
+    /// A recurring event marked done stays not done and moves on to its next occurrence.
+    MarkDone {
+        before: EventEntry,
+        after: EventEntry,
+    },
+    /// An event was moved to the trash
+    Delete(EventEntry),
+    /// Several changes made at once by a bulk action, undone and redone together
+    Bulk(Vec<Command>),
+}
+
+impl Command {
+    /// Short description of the command shown next to the Undo / Redo buttons
+    pub fn describe(&self) -> String {
+        match self {
+            Command::Create(entry) => format!("create \"{}\"", entry.title),
+            Command::Edit { after, .. } => format!("edit \"{}\"", after.title),
+            Command::MarkDone { before, after } if !before.is_done => {
+                format!("mark \"{}\" done", after.title)
+            }
+            Command::MarkDone { after, .. } => format!("mark \"{}\" not done", after.title),
+            Command::Delete(entry) => format!("delete \"{}\"", entry.title),
+            Command::Bulk(commands) if commands.iter().all(|x| matches!(x, Command::Delete(_))) => {
+                format!("delete {} events", commands.len())
+            }
+            Command::Bulk(commands) => format!("change {} events", commands.len()),
+        }
+    }
+
+    /// Remembers the version each event has right after the command was applied
+    fn record_versions(&self, versions: &mut Versions) {
+        match self {
+            Command::Create(entry) | Command::Delete(entry) => {
+                versions.insert(entry.unique_id, entry.version);
+            }
+            Command::Edit { after, .. } | Command::MarkDone { after, .. } => {
+                versions.insert(after.unique_id, after.version);
+            }
+            Command::Bulk(commands) => {
+                for command in commands {
+                    command.record_versions(versions);
+                }
+            }
+        }
+    }
+
+    /// Applies the command to the store (again). Boxed, since a Bulk command applies the commands
+    /// it holds.
+    fn apply<'a>(
+        &'a self,
+        store: &'a dyn EventStore,
+        versions: &'a mut Versions,
+    ) -> BoxFuture<'a, Result<(), MiddlewareError>> {
+        Box::pin(async move {
+            match self {
+                Command::Create(entry) => {
+                    let stored = store.add_event(entry).await?;
+                    versions.insert(stored.unique_id, stored.version);
+                }
+                Command::Edit { after, .. } | Command::MarkDone { after, .. } => {
+                    let stored = store.update_task(&at_version(after, versions)).await?;
+                    versions.insert(stored.unique_id, stored.version);
+                }
+                Command::Delete(entry) => store.delete_event(entry).await?,
+                Command::Bulk(commands) => {
+                    for command in commands {
+                        command.apply(store, versions).await?;
+                    }
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// Reverts the effect of the command on the store; a Bulk command reverts the commands it holds
+    /// in reverse order
+    fn revert<'a>(
+        &'a self,
+        store: &'a dyn EventStore,
+        versions: &'a mut Versions,
+    ) -> BoxFuture<'a, Result<(), MiddlewareError>> {
+        Box::pin(async move {
+            match self {
+                // The event didn't exist before, so it is removed for good instead of going to the trash
+                Command::Create(entry) => {
+                    store.delete_event(entry).await?;
+                    store.purge_event(entry).await?
+                }
+                Command::Edit { before, .. } | Command::MarkDone { before, .. } => {
+                    let stored = store.update_task(&at_version(before, versions)).await?;
+                    versions.insert(stored.unique_id, stored.version);
+                }
+                Command::Delete(entry) => {
+                    let stored = store.restore_event(entry).await?;
+                    versions.insert(stored.unique_id, stored.version);
+                }
+                Command::Bulk(commands) => {
+                    for command in commands.iter().rev() {
+                        command.revert(store, versions).await?;
+                    }
+                }
+            }
+            Ok(())
+        })
+    }
+}
+
+/// Latest version known of every event changed by the commands
+type Versions = HashMap<ObjectId, u64>;
+
+/// The entry carrying the latest version known of its event, so the update is made on it;
+/// an event changed elsewhere meanwhile makes the update fail with Conflict
+fn at_version(entry: &EventEntry, versions: &Versions) -> EventEntry {
+    EventEntry {
+        version: versions
+            .get(&entry.unique_id)
+            .copied()
+            .unwrap_or(entry.version),
+        ..entry.clone()
+    }
+}
+
+/// UndoStack holds the commands which can be undone and those which can be redone
+#[derive(Debug, Default)]
+pub struct UndoStack {
+    /// Commands done so far, the most recent last
+    done: Vec<Command>,
+    /// Commands undone since the last new command, the most recently undone last
+    undone: Vec<Command>,
+    /// Latest version of every event changed by the commands, which the next undo or redo
+    /// of that event is made on
+    versions: Versions,
+}
+
+impl UndoStack {
+    /// Records a command which was just applied; nothing can be redone after a new change
+    pub fn push(&mut self, command: Command) {
+        command.record_versions(&mut self.versions);
+        self.done.push(command);
+        self.undone.clear();
+    }
+
+    /// The command Undo would revert
+    pub fn next_undo(&self) -> Option<&Command> {
+        self.done.last()
+    }
+
+    /// The command Redo would apply
+    pub fn next_redo(&self) -> Option<&Command> {
+        self.undone.last()
+    }
+
+    /// Reverts the most recent command on the store; returns false when there is nothing to undo.
+    /// A command which fails to revert is dropped, since the store no longer matches it.
+    pub fn undo(&mut self, client: &KrabbyClient) -> Result<bool, MiddlewareError> {
+        let Some(command) = self.done.pop() else {
+            return Ok(false);
+        };
+        client.block_on(command.revert(client.store(), &mut self.versions))?;
+        self.undone.push(command);
+        Ok(true)
+    }
+
+    /// Applies the most recently undone command again; returns false when there is nothing to redo
+    pub fn redo(&mut self, client: &KrabbyClient) -> Result<bool, MiddlewareError> {
+        let Some(command) = self.undone.pop() else {
+            return Ok(false);
+        };
+        client.block_on(command.apply(client.store(), &mut self.versions))?;
+        self.done.push(command);
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use middleware::{EventPatch, EventSelection, MemoryStore};
+    use std::sync::Arc;
+
+    fn stored_titles(client: &KrabbyClient) -> Vec<String> {
+        client
+            .block_on(client.store().get_all_tasks())
+            .unwrap()
+            .into_iter()
+            .map(|x| x.title)
+            .collect()
+    }
+
+    #[test]
+    fn test_undo_redo() {
+        let client = KrabbyClient::with_store(Arc::new(MemoryStore::new())).unwrap();
+        let mut stack = UndoStack::default();
+
+        let before = EventEntry::new(
+            ObjectId::new(),
+            String::from("Before"),
+            String::from("Details"),
+            Utc::now(),
+            false,
+            String::from("Work"),
+        );
+        client.block_on(client.store().add_event(&before)).unwrap();
+        stack.push(Command::Create(before.clone()));
+        let after = EventEntry {
+            title: String::from("After"),
+            ..before.clone()
+        };
+        let after = client.block_on(client.store().update_task(&after)).unwrap();
+        stack.push(Command::Edit {
+            before: before.clone(),
+            after: after.clone(),
+        });
+        client
+            .block_on(client.store().delete_event(&after))
+            .unwrap();
+        stack.push(Command::Delete(after.clone()));
+        assert!(stored_titles(&client).is_empty());
+
+        assert!(stack.undo(&client).unwrap());
+        assert_eq!(stored_titles(&client), vec!["After"]);
+        assert!(stack.undo(&client).unwrap());
+        assert_eq!(stored_titles(&client), vec!["Before"]);
+        assert!(stack.undo(&client).unwrap());
+        assert!(stored_titles(&client).is_empty());
+        assert!(!stack.undo(&client).unwrap());
+
+        assert!(stack.redo(&client).unwrap());
+        assert!(stack.redo(&client).unwrap());
+        assert_eq!(stored_titles(&client), vec!["After"]);
+
+        // A new change drops what could still be redone
+        stack.push(Command::Delete(after));
+        assert!(stack.next_redo().is_none());
+    }
+
+    #[test]
+    fn test_undo_conflict() {
+        let client = KrabbyClient::with_store(Arc::new(MemoryStore::new())).unwrap();
+        let mut stack = UndoStack::default();
+
+        let before = EventEntry::new(
+            ObjectId::new(),
+            String::from("Before"),
+            String::from("Details"),
+            Utc::now(),
+            false,
+            String::from("Work"),
+        );
+        let before = client.block_on(client.store().add_event(&before)).unwrap();
+        let after = EventEntry {
+            title: String::from("After"),
+            ..before.clone()
+        };
+        let after = client.block_on(client.store().update_task(&after)).unwrap();
+        stack.push(Command::Edit {
+            before: before.clone(),
+            after: after.clone(),
+        });
+        let done = EventEntry {
+            is_done: true,
+            ..after.clone()
+        };
+        let done = client.block_on(client.store().update_task(&done)).unwrap();
+        stack.push(Command::MarkDone {
+            before: after.clone(),
+            after: done.clone(),
+        });
+
+        // Undoing two changes of the same event in a row follows its versions
+        assert!(stack.undo(&client).unwrap());
+        assert!(stack.undo(&client).unwrap());
+        assert_eq!(stored_titles(&client), vec!["Before"]);
+        assert!(stack.redo(&client).unwrap());
+
+        // Someone else changed the event since, so the redo doesn't overwrite their change
+        let current = client
+            .block_on(client.store().get_event(&before.unique_id))
+            .unwrap();
+        let theirs = EventEntry {
+            details: Some(String::from("Theirs")),
+            ..current
+        };
+        client
+            .block_on(client.store().update_task(&theirs))
+            .unwrap();
+        let result = stack.redo(&client);
+        assert!(matches!(result, Err(MiddlewareError::Conflict { .. })));
+    }
+
+    #[test]
+    fn test_undo_bulk() {
+        let client = KrabbyClient::with_store(Arc::new(MemoryStore::new())).unwrap();
+        let mut stack = UndoStack::default();
+        let first = EventEntry::new(
+            ObjectId::new(),
+            String::from("First"),
+            String::from("Details"),
+            Utc::now(),
+            false,
+            String::from("Work"),
+        );
+        let second = EventEntry {
+            unique_id: ObjectId::new(),
+            title: String::from("Second"),
+            ..first.clone()
+        };
+        for entry in [&first, &second] {
+            client.block_on(client.store().add_event(entry)).unwrap();
+        }
+
+        // Both events are marked done at once, then the first one is deleted on its own
+        let selection = EventSelection::Ids(vec![first.unique_id, second.unique_id]);
+        let changes = client
+            .block_on(
+                client
+                    .store()
+                    .update_many(&selection, &EventPatch::new().done(true)),
+            )
+            .unwrap();
+        stack.push(Command::Bulk(
+            changes
+                .into_iter()
+                .map(|(before, after)| Command::MarkDone { before, after })
+                .collect(),
+        ));
+        assert_eq!(stack.next_undo().unwrap().describe(), "change 2 events");
+        let done_first = client
+            .block_on(client.store().get_event(&first.unique_id))
+            .unwrap();
+        client
+            .block_on(client.store().delete_event(&done_first))
+            .unwrap();
+        stack.push(Command::Delete(done_first));
+
+        let is_done = |client: &KrabbyClient| -> Vec<bool> {
+            client
+                .block_on(client.store().get_all_tasks())
+                .unwrap()
+                .into_iter()
+                .map(|x| x.is_done)
+                .collect()
+        };
+        assert!(stack.undo(&client).unwrap());
+        assert_eq!(is_done(&client), vec![true, true]);
+        assert!(stack.undo(&client).unwrap());
+        assert_eq!(is_done(&client), vec![false, false]);
+        assert!(stack.redo(&client).unwrap());
+        assert_eq!(is_done(&client), vec![true, true]);
+    }
+}